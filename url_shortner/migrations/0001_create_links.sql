@@ -0,0 +1,6 @@
+-- Initial schema: maps short codes to long URLs.
+CREATE TABLE IF NOT EXISTS links (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    short_code TEXT NOT NULL UNIQUE,
+    long_url TEXT NOT NULL
+);
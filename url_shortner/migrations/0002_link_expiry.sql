@@ -0,0 +1,4 @@
+-- Link lifecycle: optional expiry by time or by click count.
+ALTER TABLE links ADD COLUMN expires_at INTEGER;
+ALTER TABLE links ADD COLUMN max_clicks INTEGER;
+ALTER TABLE links ADD COLUMN click_count INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,12 @@
+-- Click analytics. `short_code` is not a foreign key so stats survive a purged link.
+CREATE TABLE IF NOT EXISTS clicks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    short_code TEXT NOT NULL,
+    clicked_at INTEGER NOT NULL,
+    referrer TEXT,
+    user_agent TEXT,
+    ip_hash TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_clicks_short_code_clicked_at
+    ON clicks (short_code, clicked_at);
@@ -0,0 +1,5 @@
+-- Opt-in deduplication: links created with dedup share a unique key derived
+-- from the normalized long URL, so the same URL maps to a single short code.
+ALTER TABLE links ADD COLUMN dedup_key TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_links_dedup_key ON links (dedup_key);
@@ -0,0 +1,14 @@
+-- API keys for authenticated routes. Only a SHA-256 hash of each key is stored.
+CREATE TABLE IF NOT EXISTS api_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL,
+    revoked_at INTEGER
+);
+
+-- Links created through the API belong to the key that created them.
+-- Links created before authentication existed have no owner.
+ALTER TABLE links ADD COLUMN owner_id INTEGER REFERENCES api_keys (id);
+
+CREATE INDEX IF NOT EXISTS idx_links_owner_id ON links (owner_id);
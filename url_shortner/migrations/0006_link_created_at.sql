@@ -0,0 +1,3 @@
+-- Creation time of each link, used for listing and previews.
+-- Links created before this migration keep a NULL creation time.
+ALTER TABLE links ADD COLUMN created_at INTEGER;
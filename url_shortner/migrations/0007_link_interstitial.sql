@@ -0,0 +1,2 @@
+-- Links flagged as interstitial show a preview page before redirecting.
+ALTER TABLE links ADD COLUMN interstitial INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+-- Ordered redirect rules of a link; the first rule whose conditions all
+-- match the visitor replaces long_url as the redirect target.
+CREATE TABLE IF NOT EXISTS link_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    link_id INTEGER NOT NULL REFERENCES links (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    platform TEXT,
+    language TEXT,
+    country TEXT,
+    target_url TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_link_rules_link_id ON link_rules (link_id, position);
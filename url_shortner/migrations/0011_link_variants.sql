@@ -0,0 +1,14 @@
+-- Weighted A/B targets of a link; visitors are split across them by weight
+-- and stay on their variant through a cookie.
+CREATE TABLE IF NOT EXISTS link_variants (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    link_id INTEGER NOT NULL REFERENCES links (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    target_url TEXT NOT NULL,
+    weight INTEGER NOT NULL,
+    UNIQUE (link_id, name)
+);
+
+-- Variant a click was sent to; NULL for links without variants.
+ALTER TABLE clicks ADD COLUMN variant TEXT;
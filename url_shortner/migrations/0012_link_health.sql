@@ -0,0 +1,11 @@
+-- Result of the latest probe of a link's destination: 'unchecked', 'ok',
+-- 'dead' or 'redirect_loop'.
+ALTER TABLE links ADD COLUMN health TEXT NOT NULL DEFAULT 'unchecked';
+
+-- Unix timestamp (seconds) of the latest probe; NULL until the first one.
+ALTER TABLE links ADD COLUMN health_checked_at INTEGER;
+
+-- Why the latest probe failed, e.g. 'HTTP 404'.
+ALTER TABLE links ADD COLUMN health_detail TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_links_health_checked_at ON links (health_checked_at);
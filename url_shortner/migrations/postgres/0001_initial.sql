@@ -0,0 +1,37 @@
+-- Initial Postgres schema, equivalent to SQLite schema version 6.
+-- Timestamps are Unix seconds (BIGINT), like in the SQLite schema.
+CREATE TABLE IF NOT EXISTS api_keys (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    created_at BIGINT NOT NULL,
+    revoked_at BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS links (
+    id BIGSERIAL PRIMARY KEY,
+    short_code TEXT NOT NULL UNIQUE,
+    long_url TEXT NOT NULL,
+    expires_at BIGINT,
+    max_clicks BIGINT,
+    click_count BIGINT NOT NULL DEFAULT 0,
+    dedup_key TEXT,
+    owner_id BIGINT REFERENCES api_keys (id),
+    created_at BIGINT
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_links_dedup_key ON links (dedup_key);
+CREATE INDEX IF NOT EXISTS idx_links_owner_id ON links (owner_id);
+
+-- `short_code` is not a foreign key so stats survive a purged link.
+CREATE TABLE IF NOT EXISTS clicks (
+    id BIGSERIAL PRIMARY KEY,
+    short_code TEXT NOT NULL,
+    clicked_at BIGINT NOT NULL,
+    referrer TEXT,
+    user_agent TEXT,
+    ip_hash TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_clicks_short_code_clicked_at
+    ON clicks (short_code, clicked_at);
@@ -0,0 +1,2 @@
+-- Links flagged as interstitial show a preview page before redirecting.
+ALTER TABLE links ADD COLUMN interstitial BOOLEAN NOT NULL DEFAULT FALSE;
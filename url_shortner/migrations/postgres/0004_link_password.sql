@@ -0,0 +1,2 @@
+-- Argon2 PHC string of the link's password; NULL for links without one.
+ALTER TABLE links ADD COLUMN password_hash TEXT;
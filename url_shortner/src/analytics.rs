@@ -0,0 +1,235 @@
+//! Click analytics.
+//!
+//! Every redirect produces a [`ClickEvent`] that is queued on an in-memory
+//! channel and written to the `clicks` table by a background writer in
+//! batches. The redirect handler never waits for the insert.
+//!
+//! # Flow
+//!
+//! ```text
+//! redirect_handler ──record()──▶ mpsc channel ──▶ writer task ──▶ clicks table
+//! ```
+//!
+//! The writer flushes whenever [`BATCH_SIZE`] events are buffered or every
+//! [`FLUSH_INTERVAL`], whichever comes first. At shutdown,
+//! [`ClickWriter::shutdown`] closes the queue, and the writer flushes what
+//! is left and exits. It also exits when every [`ClickRecorder`] has been
+//! dropped.
+//!
+//! # Privacy
+//!
+//! Client IP addresses are never stored. They are hashed with SHA-256 together
+//! with a salt (see [`hash_ip`]) so that unique visitors can be counted.
+
+use crate::{models::ClickEvent, store::LinkStore};
+use sha2::{Digest, Sha256};
+use std::{net::IpAddr, sync::Arc, time::Duration};
+use tokio::{
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot,
+    },
+    task::JoinHandle,
+};
+
+/// Maximum number of events written in one transaction.
+pub const BATCH_SIZE: usize = 100;
+
+/// Maximum time an event waits in the buffer before being written.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of events that can be queued before new ones are dropped.
+pub const CHANNEL_CAPACITY: usize = 10_000;
+
+/// Cheap, cloneable handle for queueing click events.
+#[derive(Clone)]
+pub struct ClickRecorder {
+    sender: mpsc::Sender<ClickEvent>,
+    ip_hash_salt: Arc<str>,
+}
+
+impl ClickRecorder {
+    /// Hashes a client IP with this recorder's salt (see [`hash_ip`]).
+    pub fn hash_ip(&self, ip: IpAddr) -> String {
+        hash_ip(ip, &self.ip_hash_salt)
+    }
+
+    /// Queues a click event without waiting.
+    ///
+    /// If the queue is full the event is dropped and a warning is logged,
+    /// so a slow database can never stall redirects.
+    pub fn record(&self, event: ClickEvent) {
+        match self.sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(event)) => {
+                tracing::warn!(
+                    short_code = %event.short_code,
+                    "Click queue full, dropping click"
+                )
+            }
+            Err(TrySendError::Closed(_)) => tracing::error!("Click writer has stopped"),
+        }
+    }
+}
+
+/// Handle of the running click writer, used to stop it at shutdown.
+pub struct ClickWriter {
+    stop: oneshot::Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl ClickWriter {
+    /// Stops the writer after writing every queued event.
+    ///
+    /// The queue is closed first, so events recorded from now on are
+    /// dropped and logged. Returns once the remaining events are written.
+    pub async fn shutdown(self) {
+        let _ = self.stop.send(());
+        if let Err(err) = self.handle.await {
+            tracing::error!(error = %err, "Click writer failed");
+        }
+    }
+}
+
+/// Spawns the batched click writer.
+///
+/// # Arguments
+///
+/// * `store` - The storage backend the clicks are written to
+/// * `ip_hash_salt` - Salt used to hash client IPs
+///
+/// # Returns
+///
+/// Returns the [`ClickRecorder`] used by handlers and the [`ClickWriter`]
+/// that stops the writer task.
+pub fn spawn_click_writer(
+    store: Arc<dyn LinkStore>,
+    ip_hash_salt: &str,
+) -> (ClickRecorder, ClickWriter) {
+    let (sender, mut receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    let (stop, mut stop_requested) = oneshot::channel();
+
+    let handle = tokio::spawn(async move {
+        let mut buffer = Vec::with_capacity(BATCH_SIZE);
+        let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+        let mut stopping = false;
+
+        loop {
+            tokio::select! {
+                // Closing the queue makes `recv_many` return the queued
+                // events and then 0, which ends the loop below.
+                requested = &mut stop_requested, if !stopping => {
+                    stopping = true;
+                    if requested.is_ok() {
+                        receiver.close();
+                    }
+                }
+                received = receiver.recv_many(&mut buffer, BATCH_SIZE) => {
+                    if received == 0 {
+                        flush(store.as_ref(), &mut buffer).await;
+                        break;
+                    }
+                    if buffer.len() >= BATCH_SIZE {
+                        flush(store.as_ref(), &mut buffer).await;
+                    }
+                }
+                _ = ticker.tick() => flush(store.as_ref(), &mut buffer).await,
+            }
+        }
+    });
+
+    let recorder = ClickRecorder {
+        sender,
+        ip_hash_salt: Arc::from(ip_hash_salt),
+    };
+    (recorder, ClickWriter { stop, handle })
+}
+
+/// Writes the buffered events and clears the buffer.
+async fn flush(store: &dyn LinkStore, buffer: &mut Vec<ClickEvent>) {
+    if buffer.is_empty() {
+        return;
+    }
+    if let Err(err) = store.insert_clicks(buffer).await {
+        tracing::error!(error = %err, "Failed to write {} click events", buffer.len());
+    }
+    buffer.clear();
+}
+
+/// Hashes a client IP address with the given salt.
+///
+/// # Arguments
+///
+/// * `ip` - The client's IP address
+/// * `salt` - Secret salt (from `IP_HASH_SALT`) that prevents reversing the hash
+///
+/// # Returns
+///
+/// Returns the hex-encoded SHA-256 digest of `salt` followed by `ip`.
+pub fn hash_ip(ip: IpAddr, salt: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(ip.to_string().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::NewLink, store::MemoryStore};
+
+    #[test]
+    fn ip_hash_is_salted_and_stable() {
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        assert_eq!(hash_ip(ip, "salt"), hash_ip(ip, "salt"));
+        assert_ne!(hash_ip(ip, "salt"), hash_ip(ip, "pepper"));
+        assert_eq!(hash_ip(ip, "salt").len(), 64);
+    }
+
+    #[tokio::test]
+    async fn writer_flushes_on_shutdown() {
+        let store = Arc::new(MemoryStore::new());
+        store
+            .create_short_link(&NewLink {
+                short_code: "abc".to_string(),
+                long_url: "https://example.com".to_string(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let (recorder, writer) = spawn_click_writer(store.clone(), "salt");
+        for _ in 0..3 {
+            recorder.record(ClickEvent {
+                short_code: "abc".to_string(),
+                clicked_at: chrono::Utc::now().timestamp(),
+                referrer: Some("https://news.example".to_string()),
+                user_agent: None,
+                ip_hash: Some(recorder.hash_ip("203.0.113.7".parse().unwrap())),
+                counted: false,
+                variant: None,
+            });
+        }
+        // The recorder is still alive, as it is in handlers that are still
+        // running at shutdown.
+        writer.shutdown().await;
+
+        let stats = store.get_link_stats("abc").await.unwrap();
+        assert_eq!(stats.total_clicks, 3);
+        assert_eq!(stats.unique_visitors, 1);
+        assert_eq!(stats.top_referrers[0].clicks, 3);
+        assert_eq!(store.get_link("abc").await.unwrap().click_count, 3);
+
+        recorder.record(ClickEvent {
+            short_code: "abc".to_string(),
+            clicked_at: chrono::Utc::now().timestamp(),
+            referrer: None,
+            user_agent: None,
+            ip_hash: None,
+            counted: false,
+            variant: None,
+        });
+        let stats = store.get_link_stats("abc").await.unwrap();
+        assert_eq!(stats.total_clicks, 3);
+    }
+}
@@ -0,0 +1,288 @@
+//! Blocklist of destinations that must not be shortened.
+//!
+//! Keeps the shortener from relaying visitors to known phishing and malware
+//! sites. The list is a text file (`BLOCKLIST_FILE`, see [`crate::config`])
+//! with one entry per line:
+//!
+//! ```text
+//! # Comments and blank lines are ignored.
+//! evil.example
+//! *.phish.example
+//! https://files.example/malware/
+//! ```
+//!
+//! - A domain (optionally written as `*.domain`) blocks URLs on that host
+//!   and on all of its subdomains
+//! - An entry starting with `http://` or `https://` blocks every URL that
+//!   starts with it, after both are normalized
+//!
+//! New links, URL updates, redirect rule targets and A/B variant targets are
+//! checked against the list. The file is re-read when its modification time
+//! or size changes (see [`Blocklist::reload_if_changed`]), so entries can be
+//! added without restarting the server; links that already exist are not
+//! touched.
+
+use crate::error::AppError;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{RwLock, RwLockReadGuard},
+    time::SystemTime,
+};
+use url::{Host, Url};
+
+/// Default interval between two checks of the blocklist file, in seconds.
+pub const DEFAULT_RELOAD_INTERVAL_SECS: u64 = 30;
+
+/// The parsed entries of a blocklist file.
+#[derive(Debug, Default)]
+pub struct Entries {
+    /// Lowercase hosts; their subdomains are blocked too.
+    domains: HashSet<String>,
+    /// Normalized URL prefixes.
+    url_prefixes: Vec<String>,
+}
+
+impl Entries {
+    /// Parses the contents of a blocklist file.
+    ///
+    /// # Errors
+    ///
+    /// Returns the line number and the offending entry if a line is neither
+    /// a host nor an absolute http or https URL.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut entries = Entries::default();
+        for (index, line) in source.lines().enumerate() {
+            let entry = line.trim();
+            if entry.is_empty() || entry.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || {
+                format!(
+                    "line {}: '{entry}' is neither a domain nor an http(s) URL",
+                    index + 1
+                )
+            };
+            let lowercase = entry.to_ascii_lowercase();
+            if lowercase.starts_with("http://") || lowercase.starts_with("https://") {
+                let url = Url::parse(entry).map_err(|_| invalid())?;
+                entries.url_prefixes.push(url.into());
+            } else {
+                let domain = entry.strip_prefix("*.").unwrap_or(entry);
+                let host = Host::parse(domain.trim_end_matches('.')).map_err(|_| invalid())?;
+                entries.domains.insert(host.to_string());
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.domains.len() + self.url_prefixes.len()
+    }
+
+    /// Returns `true` if `url` (a normalized URL) matches an entry.
+    pub fn blocks(&self, url: &str) -> bool {
+        if self
+            .url_prefixes
+            .iter()
+            .any(|prefix| url.starts_with(prefix))
+        {
+            return true;
+        }
+
+        let Ok(url) = Url::parse(url) else {
+            return false;
+        };
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        // Walk up the domain: a.b.example, b.example, example.
+        let mut domain = host.trim_end_matches('.');
+        loop {
+            if self.domains.contains(domain) {
+                return true;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// The blocklist, shared by all handlers and reloaded in the background.
+pub struct Blocklist {
+    /// The file the entries come from (`None` when no file is configured).
+    path: Option<PathBuf>,
+    loaded: RwLock<Loaded>,
+}
+
+struct Loaded {
+    entries: Entries,
+    /// Modification time and size of the file when it was read.
+    version: Option<(SystemTime, u64)>,
+}
+
+impl Blocklist {
+    /// Returns a blocklist without entries.
+    pub fn disabled() -> Self {
+        Blocklist {
+            path: None,
+            loaded: RwLock::new(Loaded {
+                entries: Entries::default(),
+                version: None,
+            }),
+        }
+    }
+
+    /// Reads a blocklist file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::Internal`] if the file cannot be read or has an
+    /// invalid entry.
+    pub fn open(path: &Path) -> Result<Self, AppError> {
+        let blocklist = Blocklist {
+            path: Some(path.to_path_buf()),
+            ..Blocklist::disabled()
+        };
+        blocklist.reload_if_changed()?;
+        Ok(blocklist)
+    }
+
+    /// Number of entries currently loaded.
+    pub fn len(&self) -> usize {
+        self.read_loaded().entries.len()
+    }
+
+    /// Returns `true` if `url` (a normalized URL) is blocked.
+    pub fn blocks(&self, url: &str) -> bool {
+        self.read_loaded().entries.blocks(url)
+    }
+
+    /// Rejects a blocked destination.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::BadRequest`] if `url` is blocked.
+    pub fn check(&self, url: &str) -> Result<(), AppError> {
+        if self.blocks(url) {
+            return Err(AppError::BadRequest(format!("url '{url}' is blocked")));
+        }
+        Ok(())
+    }
+
+    /// Re-reads the file if its modification time or size changed since it
+    /// was last read.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if new entries were loaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::Internal`] if the file cannot be read or has an
+    /// invalid entry; the previous entries stay in effect.
+    pub fn reload_if_changed(&self) -> Result<bool, AppError> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+        let read_error = |err: &dyn std::fmt::Display| {
+            AppError::Internal(format!("cannot read blocklist {}: {err}", path.display()))
+        };
+
+        let metadata = fs::metadata(path).map_err(|err| read_error(&err))?;
+        let version = Some((
+            metadata.modified().map_err(|err| read_error(&err))?,
+            metadata.len(),
+        ));
+        if self.read_loaded().version == version {
+            return Ok(false);
+        }
+
+        let source = fs::read_to_string(path).map_err(|err| read_error(&err))?;
+        let entries = Entries::parse(&source).map_err(|err| read_error(&err))?;
+        let mut loaded = self.loaded.write().unwrap_or_else(|err| err.into_inner());
+        *loaded = Loaded { entries, version };
+        Ok(true)
+    }
+
+    fn read_loaded(&self) -> RwLockReadGuard<'_, Loaded> {
+        self.loaded.read().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn matches_domains_and_url_prefixes() {
+        let entries = Entries::parse(
+            "# phishing\n\
+             evil.example\n\
+             *.Phish.Example.\n\
+             \n\
+             https://files.example/malware/\n",
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 3);
+
+        for url in [
+            "https://evil.example/login",
+            "http://www.evil.example/",
+            "https://a.b.phish.example/",
+            "https://phish.example./",
+            "https://files.example/malware/dropper.exe",
+        ] {
+            assert!(entries.blocks(url), "{url} was not blocked");
+        }
+        for url in [
+            "https://notevil.example/",
+            "https://example/",
+            "https://files.example/docs/",
+            "https://www.rust-lang.org/?next=evil.example",
+        ] {
+            assert!(!entries.blocks(url), "{url} was blocked");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        let err = Entries::parse("evil.example\nnot a domain\n").unwrap_err();
+        assert!(err.starts_with("line 2:"), "{err}");
+        assert!(Entries::parse("https://\n").is_err());
+    }
+
+    #[test]
+    fn reloads_when_the_file_changes() {
+        let path = std::env::temp_dir().join(format!("blocklist-{}.txt", nanoid::nanoid!()));
+        fs::write(&path, "evil.example\n").unwrap();
+
+        let blocklist = Blocklist::open(&path).unwrap();
+        assert!(blocklist.check("https://evil.example/").is_err());
+        assert!(blocklist.check("https://bad.example/").is_ok());
+        assert!(!blocklist.reload_if_changed().unwrap());
+
+        fs::write(&path, "evil.example\nbad.example\n").unwrap();
+        assert!(blocklist.reload_if_changed().unwrap());
+        assert!(blocklist.check("https://bad.example/").is_err());
+
+        // A broken file keeps the previous entries.
+        fs::write(&path, "evil.example\nbad example\n").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(blocklist.reload_if_changed().is_err());
+        assert_eq!(blocklist.len(), 2);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+//! In-process cache for redirect lookups.
+//!
+//! Hot short codes are redirected thousands of times a minute. [`LinkCache`]
+//! keeps recently used [`Link`] rows in memory so `redirect_handler` only
+//! queries SQLite on a miss.
+//!
+//! # Eviction
+//!
+//! - **Size**: at most `capacity` links are kept; the least recently used
+//!   link is evicted first
+//! - **Time**: an entry is only served for `ttl` after it was loaded, so
+//!   changes made outside this process (e.g. by the admin CLI) show up
+//!   after at most one TTL
+//!
+//! Handlers that update or delete a link call [`LinkCache::invalidate`], so
+//! changes made through the API take effect immediately.
+//!
+//! # Correctness
+//!
+//! A cached link is still checked for expiry on every redirect. Click limits
+//! stay exact because [`LinkStore::record_click`](crate::store::LinkStore::record_click)
+//! enforces them in the database, whatever the cached `click_count` says; a
+//! link it reports as used up is invalidated, and so is the code of every
+//! link created with an alias, which may have belonged to a purged link.
+
+use crate::models::{CacheStats, Link};
+use lru::LruCache;
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Default maximum number of cached links.
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Default time a cached link is served before it is reloaded, in seconds.
+pub const DEFAULT_TTL_SECS: u64 = 60;
+
+/// A cached link and the time it was loaded.
+struct Entry {
+    link: Link,
+    loaded_at: Instant,
+}
+
+/// Bounded, TTL-aware LRU cache of links keyed by short code.
+///
+/// Cheap to clone; all clones share the same entries and counters.
+#[derive(Clone)]
+pub struct LinkCache {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    /// `None` when the cache is disabled (capacity 0).
+    entries: Option<Mutex<LruCache<String, Entry>>>,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl LinkCache {
+    /// Creates an empty cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of links to keep; `0` disables caching
+    /// * `ttl` - How long a loaded link may be served from the cache
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        let entries =
+            NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity)));
+
+        LinkCache {
+            inner: Arc::new(Inner {
+                entries,
+                ttl,
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Returns the cached link for `short_code`, counting a hit or a miss.
+    ///
+    /// Entries older than the TTL are removed and reported as a miss.
+    pub fn get(&self, short_code: &str) -> Option<Link> {
+        let link = self.inner.entries.as_ref().and_then(|entries| {
+            let mut entries = entries.lock().unwrap_or_else(|err| err.into_inner());
+            match entries.get(short_code) {
+                Some(entry) if entry.loaded_at.elapsed() < self.inner.ttl => {
+                    Some(entry.link.clone())
+                }
+                Some(_) => {
+                    entries.pop(short_code);
+                    None
+                }
+                None => None,
+            }
+        });
+
+        let counter = if link.is_some() {
+            &self.inner.hits
+        } else {
+            &self.inner.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        link
+    }
+
+    /// Stores a freshly loaded link, evicting the least recently used one if
+    /// the cache is full.
+    pub fn insert(&self, link: Link) {
+        if let Some(entries) = &self.inner.entries {
+            let mut entries = entries.lock().unwrap_or_else(|err| err.into_inner());
+            entries.put(
+                link.short_code.clone(),
+                Entry {
+                    link,
+                    loaded_at: Instant::now(),
+                },
+            );
+        }
+    }
+
+    /// Removes a link, so the next redirect reloads it from the database.
+    ///
+    /// Must be called after a link is updated or deleted.
+    pub fn invalidate(&self, short_code: &str) {
+        if let Some(entries) = &self.inner.entries {
+            let mut entries = entries.lock().unwrap_or_else(|err| err.into_inner());
+            entries.pop(short_code);
+        }
+    }
+
+    /// Returns the hit and miss counters and the current size.
+    pub fn stats(&self) -> CacheStats {
+        let (entries, capacity) = self.inner.entries.as_ref().map_or((0, 0), |entries| {
+            let entries = entries.lock().unwrap_or_else(|err| err.into_inner());
+            (entries.len(), entries.cap().get())
+        });
+
+        CacheStats {
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            entries,
+            capacity,
+            ttl_secs: self.inner.ttl.as_secs(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{LinkHealth, RedirectType};
+
+    fn link(short_code: &str) -> Link {
+        Link {
+            id: 1,
+            short_code: short_code.to_string(),
+            long_url: "https://www.rust-lang.org/".to_string(),
+            expires_at: None,
+            max_clicks: None,
+            click_count: 0,
+            owner_id: None,
+            created_at: None,
+            interstitial: false,
+            redirect_type: RedirectType::Found,
+            password_hash: None,
+            has_rules: false,
+            has_variants: false,
+            health: LinkHealth::Unchecked,
+            health_checked_at: None,
+            health_detail: None,
+        }
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let cache = LinkCache::new(10, Duration::from_secs(60));
+        assert!(cache.get("abc").is_none());
+        cache.insert(link("abc"));
+        assert!(cache.get("abc").is_some());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = LinkCache::new(2, Duration::from_secs(60));
+        cache.insert(link("a"));
+        cache.insert(link("b"));
+        cache.get("a");
+        cache.insert(link("c"));
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn expired_and_invalidated_entries_are_misses() {
+        let cache = LinkCache::new(10, Duration::ZERO);
+        cache.insert(link("old"));
+        assert!(cache.get("old").is_none());
+        assert_eq!(cache.stats().entries, 0);
+
+        let cache = LinkCache::new(10, Duration::from_secs(60));
+        cache.insert(link("gone"));
+        cache.invalidate("gone");
+        assert!(cache.get("gone").is_none());
+    }
+
+    #[test]
+    fn zero_capacity_disables_caching() {
+        let cache = LinkCache::new(0, Duration::from_secs(60));
+        cache.insert(link("abc"));
+        assert!(cache.get("abc").is_none());
+        assert_eq!(cache.stats().capacity, 0);
+    }
+}
@@ -0,0 +1,927 @@
+//! Command line interface.
+//!
+//! Parsed with [clap](https://docs.rs/clap) in `main`. Without a subcommand,
+//! or with `serve`, the binary runs the HTTP server. The other subcommands
+//! are admin tools that work directly on the database configured by
+//! `DATABASE_URL` (through the same [`LinkStore`] as the server), so links
+//! can be inspected and fixed without an API key or a running server.
+//!
+//! Results are printed as a table, or as JSON with `--output json`. Log
+//! lines go to stderr, so the output can be piped into other tools.
+//!
+//! A running server keeps serving links from its cache for up to
+//! `LINK_CACHE_TTL_SECS` after they are changed or deleted here.
+//!
+//! # Examples
+//!
+//! ```bash
+//! # Run the server
+//! cargo run
+//! cargo run -- serve
+//!
+//! # Apply pending migrations and exit
+//! cargo run -- --migrate-only
+//!
+//! # Override settings from the config file and the environment
+//! cargo run -- --config prod.toml --bind 0.0.0.0:8080 --base-url https://sho.rt
+//!
+//! # Manage API keys
+//! cargo run -- keys create --name marketing
+//! cargo run -- keys list
+//! cargo run -- keys revoke 3
+//!
+//! # Manage links
+//! cargo run -- create https://www.rust-lang.org/ --alias rust --owner 3
+//! cargo run -- get rust
+//! cargo run -- list --search rust --health dead
+//! cargo run -- stats rust --output json
+//! cargo run -- delete rust
+//!
+//! # Move links between databases and clean up
+//! cargo run -- export --format jsonl > links.jsonl
+//! cargo run -- import links.jsonl --owner 3
+//! cargo run -- vacuum
+//!
+//! # Find the row id behind a counter or obfuscated code
+//! cargo run -- decode Xq3vT0b
+//! ```
+
+use crate::{
+    auth,
+    blocklist::Blocklist,
+    codes::{self, CodeGenerator, CodeStrategy},
+    config::Config,
+    error::AppError,
+    handlers,
+    models::{
+        ApiKey, BulkResponse, CreateLinkRequest, ExportFormat, Link, LinkHealth, LinkResponse,
+        LinkStats, RedirectType,
+    },
+    state,
+    store::LinkStore,
+};
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::{
+    io::{self, Read, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+use url::Url;
+
+/// Number of links `list` prints when no `--limit` is given.
+const DEFAULT_LIST_LIMIT: i64 = 100;
+
+/// Command line arguments of the `url_shortner` binary.
+#[derive(Debug, Parser)]
+#[command(version, about = "URL shortening service built with Axum and SQLite")]
+pub struct Cli {
+    /// Apply pending database migrations and exit without starting the server.
+    #[arg(long)]
+    pub migrate_only: bool,
+
+    /// Output format of the admin subcommands.
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    pub output: OutputFormat,
+
+    /// Settings that override the config file and the environment.
+    #[command(flatten)]
+    pub config: ConfigArgs,
+
+    /// Subcommand to run; the server runs when none is given.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Command line overrides of the server configuration.
+///
+/// See [`crate::config`] for the matching config file keys and environment
+/// variables. The flags are global, so they can also follow a subcommand.
+#[derive(Debug, Default, Args)]
+pub struct ConfigArgs {
+    /// Path to a TOML config file [default: url_shortner.toml, if present].
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Address the HTTP server listens on, e.g. `0.0.0.0:8080`.
+    #[arg(long, global = true, value_name = "ADDR")]
+    pub bind: Option<SocketAddr>,
+
+    /// Public URL of the service, used to build short URLs.
+    #[arg(long, global = true, value_name = "URL")]
+    pub base_url: Option<String>,
+
+    /// How short codes are generated: random, counter or obfuscated.
+    #[arg(long, global = true, value_name = "STRATEGY")]
+    pub code_strategy: Option<CodeStrategy>,
+
+    /// Number of characters in a generated short code.
+    #[arg(long, global = true, value_name = "N")]
+    pub code_length: Option<usize>,
+
+    /// Characters generated short codes are drawn from.
+    #[arg(long, global = true, value_name = "CHARS")]
+    pub code_alphabet: Option<String>,
+
+    /// Database connection string, e.g. `sqlite://urls.db`.
+    #[arg(long, global = true, value_name = "URL")]
+    pub database_url: Option<String>,
+
+    /// Maximum number of pooled database connections.
+    #[arg(long, global = true, value_name = "N")]
+    pub database_max_connections: Option<u32>,
+}
+
+/// How admin subcommands print their results.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns for people (default).
+    #[default]
+    Table,
+    /// Pretty-printed JSON, in the shapes of the HTTP API.
+    Json,
+}
+
+/// Subcommands of the binary.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the HTTP server (the default).
+    Serve,
+    /// Create a short link.
+    Create(CreateArgs),
+    /// Show a link.
+    Get {
+        /// Short code of the link.
+        short_code: String,
+    },
+    /// List links, oldest first.
+    List(ListArgs),
+    /// Delete a link and its clicks.
+    Delete {
+        /// Short code of the link.
+        short_code: String,
+    },
+    /// Create links from a CSV, JSON or JSON Lines file, e.g. an export.
+    Import(ImportArgs),
+    /// Write links to stdout as CSV or JSON Lines.
+    Export(ExportArgs),
+    /// Show the click statistics of a link.
+    Stats {
+        /// Short code of the link.
+        short_code: String,
+    },
+    /// Print the row id a counter or obfuscated short code was generated from.
+    Decode {
+        /// The short code.
+        short_code: String,
+    },
+    /// Delete expired links and compact the database.
+    Vacuum,
+    /// Manage API keys.
+    Keys {
+        #[command(subcommand)]
+        action: KeyCommand,
+    },
+}
+
+/// Arguments of `create`.
+#[derive(Debug, Args)]
+pub struct CreateArgs {
+    /// The long URL to shorten.
+    pub url: String,
+
+    /// Custom short code instead of a generated one.
+    #[arg(long)]
+    pub alias: Option<String>,
+
+    /// Time after which the link is gone, in RFC 3339 (e.g. `2026-12-31T23:59:59Z`).
+    #[arg(long, value_name = "TIME")]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Number of redirects after which the link is gone.
+    #[arg(long, value_name = "N")]
+    pub max_clicks: Option<i64>,
+
+    /// Status of the redirect: 301, 302, 307 or 308 [default: 302].
+    #[arg(long, value_name = "STATUS", value_parser = parse_redirect_type)]
+    pub redirect_type: Option<RedirectType>,
+
+    /// Show the preview page before every redirect.
+    #[arg(long)]
+    pub interstitial: bool,
+
+    /// Id of the API key that owns the link, as shown by `keys list`.
+    #[arg(long, value_name = "KEY_ID")]
+    pub owner: Option<i64>,
+}
+
+/// Arguments of `list`.
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// Only list the links of this API key [default: every link].
+    #[arg(long, value_name = "KEY_ID")]
+    pub owner: Option<i64>,
+
+    /// Text that must appear in the short code or long URL.
+    #[arg(long, value_name = "TEXT")]
+    pub search: Option<String>,
+
+    /// Only list links with this health: unchecked, ok, dead or redirect_loop.
+    #[arg(long, value_parser = parse_health)]
+    pub health: Option<LinkHealth>,
+
+    /// Maximum number of links to print.
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_LIST_LIMIT)]
+    pub limit: i64,
+}
+
+/// Arguments of `import`.
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /// File to read, or `-` for stdin.
+    pub file: PathBuf,
+
+    /// Format of the file [default: from the file extension].
+    #[arg(long, value_enum)]
+    pub format: Option<ImportFormat>,
+
+    /// Id of the API key that owns the imported links.
+    #[arg(long, value_name = "KEY_ID")]
+    pub owner: Option<i64>,
+}
+
+/// Formats understood by `import`, the same as `POST /api/links/bulk`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ImportFormat {
+    /// CSV with a header row (`.csv`).
+    Csv,
+    /// A JSON array of create requests (`.json`).
+    Json,
+    /// One create request per line (`.jsonl` or `.ndjson`).
+    Jsonl,
+}
+
+impl ImportFormat {
+    /// Guesses the format from the file extension.
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "csv" => Some(ImportFormat::Csv),
+            "json" => Some(ImportFormat::Json),
+            "jsonl" | "ndjson" => Some(ImportFormat::Jsonl),
+            _ => None,
+        }
+    }
+}
+
+/// Arguments of `export`.
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// File format: csv or jsonl.
+    #[arg(long, default_value = "csv", value_parser = parse_export_format)]
+    pub format: ExportFormat,
+
+    /// Only export the links of this API key [default: every link].
+    #[arg(long, value_name = "KEY_ID")]
+    pub owner: Option<i64>,
+}
+
+/// API key management actions.
+#[derive(Debug, Subcommand)]
+pub enum KeyCommand {
+    /// Create a new API key and print it once.
+    Create {
+        /// Human readable label for the key, e.g. the team using it.
+        #[arg(long)]
+        name: String,
+    },
+    /// List all API keys.
+    List,
+    /// Revoke an API key so it can no longer authenticate.
+    Revoke {
+        /// Id of the key, as shown by `keys list`.
+        id: i64,
+    },
+}
+
+/// A link as printed by the admin subcommands: the API's [`LinkResponse`]
+/// plus the full short URL and the owner.
+#[derive(Serialize)]
+struct AdminLink {
+    #[serde(flatten)]
+    link: LinkResponse,
+    short_url: String,
+    owner_id: Option<i64>,
+}
+
+impl AdminLink {
+    fn new(link: Link, config: &Config) -> Self {
+        AdminLink {
+            short_url: state::short_url(&config.base_url, &link.short_code),
+            owner_id: link.owner_id,
+            link: LinkResponse::from(link),
+        }
+    }
+}
+
+/// Runs an admin subcommand against the database.
+///
+/// # Arguments
+///
+/// * `store` - The migrated storage backend
+/// * `config` - The loaded configuration (base URL, short codes, blocklist)
+/// * `output` - Table or JSON output
+/// * `command` - The parsed subcommand; `serve` is handled by `main`
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - A database operation fails
+/// - The link, key or owner does not exist
+/// - A new link fails validation (the same rules as `POST /shorten`)
+/// - The import file cannot be read or parsed
+pub async fn run(
+    store: &dyn LinkStore,
+    config: &Config,
+    output: OutputFormat,
+    command: Command,
+) -> Result<(), AppError> {
+    match command {
+        Command::Serve => Ok(()),
+        Command::Create(args) => create(store, config, output, args).await,
+        Command::Get { short_code } => {
+            let link = AdminLink::new(store.get_link(&short_code).await?, config);
+            match output {
+                OutputFormat::Table => print_link(&link),
+                OutputFormat::Json => print_json(&link)?,
+            }
+            Ok(())
+        }
+        Command::List(args) => list(store, config, output, args).await,
+        Command::Delete { short_code } => {
+            store.delete_link(&short_code).await?;
+            match output {
+                OutputFormat::Table => println!("Deleted {short_code}"),
+                OutputFormat::Json => print_json(&serde_json::json!({ "deleted": short_code }))?,
+            }
+            Ok(())
+        }
+        Command::Import(args) => import(store, config, output, args).await,
+        Command::Export(args) => export(store, args).await,
+        Command::Stats { short_code } => {
+            let stats = store.get_link_stats(&short_code).await?;
+            match output {
+                OutputFormat::Table => print_stats(&stats),
+                OutputFormat::Json => print_json(&stats)?,
+            }
+            Ok(())
+        }
+        Command::Decode { short_code } => {
+            let link_id = codes::from_config(&config.codes)
+                .decode(&short_code)
+                .ok_or_else(|| {
+                    AppError::NotFound(format!(
+                        "'{short_code}' is not a short code of the configured strategy"
+                    ))
+                })?;
+            match output {
+                OutputFormat::Table => {
+                    print_fields(&[("short_code", short_code), ("link_id", link_id.to_string())])
+                }
+                OutputFormat::Json => print_json(&serde_json::json!({
+                    "short_code": short_code,
+                    "link_id": link_id,
+                }))?,
+            }
+            Ok(())
+        }
+        Command::Vacuum => {
+            let purged = store.purge_expired_links(Utc::now().timestamp()).await?;
+            store.vacuum().await?;
+            match output {
+                OutputFormat::Table => {
+                    println!("Deleted {purged} expired links");
+                    println!("Database compacted");
+                }
+                OutputFormat::Json => print_json(&serde_json::json!({ "purged": purged }))?,
+            }
+            Ok(())
+        }
+        Command::Keys { action } => run_keys(store, output, action).await,
+    }
+}
+
+/// Creates one link with the same validation as `POST /shorten`.
+async fn create(
+    store: &dyn LinkStore,
+    config: &Config,
+    output: OutputFormat,
+    args: CreateArgs,
+) -> Result<(), AppError> {
+    let owner = find_owner(store, args.owner).await?;
+    let payload = CreateLinkRequest {
+        url: args.url,
+        alias: args.alias,
+        expires_at: args.expires_at,
+        max_clicks: args.max_clicks,
+        dedup: false,
+        interstitial: args.interstitial,
+        redirect_type: args.redirect_type.unwrap_or_default(),
+        password: None,
+        password_protected: false,
+    };
+    let blocklist = open_blocklist(config)?;
+    let mut link =
+        handlers::prepare_link(&config.base_url, &blocklist, owner.as_ref(), &payload).await?;
+    codes::create_link(store, codes::from_config(&config.codes).as_ref(), &mut link).await?;
+
+    let link = AdminLink::new(store.get_link(&link.short_code).await?, config);
+    match output {
+        OutputFormat::Table => print_link(&link),
+        OutputFormat::Json => print_json(&link)?,
+    }
+    Ok(())
+}
+
+/// Prints up to `--limit` links, reading them page by page.
+async fn list(
+    store: &dyn LinkStore,
+    config: &Config,
+    output: OutputFormat,
+    args: ListArgs,
+) -> Result<(), AppError> {
+    let mut links = Vec::new();
+    let mut cursor = None;
+    while (links.len() as i64) < args.limit {
+        let page_size = (args.limit - links.len() as i64).min(handlers::EXPORT_PAGE_SIZE);
+        let page = store
+            .list_links(
+                args.owner,
+                cursor,
+                page_size,
+                args.search.as_deref(),
+                args.health,
+            )
+            .await?;
+        let finished = (page.len() as i64) < page_size;
+        cursor = page.last().map(|link| link.id);
+        links.extend(page.into_iter().map(|link| AdminLink::new(link, config)));
+        if finished {
+            break;
+        }
+    }
+
+    match output {
+        OutputFormat::Table => print_links(&links),
+        OutputFormat::Json => print_json(&links)?,
+    }
+    Ok(())
+}
+
+/// Imports a file with the same rules as `POST /api/links/bulk`.
+///
+/// Files of any size are accepted; the rows are inserted in transactions of
+/// at most [`handlers::MAX_BULK_ROWS`] rows.
+async fn import(
+    store: &dyn LinkStore,
+    config: &Config,
+    output: OutputFormat,
+    args: ImportArgs,
+) -> Result<(), AppError> {
+    let from_stdin = args.file.as_os_str() == "-";
+    let format = args
+        .format
+        .or_else(|| ImportFormat::from_path(&args.file))
+        .ok_or_else(|| {
+            AppError::BadRequest("cannot tell the file format, pass --format".to_string())
+        })?;
+    let owner = find_owner(store, args.owner).await?;
+    let blocklist = open_blocklist(config)?;
+
+    let read_error = |err: io::Error| {
+        AppError::BadRequest(format!("cannot read {}: {err}", args.file.display()))
+    };
+    let body = if from_stdin {
+        let mut body = Vec::new();
+        io::stdin().read_to_end(&mut body).map_err(read_error)?;
+        body
+    } else {
+        std::fs::read(&args.file).map_err(read_error)?
+    };
+    let rows = match format {
+        ImportFormat::Csv => handlers::parse_csv_rows(&body)?,
+        ImportFormat::Json => handlers::parse_json_rows(&body)?,
+        ImportFormat::Jsonl => handlers::parse_jsonl_rows(&body),
+    };
+    let imported = import_rows(
+        store,
+        &config.base_url,
+        &blocklist,
+        codes::from_config(&config.codes).as_ref(),
+        owner.as_ref(),
+        rows,
+    )
+    .await?;
+
+    match output {
+        OutputFormat::Table => {
+            for result in &imported.results {
+                if let Some(error) = &result.error {
+                    println!("row {}: {error}", result.row);
+                }
+            }
+            println!(
+                "Imported {} links, {} failed",
+                imported.succeeded, imported.failed
+            );
+        }
+        OutputFormat::Json => print_json(&imported)?,
+    }
+    Ok(())
+}
+
+/// Creates links from parsed import rows, [`handlers::MAX_BULK_ROWS`] at a
+/// time, and numbers the results across all batches.
+async fn import_rows(
+    store: &dyn LinkStore,
+    base_url: &Url,
+    blocklist: &Blocklist,
+    codes: &dyn CodeGenerator,
+    owner: Option<&ApiKey>,
+    mut rows: Vec<Result<CreateLinkRequest, AppError>>,
+) -> Result<BulkResponse, AppError> {
+    let mut imported = BulkResponse {
+        succeeded: 0,
+        failed: 0,
+        results: Vec::with_capacity(rows.len()),
+    };
+    while !rows.is_empty() {
+        let rest = rows.split_off(rows.len().min(handlers::MAX_BULK_ROWS));
+        let batch = std::mem::replace(&mut rows, rest);
+        let offset = imported.results.len();
+        let response =
+            handlers::shorten_rows(store, base_url, blocklist, codes, owner, batch).await?;
+        imported.succeeded += response.succeeded;
+        imported.failed += response.failed;
+        imported
+            .results
+            .extend(response.results.into_iter().map(|mut result| {
+                result.row += offset;
+                result
+            }));
+    }
+    Ok(imported)
+}
+
+/// Streams links to stdout in the format of `GET /api/links/export`.
+async fn export(store: &dyn LinkStore, args: ExportArgs) -> Result<(), AppError> {
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    export_links(store, args.format, args.owner, &mut stdout).await
+}
+
+/// Writes the links of `owner` (or every link) to `out`, page by page.
+async fn export_links(
+    store: &dyn LinkStore,
+    format: ExportFormat,
+    owner: Option<i64>,
+    out: &mut impl Write,
+) -> Result<(), AppError> {
+    let write_error = |err: io::Error| AppError::Internal(format!("cannot write export: {err}"));
+    let mut cursor = None;
+    let mut first = true;
+    loop {
+        let links = store
+            .list_links(owner, cursor, handlers::EXPORT_PAGE_SIZE, None, None)
+            .await?;
+        let finished = (links.len() as i64) < handlers::EXPORT_PAGE_SIZE;
+        cursor = links.last().map(|link| link.id).or(cursor);
+        let chunk = handlers::encode_export_chunk(format, links, first)?;
+        out.write_all(&chunk).map_err(write_error)?;
+        first = false;
+        if finished {
+            break;
+        }
+    }
+    out.flush().map_err(write_error)
+}
+
+/// Runs an API key management action.
+async fn run_keys(
+    store: &dyn LinkStore,
+    output: OutputFormat,
+    action: KeyCommand,
+) -> Result<(), AppError> {
+    match action {
+        KeyCommand::Create { name } => {
+            let key = auth::generate_key();
+            let api_key = store.create_api_key(&name, &auth::hash_key(&key)).await?;
+            match output {
+                OutputFormat::Table => {
+                    println!("Created API key #{} ({})", api_key.id, api_key.name);
+                    println!("{key}");
+                    println!("Store this key now, it cannot be shown again.");
+                }
+                OutputFormat::Json => print_json(&serde_json::json!({
+                    "id": api_key.id,
+                    "name": api_key.name,
+                    "key": key,
+                }))?,
+            }
+        }
+        KeyCommand::List => {
+            let api_keys = store.list_api_keys().await?;
+            if output == OutputFormat::Json {
+                return print_json(&api_keys);
+            }
+            println!("{:<6} {:<24} {:<22} STATUS", "ID", "NAME", "CREATED");
+            for api_key in api_keys {
+                let status = if api_key.revoked_at.is_some() {
+                    "revoked"
+                } else {
+                    "active"
+                };
+                println!(
+                    "{:<6} {:<24} {:<22} {status}",
+                    api_key.id,
+                    api_key.name,
+                    format_timestamp(api_key.created_at),
+                );
+            }
+        }
+        KeyCommand::Revoke { id } => {
+            store.revoke_api_key(id).await?;
+            match output {
+                OutputFormat::Table => println!("Revoked API key #{id}"),
+                OutputFormat::Json => print_json(&serde_json::json!({ "revoked": id }))?,
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Looks up the API key given with `--owner`.
+///
+/// Revoked keys are accepted, so links can still be assigned to them.
+async fn find_owner(store: &dyn LinkStore, id: Option<i64>) -> Result<Option<ApiKey>, AppError> {
+    let Some(id) = id else {
+        return Ok(None);
+    };
+    store
+        .list_api_keys()
+        .await?
+        .into_iter()
+        .find(|api_key| api_key.id == id)
+        .map(Some)
+        .ok_or_else(|| AppError::NotFound(format!("API key #{id} not found")))
+}
+
+/// Loads the configured blocklist, so new links are checked like in the server.
+fn open_blocklist(config: &Config) -> Result<Blocklist, AppError> {
+    match &config.blocklist_file {
+        Some(path) => Blocklist::open(path),
+        None => Ok(Blocklist::disabled()),
+    }
+}
+
+fn parse_redirect_type(value: &str) -> Result<RedirectType, String> {
+    let code = value
+        .parse::<i32>()
+        .map_err(|_| format!("'{value}' is not a status code"))?;
+    RedirectType::try_from(code)
+}
+
+fn parse_health(value: &str) -> Result<LinkHealth, String> {
+    LinkHealth::try_from(value.to_string())
+}
+
+fn parse_export_format(value: &str) -> Result<ExportFormat, String> {
+    match value {
+        "csv" => Ok(ExportFormat::Csv),
+        "jsonl" => Ok(ExportFormat::Jsonl),
+        other => Err(format!("unknown format '{other}' (expected csv or jsonl)")),
+    }
+}
+
+/// Prints `value` as pretty JSON.
+fn print_json(value: &impl Serialize) -> Result<(), AppError> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|err| AppError::Internal(format!("cannot encode output: {err}")))?;
+    println!("{json}");
+    Ok(())
+}
+
+/// Prints label and value pairs with aligned values.
+fn print_fields(fields: &[(&str, String)]) {
+    let width = fields
+        .iter()
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or(0);
+    for (label, value) in fields {
+        println!("{label:<width$}  {value}");
+    }
+}
+
+fn print_link(link: &AdminLink) {
+    let details = &link.link;
+    print_fields(&[
+        ("short_code", details.short_code.clone()),
+        ("short_url", link.short_url.clone()),
+        ("long_url", details.long_url.clone()),
+        ("owner", format_owner(link.owner_id)),
+        ("created_at", format_time(details.created_at)),
+        ("expires_at", format_time(details.expires_at)),
+        (
+            "clicks",
+            format_clicks(details.click_count, details.max_clicks),
+        ),
+        (
+            "redirect_type",
+            details.redirect_type.status().as_u16().to_string(),
+        ),
+        ("interstitial", details.interstitial.to_string()),
+        ("password_protected", details.password_protected.to_string()),
+        ("health", details.health.as_str().to_string()),
+        ("health_checked_at", format_time(details.health_checked_at)),
+        (
+            "health_detail",
+            details
+                .health_detail
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+        ),
+    ]);
+}
+
+fn print_links(links: &[AdminLink]) {
+    let code_width = links
+        .iter()
+        .map(|link| link.link.short_code.len())
+        .max()
+        .unwrap_or(0)
+        .max("CODE".len());
+    println!(
+        "{:<code_width$} {:<6} {:<12} {:<13} {:<19} URL",
+        "CODE", "OWNER", "CLICKS", "HEALTH", "CREATED"
+    );
+    for link in links {
+        let details = &link.link;
+        println!(
+            "{:<code_width$} {:<6} {:<12} {:<13} {:<19} {}",
+            details.short_code,
+            format_owner(link.owner_id),
+            format_clicks(details.click_count, details.max_clicks),
+            details.health.as_str(),
+            format_time(details.created_at),
+            details.long_url,
+        );
+    }
+}
+
+fn print_stats(stats: &LinkStats) {
+    print_fields(&[
+        ("short_code", stats.short_code.clone()),
+        ("total_clicks", stats.total_clicks.to_string()),
+        ("unique_visitors", stats.unique_visitors.to_string()),
+    ]);
+    if !stats.daily.is_empty() {
+        println!("\n{:<12} CLICKS", "DATE");
+        for day in &stats.daily {
+            println!("{:<12} {}", day.date, day.clicks);
+        }
+    }
+    if !stats.top_referrers.is_empty() {
+        println!("\n{:<8} REFERRER", "CLICKS");
+        for referrer in &stats.top_referrers {
+            println!("{:<8} {}", referrer.clicks, referrer.referrer);
+        }
+    }
+    if !stats.variants.is_empty() {
+        println!("\n{:<8} VARIANT", "CLICKS");
+        for variant in &stats.variants {
+            println!("{:<8} {}", variant.clicks, variant.variant);
+        }
+    }
+}
+
+/// Formats a Unix timestamp as `YYYY-MM-DD HH:MM:SS` in UTC.
+fn format_timestamp(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map_or_else(
+        || "-".to_string(),
+        |time| format_timestamp(time.timestamp()),
+    )
+}
+
+fn format_owner(owner_id: Option<i64>) -> String {
+    owner_id.map_or_else(|| "-".to_string(), |id| format!("#{id}"))
+}
+
+/// Formats the click count, with the limit if the link has one (`3/10`).
+fn format_clicks(clicks: i64, max_clicks: Option<i64>) -> String {
+    match max_clicks {
+        Some(max_clicks) => format!("{clicks}/{max_clicks}"),
+        None => clicks.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{codes::RandomCodes, store::memory::MemoryStore};
+
+    fn base_url() -> Url {
+        Url::parse("http://localhost:3000/").unwrap()
+    }
+
+    async fn import_body(
+        store: &dyn LinkStore,
+        rows: Vec<Result<CreateLinkRequest, AppError>>,
+    ) -> BulkResponse {
+        import_rows(
+            store,
+            &base_url(),
+            &Blocklist::disabled(),
+            &RandomCodes::new(7, nanoid::alphabet::SAFE.into()),
+            None,
+            rows,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn import_format_follows_the_extension() {
+        assert_eq!(
+            ImportFormat::from_path(Path::new("links.csv")),
+            Some(ImportFormat::Csv)
+        );
+        assert_eq!(
+            ImportFormat::from_path(Path::new("dump/links.ndjson")),
+            Some(ImportFormat::Jsonl)
+        );
+        assert_eq!(ImportFormat::from_path(Path::new("links.txt")), None);
+        assert_eq!(ImportFormat::from_path(Path::new("-")), None);
+    }
+
+    #[tokio::test]
+    async fn exported_links_import_into_another_store() {
+        let source = MemoryStore::new();
+        let rows = handlers::parse_json_rows(
+            br#"[
+                {"url": "https://www.rust-lang.org/", "alias": "rust", "redirect_type": 301},
+                {"url": "https://docs.rs/", "max_clicks": 5, "interstitial": true}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(import_body(&source, rows).await.succeeded, 2);
+
+        for format in [ExportFormat::Csv, ExportFormat::Jsonl] {
+            let mut exported = Vec::new();
+            export_links(&source, format, None, &mut exported)
+                .await
+                .unwrap();
+            let rows = match format {
+                ExportFormat::Csv => handlers::parse_csv_rows(&exported).unwrap(),
+                ExportFormat::Jsonl => handlers::parse_jsonl_rows(&exported),
+            };
+
+            let target = MemoryStore::new();
+            let imported = import_body(&target, rows).await;
+            assert_eq!((imported.succeeded, imported.failed), (2, 0));
+            let rust = target.get_link("rust").await.unwrap();
+            assert_eq!(rust.redirect_type, RedirectType::MovedPermanently);
+            let docs = target.list_links(None, None, 10, Some("docs"), None).await;
+            let docs = &docs.unwrap()[0];
+            assert_eq!(docs.max_clicks, Some(5));
+            assert!(docs.interstitial);
+        }
+    }
+
+    #[tokio::test]
+    async fn large_imports_are_split_into_batches() {
+        let store = MemoryStore::new();
+        let mut rows: Vec<_> = (0..=handlers::MAX_BULK_ROWS)
+            .map(|n| {
+                handlers::parse_json_rows(
+                    format!(r#"[{{"url": "https://example.com/{n}"}}]"#).as_bytes(),
+                )
+                .unwrap()
+                .remove(0)
+            })
+            .collect();
+        rows.push(Err(AppError::BadRequest("invalid row".to_string())));
+
+        let imported = import_body(&store, rows).await;
+        assert_eq!(imported.succeeded, handlers::MAX_BULK_ROWS + 1);
+        assert_eq!(imported.failed, 1);
+        let failed = imported.results.last().unwrap();
+        assert_eq!(failed.row, handlers::MAX_BULK_ROWS + 2);
+        assert!(failed.error.is_some());
+    }
+}
@@ -0,0 +1,673 @@
+//! Short code generation.
+//!
+//! Links created without an alias get a code from a [`CodeGenerator`].
+//! `codes.strategy` (see [`crate::config`]) picks the generator:
+//!
+//! | Strategy | Generator | Codes |
+//! |----------|-----------|-------|
+//! | `random` (default) | [`RandomCodes`] | `codes.length` random characters from `codes.alphabet`, e.g. `kN3pL4m` |
+//! | `counter` | [`CounterCodes`] | The row id of the link in base62: `1`, `2`, ..., `Z`, `a`, ..., `10`, ... |
+//! | `obfuscated` | [`ObfuscatedCodes`] | The row id scrambled with `codes.secret` into `codes.length` characters from `codes.alphabet`, e.g. `Xq3vT0b` |
+//!
+//! Counter codes are as short as codes get but reveal how many links exist
+//! and let anyone walk through them. Obfuscated codes look random yet map
+//! one-to-one to row ids, so they never collide with each other, and the
+//! secret turns them back into the id (see [`CodeGenerator::decode`]).
+//!
+//! Codes derived from the row id are needed before the insert, so
+//! [`assign_codes`] reserves the ids first
+//! (see [`LinkStore::reserve_link_ids`]). A generated code can still be
+//! taken, by a custom alias or, for random codes, by chance:
+//! [`create_link`] and [`create_links`] then retry with a new code, up to
+//! [`MAX_CODE_ATTEMPTS`] times. Generated codes that are reserved words
+//! (see [`validation::RESERVED_ALIASES`]) are skipped.
+//!
+//! Existing links keep their codes when the strategy changes.
+
+use crate::{
+    config::CodeConfig,
+    error::AppError,
+    models::{CreatedLink, NewLink},
+    store::LinkStore,
+    validation,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{str::FromStr, sync::Arc};
+
+/// Number of codes tried for one link before its insert fails with
+/// `409 Conflict`.
+pub const MAX_CODE_ATTEMPTS: usize = 5;
+
+/// Digits of [`CounterCodes`], in ascending order.
+const BASE62: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Feistel rounds of [`ObfuscatedCodes`].
+const ROUNDS: usize = 4;
+
+/// How short codes are generated.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeStrategy {
+    /// Random nanoid codes (default).
+    #[default]
+    Random,
+    /// The row id in base62.
+    Counter,
+    /// The row id, reversibly scrambled with a secret.
+    Obfuscated,
+}
+
+impl FromStr for CodeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(CodeStrategy::Random),
+            "counter" => Ok(CodeStrategy::Counter),
+            "obfuscated" => Ok(CodeStrategy::Obfuscated),
+            other => Err(format!(
+                "unknown short code strategy '{other}' (expected random, counter or obfuscated)"
+            )),
+        }
+    }
+}
+
+/// Generates the short codes of new links.
+pub trait CodeGenerator: Send + Sync {
+    /// Whether codes are derived from the row id of the link, which then has
+    /// to be reserved before the code is generated.
+    fn uses_link_id(&self) -> bool;
+
+    /// Generates the code of a new link.
+    ///
+    /// # Arguments
+    ///
+    /// * `link_id` - The reserved row id of the link; `Some` exactly when
+    ///   [`uses_link_id`](CodeGenerator::uses_link_id) is `true`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::Internal`] if the generator needs a row id and got
+    /// none, or if the id is outside the range it can encode.
+    fn generate(&self, link_id: Option<i64>) -> Result<String, AppError>;
+
+    /// Returns the row id a code was generated from, if the generator is
+    /// reversible and `code` is one of its codes.
+    fn decode(&self, _code: &str) -> Option<i64> {
+        None
+    }
+}
+
+/// Builds the generator selected by the configuration.
+///
+/// The configuration is validated when it is loaded, so an `obfuscated`
+/// strategy always comes with a secret.
+pub fn from_config(config: &CodeConfig) -> Arc<dyn CodeGenerator> {
+    match config.strategy {
+        CodeStrategy::Random => Arc::new(RandomCodes::new(config.length, config.alphabet.clone())),
+        CodeStrategy::Counter => Arc::new(CounterCodes),
+        CodeStrategy::Obfuscated => Arc::new(ObfuscatedCodes::new(
+            config.length,
+            config.alphabet.clone(),
+            config.secret.as_deref().unwrap_or_default(),
+        )),
+    }
+}
+
+/// Random codes from [nanoid](https://docs.rs/nanoid).
+///
+/// With the default 7 URL-safe characters the probability of a collision is
+/// negligible for most use cases (~1% after 4 million codes); shorter codes
+/// or smaller alphabets raise it, and a collision costs a retry.
+pub struct RandomCodes {
+    length: usize,
+    alphabet: Arc<[char]>,
+}
+
+impl RandomCodes {
+    /// Creates a generator of `length` characters drawn from `alphabet`.
+    pub fn new(length: usize, alphabet: Arc<[char]>) -> Self {
+        RandomCodes { length, alphabet }
+    }
+}
+
+impl CodeGenerator for RandomCodes {
+    fn uses_link_id(&self) -> bool {
+        false
+    }
+
+    fn generate(&self, _link_id: Option<i64>) -> Result<String, AppError> {
+        Ok(nanoid::format(
+            nanoid::rngs::default,
+            &self.alphabet,
+            self.length,
+        ))
+    }
+}
+
+/// The row id in base62 (`0-9A-Za-z`), without padding.
+///
+/// `codes.length` and `codes.alphabet` do not apply; codes grow by one
+/// character every 62× more links (3 characters up to 238,327 links).
+pub struct CounterCodes;
+
+impl CodeGenerator for CounterCodes {
+    fn uses_link_id(&self) -> bool {
+        true
+    }
+
+    fn generate(&self, link_id: Option<i64>) -> Result<String, AppError> {
+        let id = link_id_to_u64(link_id)?;
+        Ok(to_digits(id, BASE62.len(), 1)
+            .into_iter()
+            .map(|digit| char::from(BASE62[digit]))
+            .collect())
+    }
+
+    fn decode(&self, code: &str) -> Option<i64> {
+        // A leading zero would give a second code for the same id.
+        if code.is_empty() || (code.len() > 1 && code.starts_with('0')) {
+            return None;
+        }
+        let id = decode_digits(
+            code.bytes(),
+            |byte| BASE62.iter().position(|digit| *digit == byte),
+            62,
+        )?;
+        i64::try_from(id).ok()
+    }
+}
+
+/// The row id, scrambled with a secret into a fixed number of characters.
+///
+/// The id is permuted within the `alphabet.len() ^ length` possible codes
+/// (capped at 2^63) by a keyed Feistel network, walking the cycle until the
+/// result is a valid code, and written in base `alphabet.len()`. The
+/// permutation makes consecutive ids look unrelated, but it is obfuscation,
+/// not encryption: keep the secret private and do not rely on codes being
+/// unguessable.
+///
+/// Changing the secret, length or alphabet changes the codes of new links;
+/// codes that then collide with existing ones are retried.
+pub struct ObfuscatedCodes {
+    length: usize,
+    alphabet: Arc<[char]>,
+    /// Number of possible codes.
+    space: u64,
+    /// Bits of each Feistel half.
+    half_bits: u32,
+    keys: [u64; ROUNDS],
+}
+
+impl ObfuscatedCodes {
+    /// Creates a generator of `length` characters drawn from `alphabet`,
+    /// keyed by `secret`.
+    pub fn new(length: usize, alphabet: Arc<[char]>, secret: &str) -> Self {
+        let space = u32::try_from(length)
+            .ok()
+            .and_then(|length| (alphabet.len() as u64).checked_pow(length))
+            .map_or(1 << 63, |space| space.min(1 << 63));
+        let bits = u64::BITS - (space - 1).leading_zeros();
+
+        let digest = Sha256::digest(secret.as_bytes());
+        let mut keys = [0; ROUNDS];
+        for (key, bytes) in keys.iter_mut().zip(digest.chunks_exact(8)) {
+            *key = u64::from_le_bytes(bytes.try_into().expect("chunks of 8 bytes"));
+        }
+
+        ObfuscatedCodes {
+            length,
+            alphabet,
+            space,
+            half_bits: bits.div_ceil(2).max(1),
+            keys,
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        (1 << self.half_bits) - 1
+    }
+
+    /// One step of the Feistel network; a permutation of `0..2^(2 * half_bits)`.
+    fn permute(&self, value: u64) -> u64 {
+        let (mut left, mut right) = (value >> self.half_bits, value & self.mask());
+        for key in self.keys {
+            (left, right) = (right, left ^ (mix(right ^ key) & self.mask()));
+        }
+        (left << self.half_bits) | right
+    }
+
+    /// The inverse of [`permute`](Self::permute).
+    fn unpermute(&self, value: u64) -> u64 {
+        let (mut left, mut right) = (value >> self.half_bits, value & self.mask());
+        for key in self.keys.iter().rev() {
+            (left, right) = (right ^ (mix(left ^ key) & self.mask()), left);
+        }
+        (left << self.half_bits) | right
+    }
+}
+
+impl CodeGenerator for ObfuscatedCodes {
+    fn uses_link_id(&self) -> bool {
+        true
+    }
+
+    fn generate(&self, link_id: Option<i64>) -> Result<String, AppError> {
+        let id = link_id_to_u64(link_id)?;
+        if id >= self.space {
+            return Err(AppError::Internal(format!(
+                "all {} short codes of length {} are used up",
+                self.space, self.length
+            )));
+        }
+
+        // Cycle walking: the network permutes a power of two that may be
+        // larger than the code space, so repeat until the value fits.
+        let mut value = self.permute(id);
+        while value >= self.space {
+            value = self.permute(value);
+        }
+
+        Ok(to_digits(value, self.alphabet.len(), self.length)
+            .into_iter()
+            .map(|digit| self.alphabet[digit])
+            .collect())
+    }
+
+    fn decode(&self, code: &str) -> Option<i64> {
+        if code.chars().count() != self.length {
+            return None;
+        }
+        let value = decode_digits(
+            code.chars(),
+            |c| self.alphabet.iter().position(|digit| *digit == c),
+            self.alphabet.len() as u64,
+        )?;
+        if value >= self.space {
+            return None;
+        }
+
+        let mut id = self.unpermute(value);
+        while id >= self.space {
+            id = self.unpermute(id);
+        }
+        i64::try_from(id).ok()
+    }
+}
+
+/// Checks the reserved row id handed to an id-based generator.
+fn link_id_to_u64(link_id: Option<i64>) -> Result<u64, AppError> {
+    let id = link_id
+        .ok_or_else(|| AppError::Internal("no link id reserved for the short code".to_string()))?;
+    u64::try_from(id).map_err(|_| AppError::Internal(format!("invalid link id {id}")))
+}
+
+/// Returns the digits of `value` in base `base`, most significant first and
+/// padded with zeros to at least `min_len` digits.
+fn to_digits(mut value: u64, base: usize, min_len: usize) -> Vec<usize> {
+    let base = base as u64;
+    let mut digits = Vec::new();
+    while value > 0 || digits.len() < min_len {
+        digits.push((value % base) as usize);
+        value /= base;
+    }
+    digits.reverse();
+    digits
+}
+
+/// Reads a number written in base `base`; `None` if a digit is unknown or the
+/// number overflows.
+fn decode_digits<T>(
+    mut code: impl Iterator<Item = T>,
+    digit_value: impl Fn(T) -> Option<usize>,
+    base: u64,
+) -> Option<u64> {
+    code.try_fold(0u64, |value, digit| {
+        value
+            .checked_mul(base)?
+            .checked_add(digit_value(digit)? as u64)
+    })
+}
+
+/// SplitMix64 finalizer, the round function of [`ObfuscatedCodes`].
+fn mix(mut value: u64) -> u64 {
+    value = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// Generates the short codes of `links`, reserving row ids first if the
+/// generator needs them.
+///
+/// Codes that are reserved words are skipped.
+///
+/// # Errors
+///
+/// Returns an error if the ids cannot be reserved or a code cannot be
+/// generated.
+pub async fn assign_codes(
+    store: &dyn LinkStore,
+    codes: &dyn CodeGenerator,
+    links: &mut [&mut NewLink],
+) -> Result<(), AppError> {
+    let mut ids = if codes.uses_link_id() {
+        store.reserve_link_ids(links.len()).await?
+    } else {
+        Vec::new()
+    }
+    .into_iter();
+
+    for link in links.iter_mut() {
+        loop {
+            let id = if !codes.uses_link_id() {
+                None
+            } else if let Some(id) = ids.next() {
+                Some(id)
+            } else {
+                // The previous id gave a reserved word.
+                store.reserve_link_ids(1).await?.pop()
+            };
+            let code = codes.generate(id)?;
+            if !validation::is_reserved(&code) {
+                link.id = id;
+                link.short_code = code;
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Inserts a link, generating its short code first unless it has an alias.
+///
+/// A generated code that turns out to be taken is replaced by a new one, up
+/// to [`MAX_CODE_ATTEMPTS`] times.
+///
+/// # Errors
+///
+/// Returns the error of the last insert, e.g. [`AppError::ShortCodeTaken`]
+/// if the alias is taken or [`AppError::Conflict`] if the dedup key is.
+pub async fn create_link(
+    store: &dyn LinkStore,
+    codes: &dyn CodeGenerator,
+    link: &mut NewLink,
+) -> Result<(), AppError> {
+    if !link.short_code.is_empty() {
+        return store.create_short_link(link).await;
+    }
+
+    let mut attempt = 1;
+    loop {
+        assign_codes(store, codes, &mut [&mut *link]).await?;
+        match store.create_short_link(link).await {
+            Err(err) if attempt < MAX_CODE_ATTEMPTS && is_code_taken(&err, link) => {
+                tracing::debug!(short_code = %link.short_code, "generated short code is taken");
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Inserts a batch of links, generating the short codes of those without an
+/// alias (see [`LinkStore::create_short_links`]).
+///
+/// Rows whose generated code turns out to be taken get a new code and are
+/// inserted again in a further transaction, up to [`MAX_CODE_ATTEMPTS`]
+/// times.
+///
+/// # Returns
+///
+/// Returns one result per link, in the same order. The links hold the codes
+/// they were last inserted with.
+///
+/// # Errors
+///
+/// Returns an error if codes cannot be generated or a transaction fails.
+pub async fn create_links(
+    store: &dyn LinkStore,
+    codes: &dyn CodeGenerator,
+    links: &mut [NewLink],
+) -> Result<Vec<Result<CreatedLink, AppError>>, AppError> {
+    let generated: Vec<usize> = (0..links.len())
+        .filter(|index| links[*index].short_code.is_empty())
+        .collect();
+    let mut pending = generated.clone();
+    let mut results = Vec::new();
+
+    for attempt in 1..=MAX_CODE_ATTEMPTS {
+        let mut batch: Vec<&mut NewLink> = links
+            .iter_mut()
+            .enumerate()
+            .filter(|(index, _)| pending.binary_search(index).is_ok())
+            .map(|(_, link)| link)
+            .collect();
+        assign_codes(store, codes, &mut batch).await?;
+
+        if attempt == 1 {
+            results = store.create_short_links(links).await?;
+        } else {
+            let batch: Vec<NewLink> = pending.iter().map(|index| links[*index].clone()).collect();
+            let inserted = store.create_short_links(&batch).await?;
+            for (index, result) in pending.iter().zip(inserted) {
+                results[*index] = result;
+            }
+        }
+
+        pending = generated
+            .iter()
+            .copied()
+            .filter(|index| {
+                results[*index]
+                    .as_ref()
+                    .is_err_and(|err| is_code_taken(err, &links[*index]))
+            })
+            .collect();
+        if pending.is_empty() {
+            break;
+        }
+        tracing::debug!(count = pending.len(), "generated short codes are taken");
+    }
+    Ok(results)
+}
+
+/// Whether an insert of `link` failed because its short code exists.
+fn is_code_taken(err: &AppError, link: &NewLink) -> bool {
+    matches!(err, AppError::ShortCodeTaken(code) if *code == link.short_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryStore;
+    use proptest::prelude::*;
+    use std::{collections::HashSet, sync::Mutex};
+
+    fn obfuscated() -> ObfuscatedCodes {
+        ObfuscatedCodes::new(7, nanoid::alphabet::SAFE.into(), "test-secret")
+    }
+
+    /// Hands out the given codes in order.
+    struct Scripted(Mutex<Vec<&'static str>>);
+
+    impl Scripted {
+        fn new(codes: &[&'static str]) -> Self {
+            Scripted(Mutex::new(codes.iter().rev().copied().collect()))
+        }
+    }
+
+    impl CodeGenerator for Scripted {
+        fn uses_link_id(&self) -> bool {
+            false
+        }
+
+        fn generate(&self, _link_id: Option<i64>) -> Result<String, AppError> {
+            Ok(self.0.lock().unwrap().pop().unwrap().to_string())
+        }
+    }
+
+    fn new_link(short_code: &str) -> NewLink {
+        NewLink {
+            short_code: short_code.to_string(),
+            long_url: "https://www.rust-lang.org/".to_string(),
+            ..Default::default()
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn counter_codes_round_trip(id in 0..=i64::MAX) {
+            let code = CounterCodes.generate(Some(id)).unwrap();
+            prop_assert!(code.bytes().all(|byte| BASE62.contains(&byte)));
+            prop_assert_eq!(CounterCodes.decode(&code), Some(id));
+        }
+
+        #[test]
+        fn counter_codes_are_unique(first in 0..=i64::MAX, second in 0..=i64::MAX) {
+            prop_assume!(first != second);
+            prop_assert_ne!(
+                CounterCodes.generate(Some(first)).unwrap(),
+                CounterCodes.generate(Some(second)).unwrap()
+            );
+        }
+
+        #[test]
+        fn obfuscated_codes_round_trip(id in 0..64_i64.pow(7)) {
+            let codes = obfuscated();
+            let code = codes.generate(Some(id)).unwrap();
+            prop_assert_eq!(code.len(), 7);
+            prop_assert!(code.chars().all(|c| nanoid::alphabet::SAFE.contains(&c)));
+            prop_assert_eq!(codes.decode(&code), Some(id));
+        }
+
+        #[test]
+        fn obfuscated_codes_are_unique(first in 0..64_i64.pow(7), second in 0..64_i64.pow(7)) {
+            prop_assume!(first != second);
+            let codes = obfuscated();
+            prop_assert_ne!(
+                codes.generate(Some(first)).unwrap(),
+                codes.generate(Some(second)).unwrap()
+            );
+        }
+
+        #[test]
+        fn decoding_never_panics(code in "[A-Za-z0-9_-]{0,12}") {
+            let _ = CounterCodes.decode(&code);
+            if let Some(id) = obfuscated().decode(&code) {
+                prop_assert_eq!(obfuscated().generate(Some(id)).unwrap(), code);
+            }
+        }
+    }
+
+    #[test]
+    fn obfuscated_codes_fill_the_whole_code_space() {
+        let codes = ObfuscatedCodes::new(4, "ab".chars().collect(), "secret");
+        let generated: HashSet<String> = (0..16)
+            .map(|id| codes.generate(Some(id)).unwrap())
+            .collect();
+        assert_eq!(generated.len(), 16);
+        for code in &generated {
+            assert_eq!(code.len(), 4);
+            assert!(code.chars().all(|c| c == 'a' || c == 'b'));
+        }
+        assert!(codes.generate(Some(16)).is_err());
+    }
+
+    #[test]
+    fn obfuscated_codes_depend_on_the_secret() {
+        let other = ObfuscatedCodes::new(7, nanoid::alphabet::SAFE.into(), "other-secret");
+        let ids = 1..=10;
+        assert!(ids.into_iter().any(|id| {
+            obfuscated().generate(Some(id)).unwrap() != other.generate(Some(id)).unwrap()
+        }));
+    }
+
+    #[test]
+    fn id_based_codes_need_an_id() {
+        assert!(CounterCodes.generate(None).is_err());
+        assert!(obfuscated().generate(None).is_err());
+        assert!(CounterCodes.generate(Some(-1)).is_err());
+        assert_eq!(CounterCodes.decode("01"), None);
+        assert_eq!(
+            RandomCodes::new(7, nanoid::alphabet::SAFE.into()).decode("abc"),
+            None
+        );
+    }
+
+    #[test]
+    fn random_codes_use_the_configured_alphabet() {
+        let code = RandomCodes::new(12, "ab".chars().collect())
+            .generate(None)
+            .unwrap();
+        assert_eq!(code.len(), 12);
+        assert!(code.chars().all(|c| c == 'a' || c == 'b'));
+    }
+
+    #[test]
+    fn parses_strategies() {
+        assert_eq!("counter".parse(), Ok(CodeStrategy::Counter));
+        assert_eq!("obfuscated".parse(), Ok(CodeStrategy::Obfuscated));
+        assert!("hashids".parse::<CodeStrategy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn counter_codes_skip_taken_ids() {
+        let store = MemoryStore::new();
+        // Row id 1, but the code of row id 2.
+        store.create_short_link(&new_link("2")).await.unwrap();
+
+        let mut link = new_link("");
+        create_link(&store, &CounterCodes, &mut link).await.unwrap();
+        assert_eq!((link.id, link.short_code.as_str()), (Some(3), "3"));
+        assert_eq!(store.get_link("3").await.unwrap().id, 3);
+    }
+
+    #[tokio::test]
+    async fn retries_taken_and_reserved_codes() {
+        let store = MemoryStore::new();
+        store.create_short_link(&new_link("taken")).await.unwrap();
+
+        let codes = Scripted::new(&["taken", "metrics", "free"]);
+        let mut link = new_link("");
+        create_link(&store, &codes, &mut link).await.unwrap();
+        assert_eq!(link.short_code, "free");
+
+        let codes = Scripted::new(&["taken"; MAX_CODE_ATTEMPTS]);
+        let mut link = new_link("");
+        assert!(matches!(
+            create_link(&store, &codes, &mut link).await,
+            Err(AppError::ShortCodeTaken(_))
+        ));
+
+        // An alias is never replaced.
+        let mut link = new_link("taken");
+        assert!(create_link(&store, &codes, &mut link).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn bulk_inserts_retry_only_generated_codes() {
+        let store = MemoryStore::new();
+        store.create_short_link(&new_link("taken")).await.unwrap();
+
+        let codes = Scripted::new(&["first", "taken", "second"]);
+        let mut links = vec![
+            new_link(""),
+            new_link("taken"),
+            new_link(""),
+            new_link("alias"),
+        ];
+        let results = create_links(&store, &codes, &mut links).await.unwrap();
+
+        let created: Vec<Option<&str>> = results
+            .iter()
+            .map(|result| result.as_ref().ok().map(|link| link.short_code.as_str()))
+            .collect();
+        assert_eq!(
+            created,
+            [Some("first"), None, Some("second"), Some("alias")]
+        );
+        assert_eq!(links[2].short_code, "second");
+    }
+}
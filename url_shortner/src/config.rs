@@ -0,0 +1,903 @@
+//! Server configuration.
+//!
+//! [`Config::load`] merges three layers, each overriding the one before it:
+//!
+//! 1. A TOML file: `--config <PATH>`, else `CONFIG_FILE`, else
+//!    `url_shortner.toml` in the working directory if it exists
+//! 2. Environment variables (including those loaded from `.env`)
+//! 3. Command line flags (see [`ConfigArgs`])
+//!
+//! Settings that none of the layers set fall back to their defaults.
+//!
+//! # Settings
+//!
+//! | TOML key | Environment variable | Flag | Default |
+//! |----------|----------------------|------|---------|
+//! | `server.bind_address` | `BIND_ADDRESS` | `--bind` | `127.0.0.1:3000` |
+//! | `server.base_url` | `PUBLIC_BASE_URL` | `--base-url` | `http://` + bind address |
+//! | `server.shutdown_timeout_secs` | `SHUTDOWN_TIMEOUT_SECS` | | `30` (`0` cuts requests off at once) |
+//! | `codes.length` | `SHORT_CODE_LENGTH` | `--code-length` | `7` |
+//! | `codes.alphabet` | `SHORT_CODE_ALPHABET` | `--code-alphabet` | `A-Za-z0-9_-` |
+//! | `codes.strategy` | `SHORT_CODE_STRATEGY` | `--code-strategy` | `random` (or `counter`, `obfuscated`; see [`crate::codes`]) |
+//! | `codes.secret` | `SHORT_CODE_SECRET` | | none (required by `obfuscated`) |
+//! | `database.url` | `DATABASE_URL` | `--database-url` | required |
+//! | `database.max_connections` | `DATABASE_MAX_CONNECTIONS` | `--database-max-connections` | `10` |
+//! | `cache.capacity` | `LINK_CACHE_CAPACITY` | | `10000` |
+//! | `cache.ttl_secs` | `LINK_CACHE_TTL_SECS` | | `60` |
+//! | `tasks.purge_interval_secs` | `PURGE_INTERVAL_SECS` | | `300` |
+//! | `analytics.ip_hash_salt` | `IP_HASH_SALT` | | random per process |
+//! | `geoip.database` | `GEOIP_DATABASE` | | none (country rules never match) |
+//! | `blocklist.file` | `BLOCKLIST_FILE` | | none (nothing is blocked) |
+//! | `blocklist.reload_interval_secs` | `BLOCKLIST_RELOAD_INTERVAL_SECS` | | `30` |
+//! | `probe.interval_secs` | `PROBE_INTERVAL_SECS` | | `3600` (`0` disables probing) |
+//! | `probe.recheck_after_secs` | `PROBE_RECHECK_AFTER_SECS` | | `86400` |
+//! | `probe.batch_size` | `PROBE_BATCH_SIZE` | | `100` |
+//! | `probe.timeout_secs` | `PROBE_TIMEOUT_SECS` | | `10` |
+//! | `log.format` | `LOG_FORMAT` | | `text` (or `json`) |
+//! | `log.filter` | `RUST_LOG` | | `info` |
+//! | `rate_limits.<group>.per_minute` | `RATE_LIMIT_<GROUP>_PER_MINUTE` | | see below |
+//! | `rate_limits.<group>.burst` | `RATE_LIMIT_<GROUP>_BURST` | | see below |
+//!
+//! The rate limit groups are `shorten` (60 per minute, burst 10), `redirect`
+//! (600, burst 50), `api` (300, burst 30) and `password` (failed password
+//! attempts per link and client IP: 5 per minute, burst 5); see
+//! [`crate::rate_limit`].
+//! A `per_minute` of `0` disables the group's limit.
+//!
+//! # Example File
+//!
+//! ```toml
+//! [server]
+//! bind_address = "0.0.0.0:8080"
+//! base_url = "https://sho.rt"
+//!
+//! [codes]
+//! length = 8
+//! alphabet = "23456789abcdefghjkmnpqrstuvwxyz"
+//!
+//! [database]
+//! url = "sqlite://urls.db"
+//! max_connections = 5
+//!
+//! [rate_limits.shorten]
+//! per_minute = 30
+//! burst = 5
+//!
+//! [blocklist]
+//! file = "/etc/url_shortner/blocklist.txt"
+//!
+//! [probe]
+//! interval_secs = 600
+//!
+//! [log]
+//! format = "json"
+//! filter = "info,url_shortner=debug"
+//! ```
+
+use crate::{
+    blocklist, cache,
+    cli::ConfigArgs,
+    codes::CodeStrategy,
+    probe,
+    rate_limit::{self, RateLimit},
+    shutdown, tasks,
+    telemetry::{self, LogFormat},
+    validation,
+};
+use serde::Deserialize;
+use std::{
+    env, fmt,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing_subscriber::EnvFilter;
+use url::Url;
+
+/// Config file loaded when neither `--config` nor `CONFIG_FILE` is given.
+pub const DEFAULT_CONFIG_FILE: &str = "url_shortner.toml";
+
+/// Default address the HTTP server listens on.
+pub const DEFAULT_BIND_ADDRESS: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 3000);
+
+/// Default length of generated short codes.
+pub const DEFAULT_CODE_LENGTH: usize = 7;
+
+/// Shortest allowed generated code. Shorter codes would collide with
+/// reserved route names and run out of space quickly.
+pub const MIN_CODE_LENGTH: usize = 4;
+
+/// Longest allowed generated code (the same limit as custom aliases).
+pub const MAX_CODE_LENGTH: usize = validation::ALIAS_MAX_LEN;
+
+/// Default database pool size (the sqlx default).
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+
+/// Error returned when a configuration layer cannot be read or a value is invalid.
+#[derive(Debug)]
+pub struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Fully resolved server configuration.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Address the HTTP server listens on.
+    pub bind_address: SocketAddr,
+    /// Public URL of the service. Always ends with `/`, so a short code can
+    /// be appended to build the short URL.
+    pub base_url: Url,
+    /// Seconds in-flight requests may take to finish after a shutdown signal.
+    pub shutdown_timeout_secs: u64,
+    /// How generated short codes look.
+    pub codes: CodeConfig,
+    /// Database connection settings.
+    pub database: DatabaseConfig,
+    /// Maximum number of links in the redirect cache, `0` disables it.
+    pub cache_capacity: usize,
+    /// Seconds a cached link is served before it is reloaded.
+    pub cache_ttl_secs: u64,
+    /// Seconds between two purges of expired links.
+    pub purge_interval_secs: u64,
+    /// Secret salt for hashing client IPs, if configured.
+    pub ip_hash_salt: Option<String>,
+    /// Rate limits of the `shorten` route group.
+    pub shorten_limit: RateLimit,
+    /// Rate limits of the `redirect` route group.
+    pub redirect_limit: RateLimit,
+    /// Rate limits of the `api` route group.
+    pub api_limit: RateLimit,
+    /// Limit of failed password attempts per link and client IP.
+    pub password_limit: RateLimit,
+    /// MaxMind country database (`.mmdb`) used by country redirect rules.
+    pub geoip_database: Option<PathBuf>,
+    /// File of blocked destinations (see [`crate::blocklist`]).
+    pub blocklist_file: Option<PathBuf>,
+    /// Seconds between two checks of the blocklist file for changes.
+    pub blocklist_reload_interval_secs: u64,
+    /// Seconds between two link health probe runs, `0` disables probing.
+    pub probe_interval_secs: u64,
+    /// Seconds after which a link's health is probed again.
+    pub probe_recheck_after_secs: u64,
+    /// Maximum number of links probed per run.
+    pub probe_batch_size: i64,
+    /// Seconds a probe request may take.
+    pub probe_timeout_secs: u64,
+    /// Output format of the log.
+    pub log_format: LogFormat,
+    /// `RUST_LOG` style filter directives of the log.
+    pub log_filter: String,
+}
+
+/// How generated short codes look (see [`crate::codes`]).
+#[derive(Debug, Clone)]
+pub struct CodeConfig {
+    /// How codes are generated.
+    pub strategy: CodeStrategy,
+    /// Number of characters in a generated code (not used by `counter`).
+    pub length: usize,
+    /// Characters a generated code is drawn from (not used by `counter`).
+    pub alphabet: Arc<[char]>,
+    /// Secret of `obfuscated` codes.
+    pub secret: Option<String>,
+}
+
+impl Default for CodeConfig {
+    fn default() -> Self {
+        CodeConfig {
+            strategy: CodeStrategy::default(),
+            length: DEFAULT_CODE_LENGTH,
+            alphabet: nanoid::alphabet::SAFE.into(),
+            secret: None,
+        }
+    }
+}
+
+/// Database connection settings.
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    /// Connection string; the scheme selects the storage backend.
+    pub url: String,
+    /// Maximum number of pooled connections (ignored by the `memory:` backend).
+    pub max_connections: u32,
+}
+
+/// One configuration layer. Every setting is optional so that layers can
+/// be merged; [`Config::resolve`] applies the defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Layer {
+    #[serde(default)]
+    server: ServerSection,
+    #[serde(default)]
+    codes: CodesSection,
+    #[serde(default)]
+    database: DatabaseSection,
+    #[serde(default)]
+    cache: CacheSection,
+    #[serde(default)]
+    tasks: TasksSection,
+    #[serde(default)]
+    analytics: AnalyticsSection,
+    #[serde(default)]
+    rate_limits: RateLimitsSection,
+    #[serde(default)]
+    geoip: GeoipSection,
+    #[serde(default)]
+    blocklist: BlocklistSection,
+    #[serde(default)]
+    probe: ProbeSection,
+    #[serde(default)]
+    log: LogSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServerSection {
+    bind_address: Option<SocketAddr>,
+    base_url: Option<String>,
+    shutdown_timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CodesSection {
+    strategy: Option<CodeStrategy>,
+    length: Option<usize>,
+    alphabet: Option<String>,
+    secret: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DatabaseSection {
+    url: Option<String>,
+    max_connections: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CacheSection {
+    capacity: Option<usize>,
+    ttl_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TasksSection {
+    purge_interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AnalyticsSection {
+    ip_hash_salt: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GeoipSection {
+    database: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlocklistSection {
+    file: Option<PathBuf>,
+    reload_interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProbeSection {
+    interval_secs: Option<u64>,
+    recheck_after_secs: Option<u64>,
+    batch_size: Option<i64>,
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LogSection {
+    format: Option<LogFormat>,
+    filter: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RateLimitsSection {
+    #[serde(default)]
+    shorten: LimitSection,
+    #[serde(default)]
+    redirect: LimitSection,
+    #[serde(default)]
+    api: LimitSection,
+    #[serde(default)]
+    password: LimitSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LimitSection {
+    per_minute: Option<u32>,
+    burst: Option<u32>,
+}
+
+impl LimitSection {
+    /// Reads `RATE_LIMIT_<GROUP>_PER_MINUTE` and `RATE_LIMIT_<GROUP>_BURST`.
+    fn from_env(var: &impl Fn(&str) -> Option<String>, group: &str) -> Result<Self, ConfigError> {
+        Ok(LimitSection {
+            per_minute: parse_var(var, &format!("RATE_LIMIT_{group}_PER_MINUTE"))?,
+            burst: parse_var(var, &format!("RATE_LIMIT_{group}_BURST"))?,
+        })
+    }
+
+    /// Returns `self` with every setting that `over` sets replaced.
+    fn merge(self, over: LimitSection) -> LimitSection {
+        LimitSection {
+            per_minute: over.per_minute.or(self.per_minute),
+            burst: over.burst.or(self.burst),
+        }
+    }
+
+    /// Fills in the group's defaults.
+    fn resolve(self, default: RateLimit) -> RateLimit {
+        RateLimit {
+            per_minute: self.per_minute.unwrap_or(default.per_minute),
+            burst: self.burst.unwrap_or(default.burst),
+        }
+    }
+}
+
+impl Layer {
+    /// Parses a TOML config file.
+    fn from_toml(source: &str) -> Result<Self, ConfigError> {
+        toml::from_str(source).map_err(|err| ConfigError(err.to_string()))
+    }
+
+    /// Reads the environment variables listed in the module docs.
+    ///
+    /// `var` looks a variable up, so tests do not have to touch the process
+    /// environment.
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        Ok(Layer {
+            server: ServerSection {
+                bind_address: parse_var(&var, "BIND_ADDRESS")?,
+                base_url: var("PUBLIC_BASE_URL"),
+                shutdown_timeout_secs: parse_var(&var, "SHUTDOWN_TIMEOUT_SECS")?,
+            },
+            codes: CodesSection {
+                strategy: parse_var(&var, "SHORT_CODE_STRATEGY")?,
+                length: parse_var(&var, "SHORT_CODE_LENGTH")?,
+                alphabet: var("SHORT_CODE_ALPHABET"),
+                secret: var("SHORT_CODE_SECRET"),
+            },
+            database: DatabaseSection {
+                url: var("DATABASE_URL"),
+                max_connections: parse_var(&var, "DATABASE_MAX_CONNECTIONS")?,
+            },
+            cache: CacheSection {
+                capacity: parse_var(&var, "LINK_CACHE_CAPACITY")?,
+                ttl_secs: parse_var(&var, "LINK_CACHE_TTL_SECS")?,
+            },
+            tasks: TasksSection {
+                purge_interval_secs: parse_var(&var, "PURGE_INTERVAL_SECS")?,
+            },
+            analytics: AnalyticsSection {
+                ip_hash_salt: var("IP_HASH_SALT"),
+            },
+            rate_limits: RateLimitsSection {
+                shorten: LimitSection::from_env(&var, "SHORTEN")?,
+                redirect: LimitSection::from_env(&var, "REDIRECT")?,
+                api: LimitSection::from_env(&var, "API")?,
+                password: LimitSection::from_env(&var, "PASSWORD")?,
+            },
+            geoip: GeoipSection {
+                database: var("GEOIP_DATABASE").map(PathBuf::from),
+            },
+            blocklist: BlocklistSection {
+                file: var("BLOCKLIST_FILE").map(PathBuf::from),
+                reload_interval_secs: parse_var(&var, "BLOCKLIST_RELOAD_INTERVAL_SECS")?,
+            },
+            probe: ProbeSection {
+                interval_secs: parse_var(&var, "PROBE_INTERVAL_SECS")?,
+                recheck_after_secs: parse_var(&var, "PROBE_RECHECK_AFTER_SECS")?,
+                batch_size: parse_var(&var, "PROBE_BATCH_SIZE")?,
+                timeout_secs: parse_var(&var, "PROBE_TIMEOUT_SECS")?,
+            },
+            log: LogSection {
+                format: parse_var(&var, "LOG_FORMAT")?,
+                filter: var("RUST_LOG"),
+            },
+        })
+    }
+
+    /// Takes the command line overrides.
+    fn from_args(args: &ConfigArgs) -> Self {
+        Layer {
+            server: ServerSection {
+                bind_address: args.bind,
+                base_url: args.base_url.clone(),
+                shutdown_timeout_secs: None,
+            },
+            codes: CodesSection {
+                strategy: args.code_strategy,
+                length: args.code_length,
+                alphabet: args.code_alphabet.clone(),
+                secret: None,
+            },
+            database: DatabaseSection {
+                url: args.database_url.clone(),
+                max_connections: args.database_max_connections,
+            },
+            ..Layer::default()
+        }
+    }
+
+    /// Returns `self` with every setting that `over` sets replaced.
+    fn merge(self, over: Layer) -> Layer {
+        Layer {
+            server: ServerSection {
+                bind_address: over.server.bind_address.or(self.server.bind_address),
+                base_url: over.server.base_url.or(self.server.base_url),
+                shutdown_timeout_secs: over
+                    .server
+                    .shutdown_timeout_secs
+                    .or(self.server.shutdown_timeout_secs),
+            },
+            codes: CodesSection {
+                strategy: over.codes.strategy.or(self.codes.strategy),
+                length: over.codes.length.or(self.codes.length),
+                alphabet: over.codes.alphabet.or(self.codes.alphabet),
+                secret: over.codes.secret.or(self.codes.secret),
+            },
+            database: DatabaseSection {
+                url: over.database.url.or(self.database.url),
+                max_connections: over
+                    .database
+                    .max_connections
+                    .or(self.database.max_connections),
+            },
+            cache: CacheSection {
+                capacity: over.cache.capacity.or(self.cache.capacity),
+                ttl_secs: over.cache.ttl_secs.or(self.cache.ttl_secs),
+            },
+            tasks: TasksSection {
+                purge_interval_secs: over
+                    .tasks
+                    .purge_interval_secs
+                    .or(self.tasks.purge_interval_secs),
+            },
+            analytics: AnalyticsSection {
+                ip_hash_salt: over.analytics.ip_hash_salt.or(self.analytics.ip_hash_salt),
+            },
+            rate_limits: RateLimitsSection {
+                shorten: self.rate_limits.shorten.merge(over.rate_limits.shorten),
+                redirect: self.rate_limits.redirect.merge(over.rate_limits.redirect),
+                api: self.rate_limits.api.merge(over.rate_limits.api),
+                password: self.rate_limits.password.merge(over.rate_limits.password),
+            },
+            geoip: GeoipSection {
+                database: over.geoip.database.or(self.geoip.database),
+            },
+            blocklist: BlocklistSection {
+                file: over.blocklist.file.or(self.blocklist.file),
+                reload_interval_secs: over
+                    .blocklist
+                    .reload_interval_secs
+                    .or(self.blocklist.reload_interval_secs),
+            },
+            probe: ProbeSection {
+                interval_secs: over.probe.interval_secs.or(self.probe.interval_secs),
+                recheck_after_secs: over
+                    .probe
+                    .recheck_after_secs
+                    .or(self.probe.recheck_after_secs),
+                batch_size: over.probe.batch_size.or(self.probe.batch_size),
+                timeout_secs: over.probe.timeout_secs.or(self.probe.timeout_secs),
+            },
+            log: LogSection {
+                format: over.log.format.or(self.log.format),
+                filter: over.log.filter.or(self.log.filter),
+            },
+        }
+    }
+}
+
+/// Parses the environment variable `name`, if it is set.
+fn parse_var<T: std::str::FromStr>(
+    var: &impl Fn(&str) -> Option<String>,
+    name: &str,
+) -> Result<Option<T>, ConfigError> {
+    var(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| ConfigError(format!("{name} has an invalid value '{value}'")))
+        })
+        .transpose()
+}
+
+impl Config {
+    /// Loads the configuration from the config file, the environment and
+    /// the command line flags.
+    ///
+    /// Call after `.env` has been loaded, so its variables are seen.
+    ///
+    /// # Arguments
+    ///
+    /// * `args` - Command line flags; they take precedence over everything else
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - An explicitly given config file cannot be read
+    /// - The config file is not valid TOML or has unknown keys
+    /// - An environment variable or setting has an invalid value
+    /// - No database URL is configured
+    pub fn load(args: &ConfigArgs) -> Result<Self, ConfigError> {
+        let explicit_path = args
+            .config
+            .clone()
+            .or_else(|| env::var_os("CONFIG_FILE").map(PathBuf::from));
+        let file = match explicit_path {
+            Some(path) => read_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                read_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Layer::default(),
+        };
+
+        let env = Layer::from_env(|name| env::var(name).ok())?;
+        Config::resolve(file.merge(env).merge(Layer::from_args(args)))
+    }
+
+    /// Validates a merged layer and fills in the defaults.
+    fn resolve(layer: Layer) -> Result<Self, ConfigError> {
+        let bind_address = layer.server.bind_address.unwrap_or(DEFAULT_BIND_ADDRESS);
+        let base_url = match layer.server.base_url {
+            Some(base_url) => parse_base_url(&base_url)?,
+            None => default_base_url(bind_address),
+        };
+
+        let length = layer.codes.length.unwrap_or(DEFAULT_CODE_LENGTH);
+        if !(MIN_CODE_LENGTH..=MAX_CODE_LENGTH).contains(&length) {
+            return Err(ConfigError(format!(
+                "short code length must be between {MIN_CODE_LENGTH} and {MAX_CODE_LENGTH}"
+            )));
+        }
+        let alphabet = match layer.codes.alphabet {
+            Some(alphabet) => parse_alphabet(&alphabet)?,
+            None => CodeConfig::default().alphabet,
+        };
+        let strategy = layer.codes.strategy.unwrap_or_default();
+        if strategy == CodeStrategy::Obfuscated
+            && layer.codes.secret.as_deref().is_none_or(str::is_empty)
+        {
+            return Err(ConfigError(
+                "obfuscated short codes need a secret (SHORT_CODE_SECRET or codes.secret)"
+                    .to_string(),
+            ));
+        }
+
+        let url = layer.database.url.ok_or_else(|| {
+            ConfigError(
+                "a database URL must be set (DATABASE_URL, --database-url or database.url)"
+                    .to_string(),
+            )
+        })?;
+        let max_connections = layer
+            .database
+            .max_connections
+            .unwrap_or(DEFAULT_MAX_CONNECTIONS);
+        if max_connections == 0 {
+            return Err(ConfigError(
+                "database max_connections must be at least 1".to_string(),
+            ));
+        }
+
+        let purge_interval_secs = layer
+            .tasks
+            .purge_interval_secs
+            .unwrap_or(tasks::DEFAULT_PURGE_INTERVAL_SECS);
+        if purge_interval_secs == 0 {
+            return Err(ConfigError(
+                "purge interval must be a positive number of seconds".to_string(),
+            ));
+        }
+
+        let blocklist_reload_interval_secs = layer
+            .blocklist
+            .reload_interval_secs
+            .unwrap_or(blocklist::DEFAULT_RELOAD_INTERVAL_SECS);
+        if blocklist_reload_interval_secs == 0 {
+            return Err(ConfigError(
+                "blocklist reload interval must be a positive number of seconds".to_string(),
+            ));
+        }
+        let probe_batch_size = layer.probe.batch_size.unwrap_or(probe::DEFAULT_BATCH_SIZE);
+        if probe_batch_size < 1 {
+            return Err(ConfigError(
+                "probe batch size must be at least 1".to_string(),
+            ));
+        }
+        let probe_timeout_secs = layer
+            .probe
+            .timeout_secs
+            .unwrap_or(probe::DEFAULT_TIMEOUT_SECS);
+        if probe_timeout_secs == 0 {
+            return Err(ConfigError(
+                "probe timeout must be a positive number of seconds".to_string(),
+            ));
+        }
+
+        let log_filter = layer
+            .log
+            .filter
+            .unwrap_or_else(|| telemetry::DEFAULT_LOG_FILTER.to_string());
+        if let Err(err) = EnvFilter::try_new(&log_filter) {
+            return Err(ConfigError(format!(
+                "log filter '{log_filter}' is invalid: {err}"
+            )));
+        }
+
+        Ok(Config {
+            bind_address,
+            base_url,
+            shutdown_timeout_secs: layer
+                .server
+                .shutdown_timeout_secs
+                .unwrap_or(shutdown::DEFAULT_DRAIN_TIMEOUT_SECS),
+            codes: CodeConfig {
+                strategy,
+                length,
+                alphabet,
+                secret: layer.codes.secret,
+            },
+            database: DatabaseConfig {
+                url,
+                max_connections,
+            },
+            cache_capacity: layer.cache.capacity.unwrap_or(cache::DEFAULT_CAPACITY),
+            cache_ttl_secs: layer.cache.ttl_secs.unwrap_or(cache::DEFAULT_TTL_SECS),
+            purge_interval_secs,
+            ip_hash_salt: layer.analytics.ip_hash_salt,
+            shorten_limit: layer
+                .rate_limits
+                .shorten
+                .resolve(rate_limit::DEFAULT_SHORTEN_LIMIT),
+            redirect_limit: layer
+                .rate_limits
+                .redirect
+                .resolve(rate_limit::DEFAULT_REDIRECT_LIMIT),
+            api_limit: layer.rate_limits.api.resolve(rate_limit::DEFAULT_API_LIMIT),
+            password_limit: layer
+                .rate_limits
+                .password
+                .resolve(rate_limit::DEFAULT_PASSWORD_LIMIT),
+            geoip_database: layer.geoip.database,
+            blocklist_file: layer.blocklist.file,
+            blocklist_reload_interval_secs,
+            probe_interval_secs: layer
+                .probe
+                .interval_secs
+                .unwrap_or(probe::DEFAULT_INTERVAL_SECS),
+            probe_recheck_after_secs: layer
+                .probe
+                .recheck_after_secs
+                .unwrap_or(probe::DEFAULT_RECHECK_AFTER_SECS),
+            probe_batch_size,
+            probe_timeout_secs,
+            log_format: layer.log.format.unwrap_or_default(),
+            log_filter,
+        })
+    }
+}
+
+/// Reads and parses a config file.
+fn read_file(path: &Path) -> Result<Layer, ConfigError> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|err| ConfigError(format!("cannot read {}: {err}", path.display())))?;
+    Layer::from_toml(&source).map_err(|err| ConfigError(format!("{}: {err}", path.display())))
+}
+
+/// Parses the public base URL and makes sure its path ends with `/`.
+fn parse_base_url(raw: &str) -> Result<Url, ConfigError> {
+    let mut url = Url::parse(raw)
+        .map_err(|err| ConfigError(format!("base URL '{raw}' is invalid: {err}")))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+        return Err(ConfigError(format!(
+            "base URL '{raw}' must be an absolute http or https URL"
+        )));
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err(ConfigError(format!(
+            "base URL '{raw}' must not have a query or fragment"
+        )));
+    }
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    Ok(url)
+}
+
+/// Derives the base URL from the bind address, using `localhost` when the
+/// server listens on all interfaces.
+fn default_base_url(bind_address: SocketAddr) -> Url {
+    let host = match bind_address.ip() {
+        ip if ip.is_unspecified() || ip.is_loopback() => "localhost".to_string(),
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("[{ip}]"),
+    };
+    Url::parse(&format!("http://{host}:{}/", bind_address.port()))
+        .expect("a socket address always forms a valid URL")
+}
+
+/// Parses a short code alphabet.
+///
+/// The characters must be usable in a URL path and in a custom alias
+/// (`[A-Za-z0-9_-]`), and there must be at least two distinct ones.
+fn parse_alphabet(raw: &str) -> Result<Arc<[char]>, ConfigError> {
+    let mut alphabet: Vec<char> = raw.chars().collect();
+    if let Some(invalid) = alphabet
+        .iter()
+        .find(|c| !c.is_ascii_alphanumeric() && **c != '-' && **c != '_')
+    {
+        return Err(ConfigError(format!(
+            "short code alphabet may only contain letters, digits, '-' and '_', found '{invalid}'"
+        )));
+    }
+
+    alphabet.sort_unstable();
+    alphabet.dedup();
+    if alphabet.len() < 2 {
+        return Err(ConfigError(
+            "short code alphabet needs at least two distinct characters".to_string(),
+        ));
+    }
+    Ok(alphabet.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env_layer(vars: &[(&str, &str)]) -> Result<Layer, ConfigError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Layer::from_env(|name| vars.get(name).cloned())
+    }
+
+    fn with_database(layer: Layer) -> Layer {
+        Layer {
+            database: DatabaseSection {
+                url: Some("memory:".to_string()),
+                max_connections: None,
+            },
+            ..Layer::default()
+        }
+        .merge(layer)
+    }
+
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let file = Layer::from_toml(
+            r#"
+            [server]
+            bind_address = "0.0.0.0:8080"
+            base_url = "https://file.example"
+            shutdown_timeout_secs = 5
+
+            [codes]
+            strategy = "counter"
+            length = 9
+
+            [database]
+            url = "sqlite://file.db"
+
+            [log]
+            format = "json"
+            filter = "warn"
+            "#,
+        )
+        .unwrap();
+        let env = env_layer(&[
+            ("PUBLIC_BASE_URL", "https://env.example/s"),
+            ("SHORT_CODE_LENGTH", "8"),
+            ("SHORT_CODE_STRATEGY", "obfuscated"),
+            ("SHORT_CODE_SECRET", "s3cret"),
+            ("SHUTDOWN_TIMEOUT_SECS", "0"),
+            ("RUST_LOG", "info,url_shortner=debug"),
+        ])
+        .unwrap();
+        let args = Layer::from_args(&ConfigArgs {
+            code_length: Some(6),
+            ..ConfigArgs::default()
+        });
+
+        let config = Config::resolve(file.merge(env).merge(args)).unwrap();
+        assert_eq!(config.bind_address, "0.0.0.0:8080".parse().unwrap());
+        assert_eq!(config.base_url.as_str(), "https://env.example/s/");
+        assert_eq!(config.shutdown_timeout_secs, 0);
+        assert_eq!(config.codes.length, 6);
+        assert_eq!(config.codes.strategy, CodeStrategy::Obfuscated);
+        assert_eq!(config.codes.secret.as_deref(), Some("s3cret"));
+        assert_eq!(config.database.url, "sqlite://file.db");
+        assert_eq!(config.database.max_connections, DEFAULT_MAX_CONNECTIONS);
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.log_filter, "info,url_shortner=debug");
+    }
+
+    #[test]
+    fn rate_limits_merge_per_group() {
+        let file = Layer::from_toml(
+            r#"
+            [rate_limits.shorten]
+            per_minute = 30
+            burst = 5
+
+            [rate_limits.redirect]
+            per_minute = 0
+            "#,
+        )
+        .unwrap();
+        let env = env_layer(&[("RATE_LIMIT_SHORTEN_BURST", "2")]).unwrap();
+
+        let config = Config::resolve(with_database(file.merge(env))).unwrap();
+        assert_eq!(
+            config.shorten_limit,
+            RateLimit {
+                per_minute: 30,
+                burst: 2
+            }
+        );
+        assert!(config.redirect_limit.is_disabled());
+        assert_eq!(config.api_limit, rate_limit::DEFAULT_API_LIMIT);
+        assert_eq!(config.password_limit, rate_limit::DEFAULT_PASSWORD_LIMIT);
+    }
+
+    #[test]
+    fn base_url_defaults_to_bind_address() {
+        let config = Config::resolve(with_database(Layer::default())).unwrap();
+        assert_eq!(config.base_url.as_str(), "http://localhost:3000/");
+
+        let env = env_layer(&[("BIND_ADDRESS", "192.168.1.5:8080")]).unwrap();
+        let config = Config::resolve(with_database(env)).unwrap();
+        assert_eq!(config.base_url.as_str(), "http://192.168.1.5:8080/");
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        assert!(Layer::from_toml("[server]\nport = 3000").is_err());
+        assert!(env_layer(&[("SHORT_CODE_LENGTH", "seven")]).is_err());
+        assert!(env_layer(&[("LOG_FORMAT", "xml")]).is_err());
+        assert!(Config::resolve(Layer::default()).is_err());
+
+        for vars in [
+            [("SHORT_CODE_LENGTH", "2")],
+            [("SHORT_CODE_ALPHABET", "aaaa")],
+            [("SHORT_CODE_ALPHABET", "ab/cd")],
+            [("SHORT_CODE_STRATEGY", "obfuscated")],
+            [("PUBLIC_BASE_URL", "ftp://sho.rt")],
+            [("PUBLIC_BASE_URL", "https://sho.rt/?x=1")],
+            [("DATABASE_MAX_CONNECTIONS", "0")],
+            [("BLOCKLIST_RELOAD_INTERVAL_SECS", "0")],
+            [("PROBE_BATCH_SIZE", "0")],
+            [("PROBE_TIMEOUT_SECS", "0")],
+            [("RUST_LOG", "url_shortner=loud")],
+        ] {
+            let layer = with_database(env_layer(&vars).unwrap());
+            assert!(Config::resolve(layer).is_err(), "{vars:?} was accepted");
+        }
+    }
+}
@@ -1,17 +1,77 @@
-//! Database operations and connection management.
+//! SQLite database operations and connection management.
+//!
+//! These are the queries behind [`SqliteStore`](crate::store::SqliteStore);
+//! the rest of the service goes through the [`LinkStore`](crate::store::LinkStore)
+//! trait instead of calling them directly.
 //!
 //! This module provides all database-related functionality including:
 //! - Connection pool management
-//! - Schema initialization
-//! - CRUD operations for URL links
+//! - Schema initialization (via [`crate::migrations`])
+//! - CRUD operations for URL links (create, get, list, update, delete)
+//! - Link lifecycle (expiry by time or click count)
+//! - Click analytics storage and aggregation
+//! - API key storage
+//!
+//! Every function returns [`AppError`] on failure so handlers can propagate
+//! errors with `?` instead of panicking.
 //!
 //! # Database Schema
 //!
+//! The schema is defined by the versioned SQL files in `migrations/`.
+//! After all migrations are applied it looks like this:
+//!
 //! ```sql
 //! CREATE TABLE IF NOT EXISTS links (
 //!     id INTEGER PRIMARY KEY AUTOINCREMENT,
 //!     short_code TEXT NOT NULL UNIQUE,
-//!     long_url TEXT NOT NULL
+//!     long_url TEXT NOT NULL,
+//!     expires_at INTEGER,
+//!     max_clicks INTEGER,
+//!     click_count INTEGER NOT NULL DEFAULT 0,
+//!     dedup_key TEXT UNIQUE,
+//!     owner_id INTEGER REFERENCES api_keys (id),
+//!     created_at INTEGER,
+//!     interstitial INTEGER NOT NULL DEFAULT 0,
+//!     redirect_type INTEGER NOT NULL DEFAULT 302,
+//!     password_hash TEXT
+//! );
+//!
+//! CREATE TABLE IF NOT EXISTS link_rules (
+//!     id INTEGER PRIMARY KEY AUTOINCREMENT,
+//!     link_id INTEGER NOT NULL REFERENCES links (id) ON DELETE CASCADE,
+//!     position INTEGER NOT NULL,
+//!     platform TEXT,
+//!     language TEXT,
+//!     country TEXT,
+//!     target_url TEXT NOT NULL
+//! );
+//!
+//! CREATE TABLE IF NOT EXISTS link_variants (
+//!     id INTEGER PRIMARY KEY AUTOINCREMENT,
+//!     link_id INTEGER NOT NULL REFERENCES links (id) ON DELETE CASCADE,
+//!     position INTEGER NOT NULL,
+//!     name TEXT NOT NULL,
+//!     target_url TEXT NOT NULL,
+//!     weight INTEGER NOT NULL,
+//!     UNIQUE (link_id, name)
+//! );
+//!
+//! CREATE TABLE IF NOT EXISTS api_keys (
+//!     id INTEGER PRIMARY KEY AUTOINCREMENT,
+//!     name TEXT NOT NULL,
+//!     key_hash TEXT NOT NULL UNIQUE,
+//!     created_at INTEGER NOT NULL,
+//!     revoked_at INTEGER
+//! );
+//!
+//! CREATE TABLE IF NOT EXISTS clicks (
+//!     id INTEGER PRIMARY KEY AUTOINCREMENT,
+//!     short_code TEXT NOT NULL,
+//!     clicked_at INTEGER NOT NULL,
+//!     referrer TEXT,
+//!     user_agent TEXT,
+//!     ip_hash TEXT,
+//!     variant TEXT
 //! );
 //! ```
 //!
@@ -22,35 +82,50 @@
 //!
 //! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
 //! // Connect to database
-//! let pool = db_connection("sqlite://urls.db").await?;
+//! let pool = db_connection("sqlite://urls.db", 10).await?;
 //!
 //! // Initialize schema
 //! init_db(&pool).await?;
 //!
 //! // Create a short link
-//! create_short_link(&pool, "abc123", "https://example.com").await?;
+//! let link = NewLink {
+//!     short_code: "abc123".to_string(),
+//!     long_url: "https://example.com".to_string(),
+//!     ..Default::default()
+//! };
+//! create_short_link(&pool, &link).await?;
 //!
-//! // Retrieve the long URL
-//! let url = get_long_url(&pool, "abc123").await?;
+//! // Resolve the link (fails with 410 Gone once expired)
+//! let link = get_active_link(&pool, "abc123").await?;
 //! # Ok(())
 //! # }
 //! ```
 
-use sqlx::{Error, SqlitePool, sqlite::SqliteQueryResult};
+use crate::{
+    error::AppError,
+    migrations,
+    models::{
+        ApiKey, ClickEvent, CreatedLink, DailyClicks, HealthCheck, Link, LinkHealth, LinkRule,
+        LinkStats, LinkUpdate, LinkVariant, NewLink, NewLinkRule, NewLinkVariant, Platform,
+        ReferrerCount, VariantClicks,
+    },
+};
+use sqlx::{
+    SqliteConnection, SqlitePool,
+    error::ErrorKind,
+    sqlite::{SqlitePoolOptions, SqliteQueryResult},
+};
 
-/// SQL query to create the links table if it doesn't exist.
-///
-/// The table schema includes:
-/// - `id`: Auto-incrementing primary key
-/// - `short_code`: Unique 7-character identifier for the short URL
-/// - `long_url`: The original long URL to redirect to
-const CREATE_TABLE_SQL: &str = "
-    CREATE TABLE IF NOT EXISTS links (
-        id INTEGER PRIMARY KEY AUTOINCREMENT,
-        short_code TEXT NOT NULL UNIQUE,
-        long_url TEXT NOT NULL
-    )
-";
+/// Column list shared by every query that loads a [`Link`].
+const LINK_SELECT: &str = "SELECT id, short_code, long_url, expires_at, max_clicks, click_count,
+    owner_id, created_at, interstitial, redirect_type, password_hash,
+    EXISTS (SELECT 1 FROM link_rules WHERE link_rules.link_id = links.id) AS has_rules,
+    EXISTS (SELECT 1 FROM link_variants WHERE link_variants.link_id = links.id) AS has_variants,
+    health, health_checked_at, health_detail
+    FROM links";
+
+/// Number of referrers returned by [`get_link_stats`].
+pub const TOP_REFERRERS_LIMIT: i64 = 10;
 
 /// Establishes a connection to the SQLite database.
 ///
@@ -60,17 +135,18 @@ const CREATE_TABLE_SQL: &str = "
 /// # Arguments
 ///
 /// * `db_url` - SQLite connection string (e.g., "sqlite://urls.db")
+/// * `max_connections` - Maximum number of pooled connections
 ///
 /// # Returns
 ///
-/// Returns a `SqlitePool` on success, or an `Error` if connection fails.
+/// Returns a `SqlitePool` on success, or an `AppError` if connection fails.
 ///
 /// # Examples
 ///
 /// ```no_run
 /// # use sqlx::SqlitePool;
-/// # async fn example() -> Result<(), sqlx::Error> {
-/// let pool = db_connection("sqlite://urls.db").await?;
+/// # async fn example() -> Result<(), AppError> {
+/// let pool = db_connection("sqlite://urls.db", 10).await?;
 /// # Ok(())
 /// # }
 /// ```
@@ -81,14 +157,17 @@ const CREATE_TABLE_SQL: &str = "
 /// - The database URL is invalid
 /// - Cannot create/open the database file
 /// - Insufficient permissions
-pub async fn db_connection(db_url: &str) -> Result<SqlitePool, Error> {
-    SqlitePool::connect(db_url).await
+pub async fn db_connection(db_url: &str, max_connections: u32) -> Result<SqlitePool, AppError> {
+    Ok(SqlitePoolOptions::new()
+        .max_connections(max_connections)
+        .connect(db_url)
+        .await?)
 }
 
 /// Initializes the database schema by creating necessary tables.
 ///
-/// Creates the `links` table if it doesn't already exist. This function
-/// is idempotent and safe to call multiple times.
+/// Applies every pending schema migration (see [`migrations::run`]).
+/// This function is idempotent and safe to call multiple times.
 ///
 /// # Arguments
 ///
@@ -96,13 +175,13 @@ pub async fn db_connection(db_url: &str) -> Result<SqlitePool, Error> {
 ///
 /// # Returns
 ///
-/// Returns a `SqliteQueryResult` on success, or an error if table creation fails.
+/// Returns `()` on success, or an error if a migration fails.
 ///
 /// # Examples
 ///
 /// ```no_run
 /// # use sqlx::SqlitePool;
-/// # async fn example(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+/// # async fn example(pool: &SqlitePool) -> Result<(), AppError> {
 /// init_db(pool).await?;
 /// # Ok(())
 /// # }
@@ -110,24 +189,104 @@ pub async fn db_connection(db_url: &str) -> Result<SqlitePool, Error> {
 ///
 /// # Errors
 ///
-/// Returns an error if the SQL execution fails due to:
+/// Returns an error if:
+/// - The database schema is newer than this binary ([`AppError::Internal`])
 /// - Database permission issues
 /// - Corrupted database file
-/// - Invalid SQL syntax (unlikely with const query)
-pub async fn init_db(pool: &SqlitePool) -> Result<SqliteQueryResult, sqlx::Error> {
-    sqlx::query(CREATE_TABLE_SQL).execute(pool).await
+/// - A migration fails to execute
+pub async fn init_db(pool: &SqlitePool) -> Result<(), AppError> {
+    migrations::run(pool).await?;
+    Ok(())
+}
+
+/// Checks that the database answers queries.
+///
+/// Used by the readiness check (`GET /`).
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+///
+/// # Errors
+///
+/// Returns an error if no connection can be acquired (e.g. the pool timed
+/// out) or the query fails.
+pub async fn ping(pool: &SqlitePool) -> Result<(), AppError> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(())
+}
+
+/// Rebuilds the database file to return the space of deleted rows to the
+/// file system, then refreshes the query planner statistics.
+///
+/// `VACUUM` needs exclusive access while it runs, so writers wait for it.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+///
+/// # Errors
+///
+/// Returns an error if the database is busy or the statements fail.
+pub async fn vacuum(pool: &SqlitePool) -> Result<(), AppError> {
+    sqlx::query("VACUUM").execute(pool).await?;
+    sqlx::query("PRAGMA optimize").execute(pool).await?;
+    Ok(())
+}
+
+/// Reserves row ids for links that are inserted later with an explicit id.
+///
+/// `links.id` is an `AUTOINCREMENT` column, so SQLite keeps the highest id
+/// it ever handed out in `sqlite_sequence` and never goes below it. Raising
+/// that value reserves the ids in between: inserts without an id continue
+/// after them.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `count` - Number of ids to reserve
+///
+/// # Returns
+///
+/// Returns `count` consecutive ids in ascending order.
+///
+/// # Errors
+///
+/// Returns an error if either statement fails.
+pub async fn reserve_link_ids(pool: &SqlitePool, count: usize) -> Result<Vec<i64>, AppError> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let count = i64::try_from(count)
+        .map_err(|_| AppError::Internal(format!("cannot reserve {count} link ids")))?;
+
+    // The row only appears with the first insert into `links`.
+    sqlx::query(
+        "INSERT INTO sqlite_sequence (name, seq)
+         SELECT 'links', COALESCE(MAX(id), 0) FROM links
+         WHERE NOT EXISTS (SELECT 1 FROM sqlite_sequence WHERE name = 'links')",
+    )
+    .execute(pool)
+    .await?;
+    let last: i64 = sqlx::query_scalar(
+        "UPDATE sqlite_sequence SET seq = seq + ? WHERE name = 'links' RETURNING seq",
+    )
+    .bind(count)
+    .fetch_one(pool)
+    .await?;
+    Ok((last - count + 1..=last).collect())
 }
 
 /// Creates a new short link entry in the database.
 ///
-/// Inserts a mapping between a short code and a long URL into the links table.
+/// Inserts a mapping between a short code and a long URL into the links table,
+/// together with the link's optional expiry limits and deduplication key.
 /// The short code must be unique - attempting to insert a duplicate will result in an error.
 ///
 /// # Arguments
 ///
 /// * `pool` - Reference to the SQLite connection pool
-/// * `short_code` - The unique 7-character identifier (typically generated by nanoid)
-/// * `long_url` - The original long URL to be shortened
+/// * `link` - The link to insert (short code, long URL and optional limits)
 ///
 /// # Returns
 ///
@@ -138,8 +297,14 @@ pub async fn init_db(pool: &SqlitePool) -> Result<SqliteQueryResult, sqlx::Error
 ///
 /// ```no_run
 /// # use sqlx::SqlitePool;
-/// # async fn example(pool: &SqlitePool) -> Result<(), sqlx::Error> {
-/// create_short_link(pool, "kN3pL4m", "https://www.rust-lang.org/").await?;
+/// # async fn example(pool: &SqlitePool) -> Result<(), AppError> {
+/// let link = NewLink {
+///     short_code: "kN3pL4m".to_string(),
+///     long_url: "https://www.rust-lang.org/".to_string(),
+///     max_clicks: Some(100),
+///     ..Default::default()
+/// };
+/// create_short_link(pool, &link).await?;
 /// # Ok(())
 /// # }
 /// ```
@@ -147,25 +312,380 @@ pub async fn init_db(pool: &SqlitePool) -> Result<SqliteQueryResult, sqlx::Error
 /// # Errors
 ///
 /// Returns an error if:
-/// - The short code already exists (UNIQUE constraint violation)
+/// - The short code already exists ([`AppError::ShortCodeTaken`])
+/// - The deduplication key already exists ([`AppError::Conflict`])
 /// - Database connection is lost
 /// - Invalid SQL parameter binding
 pub async fn create_short_link(
     pool: &SqlitePool,
-    short_code: &str,
-    long_url: &str,
-) -> Result<SqliteQueryResult, Error> {
-    sqlx::query("INSERT INTO links (short_code, long_url) VALUES (?, ?)")
+    link: &NewLink,
+) -> Result<SqliteQueryResult, AppError> {
+    let mut conn = pool.acquire().await?;
+    insert_link(&mut conn, link).await
+}
+
+/// Inserts a batch of links in a single transaction.
+///
+/// Each row succeeds or fails on its own: a taken short code only fails that
+/// row, the others are still inserted. Rows with a `dedup_key` reuse a live
+/// link that already owns the key, like a single `dedup` request does.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `links` - The validated links to insert
+///
+/// # Returns
+///
+/// Returns one result per input link, in the same order: the short code and
+/// whether it was newly created, or the error for that row.
+///
+/// # Errors
+///
+/// Returns an error (and inserts nothing) if the transaction cannot be
+/// started or committed.
+pub async fn create_short_links(
+    pool: &SqlitePool,
+    links: &[NewLink],
+) -> Result<Vec<Result<CreatedLink, AppError>>, AppError> {
+    let now = chrono::Utc::now().timestamp();
+    let mut tx = pool.begin().await?;
+    let mut results = Vec::with_capacity(links.len());
+
+    for link in links {
+        if let Some(dedup_key) = &link.dedup_key {
+            let existing = sqlx::query_as::<_, Link>(&format!("{LINK_SELECT} WHERE dedup_key = ?"))
+                .bind(dedup_key)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+            match existing {
+                Some(existing) if existing.is_expired(now) => {
+                    sqlx::query("UPDATE links SET dedup_key = NULL WHERE id = ?")
+                        .bind(existing.id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                Some(existing) => {
+                    results.push(Ok(CreatedLink {
+                        short_code: existing.short_code,
+                        created: false,
+                    }));
+                    continue;
+                }
+                None => {}
+            }
+        }
+
+        // A failed INSERT only rolls back its own statement, not the transaction.
+        let result = insert_link(&mut tx, link).await.map(|_| CreatedLink {
+            short_code: link.short_code.clone(),
+            created: true,
+        });
+        results.push(result);
+    }
+
+    tx.commit().await?;
+    Ok(results)
+}
+
+/// Inserts a single link on a connection (pooled or inside a transaction).
+///
+/// SQLite does not report which UNIQUE index a violation hit, so on a clash
+/// the same connection checks whether the deduplication key is taken; if it
+/// is not, the short code was.
+async fn insert_link(
+    conn: &mut SqliteConnection,
+    link: &NewLink,
+) -> Result<SqliteQueryResult, AppError> {
+    let result = sqlx::query(
+        "INSERT INTO links
+            (id, short_code, long_url, expires_at, max_clicks, dedup_key, owner_id, created_at,
+             interstitial, redirect_type, password_hash)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(link.id)
+    .bind(&link.short_code)
+    .bind(&link.long_url)
+    .bind(link.expires_at)
+    .bind(link.max_clicks)
+    .bind(&link.dedup_key)
+    .bind(link.owner_id)
+    .bind(chrono::Utc::now().timestamp())
+    .bind(link.interstitial)
+    .bind(i32::from(link.redirect_type))
+    .bind(&link.password_hash)
+    .execute(&mut *conn)
+    .await;
+
+    let err = match result {
+        Ok(result) => return Ok(result),
+        Err(err) => err,
+    };
+    let unique_violation = err
+        .as_database_error()
+        .is_some_and(|db_err| db_err.kind() == ErrorKind::UniqueViolation);
+    if !unique_violation {
+        return Err(err.into());
+    }
+
+    let dedup_clash = match &link.dedup_key {
+        Some(dedup_key) => {
+            sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM links WHERE dedup_key = ?)")
+                .bind(dedup_key)
+                .fetch_one(&mut *conn)
+                .await?
+        }
+        None => false,
+    };
+    if dedup_clash {
+        Err(AppError::Conflict(format!(
+            "url '{}' is already shortened",
+            link.long_url
+        )))
+    } else {
+        Err(AppError::ShortCodeTaken(link.short_code.clone()))
+    }
+}
+
+/// Looks up the deduplicated link that owns a deduplication key.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `dedup_key` - Key derived from the normalized long URL
+///
+/// # Returns
+///
+/// Returns `Some(link)` if a deduplicated link exists for the key (it may have
+/// expired), `None` otherwise.
+///
+/// # Errors
+///
+/// Returns an error if the query fails.
+pub async fn find_by_dedup_key(
+    pool: &SqlitePool,
+    dedup_key: &str,
+) -> Result<Option<Link>, AppError> {
+    Ok(
+        sqlx::query_as::<_, Link>(&format!("{LINK_SELECT} WHERE dedup_key = ?"))
+            .bind(dedup_key)
+            .fetch_optional(pool)
+            .await?,
+    )
+}
+
+/// Clears the deduplication key of a link.
+///
+/// Used when the link that owns a key has expired, so a fresh link can take
+/// the key over without waiting for the purge task.
+///
+/// # Errors
+///
+/// Returns an error if the update fails.
+pub async fn release_dedup_key(pool: &SqlitePool, short_code: &str) -> Result<(), AppError> {
+    sqlx::query("UPDATE links SET dedup_key = NULL WHERE short_code = ?")
         .bind(short_code)
-        .bind(long_url)
         .execute(pool)
-        .await
+        .await?;
+    Ok(())
+}
+
+/// Retrieves the full link record for a short code.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `short_code` - The identifier to look up
+///
+/// # Returns
+///
+/// Returns the [`Link`] row, including its lifecycle columns.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - No entry exists with the given short code ([`AppError::NotFound`])
+/// - Database connection is lost
+pub async fn get_link(pool: &SqlitePool, short_code: &str) -> Result<Link, AppError> {
+    sqlx::query_as::<_, Link>(&format!("{LINK_SELECT} WHERE short_code = ?"))
+        .bind(short_code)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("short code '{short_code}' not found")))
+}
+
+/// Lists the links of an owner, one page at a time.
+///
+/// Uses keyset pagination on `id`: each page returns links with an id greater
+/// than `cursor`, so pages stay stable while new links are added.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `owner_id` - Only links created by this API key are returned; `None`
+///   lists every link
+/// * `cursor` - Id of the last link of the previous page (`None` for the first page)
+/// * `limit` - Maximum number of links to return
+/// * `search` - Optional text that must appear in the short code or long URL
+///
+/// # Returns
+///
+/// Returns up to `limit` links ordered by id.
+///
+/// # Errors
+///
+/// Returns an error if the query fails.
+pub async fn list_links(
+    pool: &SqlitePool,
+    owner_id: Option<i64>,
+    cursor: Option<i64>,
+    limit: i64,
+    search: Option<&str>,
+    health: Option<LinkHealth>,
+) -> Result<Vec<Link>, AppError> {
+    let pattern = search.map(|search| format!("%{}%", escape_like(search)));
+    let health = health.map(LinkHealth::as_str);
+
+    Ok(sqlx::query_as::<_, Link>(&format!(
+        "{LINK_SELECT}
+         WHERE (? IS NULL OR owner_id = ?) AND id > ?
+           AND (? IS NULL OR short_code LIKE ? ESCAPE '\\' OR long_url LIKE ? ESCAPE '\\')
+           AND (? IS NULL OR health = ?)
+         ORDER BY id LIMIT ?"
+    ))
+    .bind(owner_id)
+    .bind(owner_id)
+    .bind(cursor.unwrap_or(0))
+    .bind(&pattern)
+    .bind(&pattern)
+    .bind(&pattern)
+    .bind(health)
+    .bind(health)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Escapes the `LIKE` wildcards `%` and `_` (and the escape character itself).
+pub fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Applies a partial update to a link.
+///
+/// Only the columns set in `update` are changed. A new `long_url` resets the
+/// link's health to `unchecked` until the next probe.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `short_code` - The link to update
+/// * `update` - The validated changes
+///
+/// # Returns
+///
+/// Returns the updated [`Link`].
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - No entry exists with the given short code ([`AppError::NotFound`])
+/// - The new URL clashes with another deduplicated link ([`AppError::Conflict`])
+/// - The update fails
+pub async fn update_link(
+    pool: &SqlitePool,
+    short_code: &str,
+    update: &LinkUpdate,
+) -> Result<Link, AppError> {
+    let result = sqlx::query(
+        "UPDATE links SET
+            long_url = CASE WHEN ? THEN ? ELSE long_url END,
+            dedup_key = CASE WHEN ? AND dedup_key IS NOT NULL THEN ? ELSE dedup_key END,
+            expires_at = CASE WHEN ? THEN ? ELSE expires_at END,
+            max_clicks = CASE WHEN ? THEN ? ELSE max_clicks END,
+            interstitial = COALESCE(?, interstitial),
+            redirect_type = COALESCE(?, redirect_type),
+            password_hash = CASE WHEN ? THEN ? ELSE password_hash END,
+            health = CASE WHEN long_url <> ? THEN 'unchecked' ELSE health END,
+            health_checked_at = CASE WHEN long_url <> ? THEN NULL ELSE health_checked_at END,
+            health_detail = CASE WHEN long_url <> ? THEN NULL ELSE health_detail END
+         WHERE short_code = ?",
+    )
+    .bind(update.long_url.is_some())
+    .bind(&update.long_url)
+    .bind(update.dedup_key.is_some())
+    .bind(&update.dedup_key)
+    .bind(update.expires_at.is_some())
+    .bind(update.expires_at.flatten())
+    .bind(update.max_clicks.is_some())
+    .bind(update.max_clicks.flatten())
+    .bind(update.interstitial)
+    .bind(update.redirect_type.map(i32::from))
+    .bind(update.password_hash.is_some())
+    .bind(update.password_hash.as_ref().and_then(Option::as_deref))
+    .bind(&update.long_url)
+    .bind(&update.long_url)
+    .bind(&update.long_url)
+    .bind(short_code)
+    .execute(pool)
+    .await
+    .map_err(|err| match AppError::from(err) {
+        AppError::Conflict(_) => AppError::Conflict("url is already shortened".to_string()),
+        other => other,
+    })?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "short code '{short_code}' not found"
+        )));
+    }
+
+    get_link(pool, short_code).await
 }
 
-/// Retrieves the long URL associated with a short code.
+/// Deletes a link together with its recorded clicks.
 ///
-/// Queries the database for the original long URL mapped to the given short code.
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `short_code` - The link to delete
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - No entry exists with the given short code ([`AppError::NotFound`])
+/// - The delete fails; nothing is deleted in that case
+pub async fn delete_link(pool: &SqlitePool, short_code: &str) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query("DELETE FROM links WHERE short_code = ?")
+        .bind(short_code)
+        .execute(&mut *tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "short code '{short_code}' not found"
+        )));
+    }
+
+    sqlx::query("DELETE FROM clicks WHERE short_code = ?")
+        .bind(short_code)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Retrieves a link that can still be followed.
+///
+/// Queries the database for the link mapped to the given short code.
 /// This is used to redirect users from short URLs to their destinations.
+/// Links that have passed their `expires_at` or used up their `max_clicks`
+/// are reported as gone rather than returned.
 ///
 /// # Arguments
 ///
@@ -174,15 +694,15 @@ pub async fn create_short_link(
 ///
 /// # Returns
 ///
-/// Returns the long URL as a `String` on success, or an error if not found.
+/// Returns the active [`Link`] on success, or an error if not found or expired.
 ///
 /// # Examples
 ///
 /// ```no_run
 /// # use sqlx::SqlitePool;
-/// # async fn example(pool: &SqlitePool) -> Result<(), sqlx::Error> {
-/// let long_url = get_long_url(pool, "kN3pL4m").await?;
-/// println!("Redirecting to: {}", long_url);
+/// # async fn example(pool: &SqlitePool) -> Result<(), AppError> {
+/// let link = get_active_link(pool, "kN3pL4m").await?;
+/// println!("Redirecting to: {}", link.long_url);
 /// # Ok(())
 /// # }
 /// ```
@@ -190,12 +710,742 @@ pub async fn create_short_link(
 /// # Errors
 ///
 /// Returns an error if:
-/// - No entry exists with the given short code (RowNotFound)
+/// - No entry exists with the given short code ([`AppError::NotFound`])
+/// - The link has expired or reached its click limit ([`AppError::Gone`])
 /// - Database connection is lost
 /// - Query execution fails
-pub async fn get_long_url(pool: &SqlitePool, short_code: &str) -> Result<String, Error> {
-    sqlx::query_scalar("SELECT long_url FROM links WHERE short_code = ?")
-        .bind(short_code)
-        .fetch_one(pool)
+pub async fn get_active_link(pool: &SqlitePool, short_code: &str) -> Result<Link, AppError> {
+    let link = get_link(pool, short_code).await?;
+
+    if link.is_expired(chrono::Utc::now().timestamp()) {
+        return Err(AppError::Gone(format!(
+            "short code '{short_code}' has expired"
+        )));
+    }
+
+    Ok(link)
+}
+
+/// Counts one redirect against a link's click limit.
+///
+/// The increment is conditional on the link still being under its
+/// `max_clicks` limit, so concurrent redirects can never overshoot it.
+/// Only links with a click limit need this synchronous update; all other
+/// counters are incremented in batches by [`insert_clicks`].
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `short_code` - The identifier of the link being followed
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - The link reached its click limit in the meantime ([`AppError::Gone`])
+/// - Database connection is lost
+pub async fn record_click(pool: &SqlitePool, short_code: &str) -> Result<(), AppError> {
+    let result = sqlx::query(
+        "UPDATE links SET click_count = click_count + 1
+         WHERE short_code = ? AND (max_clicks IS NULL OR click_count < max_clicks)",
+    )
+    .bind(short_code)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::Gone(format!(
+            "short code '{short_code}' has expired"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Deletes every link that has expired or used up its click limit, together
+/// with its clicks.
+///
+/// Called periodically by the purge task in [`crate::tasks`]. Clicks go with
+/// the link, like in [`delete_link`], so a link that later takes over the
+/// short code does not inherit its stats.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `now` - Current Unix timestamp in seconds
+///
+/// # Returns
+///
+/// Returns the number of deleted links.
+///
+/// # Errors
+///
+/// Returns an error if the delete statement fails.
+pub async fn purge_expired_links(pool: &SqlitePool, now: i64) -> Result<u64, AppError> {
+    const EXPIRED: &str = "(expires_at IS NOT NULL AND expires_at <= ?)
+         OR (max_clicks IS NOT NULL AND click_count >= max_clicks)";
+
+    // SQLite serializes writers, so both statements see the same links.
+    let mut tx = pool.begin().await?;
+    sqlx::query(&format!(
+        "DELETE FROM clicks
+         WHERE short_code IN (SELECT short_code FROM links WHERE {EXPIRED})"
+    ))
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+    let result = sqlx::query(&format!("DELETE FROM links WHERE {EXPIRED}"))
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(result.rows_affected())
+}
+
+/// Loads the links whose destination is due for a health probe.
+///
+/// Links that were never probed come first, then those with the oldest probe.
+/// Called periodically by the probe task in [`crate::tasks`].
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `checked_before` - Unix timestamp; links probed at or after it are skipped
+/// * `limit` - Maximum number of links to return
+///
+/// # Errors
+///
+/// Returns an error if the query fails.
+pub async fn links_due_for_probe(
+    pool: &SqlitePool,
+    checked_before: i64,
+    limit: i64,
+) -> Result<Vec<Link>, AppError> {
+    Ok(sqlx::query_as::<_, Link>(&format!(
+        "{LINK_SELECT}
+         WHERE health_checked_at IS NULL OR health_checked_at < ?
+         ORDER BY health_checked_at IS NOT NULL, health_checked_at, id LIMIT ?"
+    ))
+    .bind(checked_before)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Stores the result of a health probe.
+///
+/// Nothing is written if the link was deleted or its `long_url` changed
+/// while the probe ran, so a stale result never sticks to a new URL.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `link_id` - Row id of the probed link
+/// * `long_url` - The URL that was probed
+/// * `check` - The probe's result
+/// * `checked_at` - Unix timestamp (seconds) of the probe
+///
+/// # Errors
+///
+/// Returns an error if the update fails.
+pub async fn set_link_health(
+    pool: &SqlitePool,
+    link_id: i64,
+    long_url: &str,
+    check: &HealthCheck,
+    checked_at: i64,
+) -> Result<(), AppError> {
+    sqlx::query(
+        "UPDATE links SET health = ?, health_checked_at = ?, health_detail = ?
+         WHERE id = ? AND long_url = ?",
+    )
+    .bind(check.health.as_str())
+    .bind(checked_at)
+    .bind(&check.detail)
+    .bind(link_id)
+    .bind(long_url)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Writes a batch of click events in a single transaction.
+///
+/// Inserts one `clicks` row per event and increments `links.click_count` for
+/// events that were not already counted by [`record_click`].
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `events` - The buffered click events
+///
+/// # Errors
+///
+/// Returns an error if any statement fails; the whole batch is rolled back.
+pub async fn insert_clicks(pool: &SqlitePool, events: &[ClickEvent]) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+
+    for event in events {
+        sqlx::query(
+            "INSERT INTO clicks (short_code, clicked_at, referrer, user_agent, ip_hash, variant)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&event.short_code)
+        .bind(event.clicked_at)
+        .bind(&event.referrer)
+        .bind(&event.user_agent)
+        .bind(&event.ip_hash)
+        .bind(&event.variant)
+        .execute(&mut *tx)
+        .await?;
+
+        if !event.counted {
+            sqlx::query("UPDATE links SET click_count = click_count + 1 WHERE short_code = ?")
+                .bind(&event.short_code)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Aggregates the recorded clicks of a link.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `short_code` - The link to report on
+///
+/// # Returns
+///
+/// Returns a [`LinkStats`] with total clicks, unique visitors (distinct IP
+/// hashes), a per-day histogram, the top referrers and the clicks per A/B
+/// variant.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - No link exists with the given short code ([`AppError::NotFound`])
+/// - Any aggregate query fails
+pub async fn get_link_stats(pool: &SqlitePool, short_code: &str) -> Result<LinkStats, AppError> {
+    get_link(pool, short_code).await?;
+
+    let (total_clicks, unique_visitors): (i64, i64) =
+        sqlx::query_as("SELECT COUNT(*), COUNT(DISTINCT ip_hash) FROM clicks WHERE short_code = ?")
+            .bind(short_code)
+            .fetch_one(pool)
+            .await?;
+
+    let daily = sqlx::query_as::<_, DailyClicks>(
+        "SELECT date(clicked_at, 'unixepoch') AS date, COUNT(*) AS clicks
+         FROM clicks WHERE short_code = ?
+         GROUP BY date ORDER BY date",
+    )
+    .bind(short_code)
+    .fetch_all(pool)
+    .await?;
+
+    let top_referrers = sqlx::query_as::<_, ReferrerCount>(
+        "SELECT referrer, COUNT(*) AS clicks
+         FROM clicks WHERE short_code = ? AND referrer IS NOT NULL
+         GROUP BY referrer ORDER BY clicks DESC, referrer LIMIT ?",
+    )
+    .bind(short_code)
+    .bind(TOP_REFERRERS_LIMIT)
+    .fetch_all(pool)
+    .await?;
+
+    let variants = sqlx::query_as::<_, VariantClicks>(
+        "SELECT variant, COUNT(*) AS clicks
+         FROM clicks WHERE short_code = ? AND variant IS NOT NULL
+         GROUP BY variant ORDER BY variant",
+    )
+    .bind(short_code)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(LinkStats {
+        short_code: short_code.to_string(),
+        total_clicks,
+        unique_visitors,
+        daily,
+        top_referrers,
+        variants,
+    })
+}
+
+/// Loads the redirect rules of a link in evaluation order.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `link_id` - Row id of the link
+///
+/// # Returns
+///
+/// Returns the rules ordered by `position`; empty if the link has none.
+pub async fn get_link_rules(pool: &SqlitePool, link_id: i64) -> Result<Vec<LinkRule>, AppError> {
+    Ok(sqlx::query_as::<_, LinkRule>(
+        "SELECT platform, language, country, target_url
+         FROM link_rules WHERE link_id = ? ORDER BY position",
+    )
+    .bind(link_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Replaces every redirect rule of a link in a single transaction.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `link_id` - Row id of the link
+/// * `rules` - The new rules in evaluation order; empty removes all rules
+///
+/// # Returns
+///
+/// Returns the stored rules, as [`get_link_rules`] would.
+///
+/// # Errors
+///
+/// Returns an error if a statement fails; the old rules are kept in that case.
+pub async fn replace_link_rules(
+    pool: &SqlitePool,
+    link_id: i64,
+    rules: &[NewLinkRule],
+) -> Result<Vec<LinkRule>, AppError> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM link_rules WHERE link_id = ?")
+        .bind(link_id)
+        .execute(&mut *tx)
+        .await?;
+    for (position, rule) in rules.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO link_rules (link_id, position, platform, language, country, target_url)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(link_id)
+        .bind(position as i64)
+        .bind(rule.platform.map(Platform::as_str))
+        .bind(&rule.language)
+        .bind(&rule.country)
+        .bind(&rule.target_url)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    get_link_rules(pool, link_id).await
+}
+
+/// Loads the A/B variants of a link in their stored order.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `link_id` - Row id of the link
+pub async fn get_link_variants(
+    pool: &SqlitePool,
+    link_id: i64,
+) -> Result<Vec<LinkVariant>, AppError> {
+    Ok(sqlx::query_as::<_, LinkVariant>(
+        "SELECT name, target_url, weight
+         FROM link_variants WHERE link_id = ? ORDER BY position",
+    )
+    .bind(link_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Replaces every A/B variant of a link in a single transaction.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `link_id` - Row id of the link
+/// * `variants` - The new variants; empty removes all variants
+///
+/// # Returns
+///
+/// Returns the stored variants, as [`get_link_variants`] would.
+///
+/// # Errors
+///
+/// Returns an error if a statement fails (e.g. two variants share a name);
+/// the old variants are kept in that case.
+pub async fn replace_link_variants(
+    pool: &SqlitePool,
+    link_id: i64,
+    variants: &[NewLinkVariant],
+) -> Result<Vec<LinkVariant>, AppError> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM link_variants WHERE link_id = ?")
+        .bind(link_id)
+        .execute(&mut *tx)
+        .await?;
+    for (position, variant) in variants.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO link_variants (link_id, position, name, target_url, weight)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(link_id)
+        .bind(position as i64)
+        .bind(&variant.name)
+        .bind(&variant.target_url)
+        .bind(variant.weight)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    get_link_variants(pool, link_id).await
+}
+
+/// Stores a new API key.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+/// * `name` - Human readable label for the key
+/// * `key_hash` - SHA-256 hash of the plain key (see [`crate::auth::hash_key`])
+///
+/// # Returns
+///
+/// Returns the stored [`ApiKey`].
+///
+/// # Errors
+///
+/// Returns an error if the insert fails.
+pub async fn create_api_key(
+    pool: &SqlitePool,
+    name: &str,
+    key_hash: &str,
+) -> Result<ApiKey, AppError> {
+    Ok(sqlx::query_as::<_, ApiKey>(
+        "INSERT INTO api_keys (name, key_hash, created_at) VALUES (?, ?, ?)
+         RETURNING id, name, created_at, revoked_at",
+    )
+    .bind(name)
+    .bind(key_hash)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_one(pool)
+    .await?)
+}
+
+/// Looks up an unrevoked API key by its hash.
+///
+/// # Returns
+///
+/// Returns `Some(key)` if the hash belongs to an active key, `None` otherwise.
+///
+/// # Errors
+///
+/// Returns an error if the query fails.
+pub async fn find_active_api_key(
+    pool: &SqlitePool,
+    key_hash: &str,
+) -> Result<Option<ApiKey>, AppError> {
+    Ok(sqlx::query_as::<_, ApiKey>(
+        "SELECT id, name, created_at, revoked_at FROM api_keys
+         WHERE key_hash = ? AND revoked_at IS NULL",
+    )
+    .bind(key_hash)
+    .fetch_optional(pool)
+    .await?)
+}
+
+/// Lists every API key, including revoked ones, oldest first.
+///
+/// # Errors
+///
+/// Returns an error if the query fails.
+pub async fn list_api_keys(pool: &SqlitePool) -> Result<Vec<ApiKey>, AppError> {
+    Ok(sqlx::query_as::<_, ApiKey>(
+        "SELECT id, name, created_at, revoked_at FROM api_keys ORDER BY id",
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Revokes an API key. Links created with it keep their owner.
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - No active key exists with the given id ([`AppError::NotFound`])
+/// - The update fails
+pub async fn revoke_api_key(pool: &SqlitePool, id: i64) -> Result<(), AppError> {
+    let result =
+        sqlx::query("UPDATE api_keys SET revoked_at = ? WHERE id = ? AND revoked_at IS NULL")
+            .bind(chrono::Utc::now().timestamp())
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "no active api key with id {id}"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn test_pool() -> SqlitePool {
+        // A single connection keeps every query on the same in-memory database.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        init_db(&pool).await.unwrap();
+        pool
+    }
+
+    fn new_link(short_code: &str, expires_at: Option<i64>, max_clicks: Option<i64>) -> NewLink {
+        NewLink {
+            short_code: short_code.to_string(),
+            long_url: "https://example.com".to_string(),
+            expires_at,
+            max_clicks,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn reserves_ids_before_the_first_link() {
+        let pool = test_pool().await;
+        assert_eq!(reserve_link_ids(&pool, 2).await.unwrap(), vec![1, 2]);
+
+        create_short_link(&pool, &new_link("third", None, None))
+            .await
+            .unwrap();
+        assert_eq!(get_link(&pool, "third").await.unwrap().id, 3);
+        assert_eq!(reserve_link_ids(&pool, 1).await.unwrap(), vec![4]);
+    }
+
+    #[tokio::test]
+    async fn dedup_key_is_unique_and_releasable() {
+        let pool = test_pool().await;
+        let key = Some("https://example.com".to_string());
+        create_short_link(
+            &pool,
+            &NewLink {
+                dedup_key: key.clone(),
+                ..new_link("first", None, None)
+            },
+        )
         .await
+        .unwrap();
+
+        let second = NewLink {
+            dedup_key: key.clone(),
+            ..new_link("second", None, None)
+        };
+        let err = create_short_link(&pool, &second).await.unwrap_err();
+        assert!(matches!(err, AppError::Conflict(_)));
+
+        let existing = find_by_dedup_key(&pool, "https://example.com")
+            .await
+            .unwrap();
+        assert_eq!(existing.unwrap().short_code, "first");
+
+        release_dedup_key(&pool, "first").await.unwrap();
+        create_short_link(&pool, &second).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn short_code_clash_with_free_dedup_key_is_code_taken() {
+        let pool = test_pool().await;
+        create_short_link(&pool, &new_link("taken", None, None))
+            .await
+            .unwrap();
+
+        let link = NewLink {
+            dedup_key: Some("https://example.com".to_string()),
+            ..new_link("taken", None, None)
+        };
+        let err = create_short_link(&pool, &link).await.unwrap_err();
+        assert!(matches!(err, AppError::ShortCodeTaken(code) if code == "taken"));
+
+        let results = create_short_links(&pool, std::slice::from_ref(&link))
+            .await
+            .unwrap();
+        let err = results.into_iter().next().unwrap().unwrap_err();
+        assert!(matches!(err, AppError::ShortCodeTaken(code) if code == "taken"));
+    }
+
+    #[tokio::test]
+    async fn duplicate_short_code_is_conflict() {
+        let pool = test_pool().await;
+        create_short_link(&pool, &new_link("dup", None, None))
+            .await
+            .unwrap();
+        let err = create_short_link(&pool, &new_link("dup", None, None))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::ShortCodeTaken(code) if code == "dup"));
+    }
+
+    #[tokio::test]
+    async fn expired_link_is_gone() {
+        let pool = test_pool().await;
+        let past = chrono::Utc::now().timestamp() - 10;
+        create_short_link(&pool, &new_link("old", Some(past), None))
+            .await
+            .unwrap();
+        let err = get_active_link(&pool, "old").await.unwrap_err();
+        assert!(matches!(err, AppError::Gone(_)));
+    }
+
+    #[tokio::test]
+    async fn click_limit_is_enforced() {
+        let pool = test_pool().await;
+        create_short_link(&pool, &new_link("once", None, Some(1)))
+            .await
+            .unwrap();
+
+        get_active_link(&pool, "once").await.unwrap();
+        record_click(&pool, "once").await.unwrap();
+
+        assert!(matches!(
+            record_click(&pool, "once").await,
+            Err(AppError::Gone(_))
+        ));
+        assert!(matches!(
+            get_active_link(&pool, "once").await,
+            Err(AppError::Gone(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn purge_removes_only_dead_links() {
+        let pool = test_pool().await;
+        let now = chrono::Utc::now().timestamp();
+        create_short_link(&pool, &new_link("live", Some(now + 60), None))
+            .await
+            .unwrap();
+        create_short_link(&pool, &new_link("dead", Some(now - 60), None))
+            .await
+            .unwrap();
+
+        assert_eq!(purge_expired_links(&pool, now).await.unwrap(), 1);
+        assert!(get_link(&pool, "live").await.is_ok());
+        assert!(matches!(
+            get_link(&pool, "dead").await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn revoked_keys_are_not_found() {
+        let pool = test_pool().await;
+        let key = create_api_key(&pool, "ci", "hash").await.unwrap();
+
+        assert!(find_active_api_key(&pool, "hash").await.unwrap().is_some());
+        revoke_api_key(&pool, key.id).await.unwrap();
+        assert!(find_active_api_key(&pool, "hash").await.unwrap().is_none());
+        assert!(matches!(
+            revoke_api_key(&pool, key.id).await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn list_paginates_per_owner_with_search() {
+        let pool = test_pool().await;
+        let owner = create_api_key(&pool, "owner", "h1").await.unwrap();
+        let other = create_api_key(&pool, "other", "h2").await.unwrap();
+        for (code, owner_id) in [("a_1", owner.id), ("b%2", owner.id), ("c3", other.id)] {
+            create_short_link(
+                &pool,
+                &NewLink {
+                    owner_id: Some(owner_id),
+                    ..new_link(code, None, None)
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        let first = list_links(&pool, Some(owner.id), None, 1, None, None)
+            .await
+            .unwrap();
+        assert_eq!(first.len(), 1);
+        let second = list_links(&pool, Some(owner.id), Some(first[0].id), 10, None, None)
+            .await
+            .unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].short_code, "b%2");
+
+        let found = list_links(&pool, Some(owner.id), None, 10, Some("%"), None)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].short_code, "b%2");
+    }
+
+    #[tokio::test]
+    async fn update_and_delete_link() {
+        let pool = test_pool().await;
+        create_short_link(&pool, &new_link("edit", Some(1), Some(5)))
+            .await
+            .unwrap();
+
+        let update = LinkUpdate {
+            long_url: Some("https://rust-lang.org/".to_string()),
+            expires_at: Some(None),
+            ..Default::default()
+        };
+        let link = update_link(&pool, "edit", &update).await.unwrap();
+        assert_eq!(link.long_url, "https://rust-lang.org/");
+        assert_eq!(link.expires_at, None);
+        assert_eq!(link.max_clicks, Some(5));
+
+        delete_link(&pool, "edit").await.unwrap();
+        assert!(matches!(
+            delete_link(&pool, "edit").await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn bulk_insert_reports_each_row() {
+        let pool = test_pool().await;
+        create_short_link(&pool, &new_link("taken", None, None))
+            .await
+            .unwrap();
+
+        let deduped = |code: &str| NewLink {
+            dedup_key: Some("1:https://www.rust-lang.org/".to_string()),
+            ..new_link(code, None, None)
+        };
+        let links = [
+            new_link("fresh", None, None),
+            new_link("taken", None, None),
+            deduped("first"),
+            deduped("second"),
+        ];
+        let results = create_short_links(&pool, &links).await.unwrap();
+
+        assert!(results[0].as_ref().is_ok_and(|r| r.created));
+        assert!(matches!(results[1], Err(AppError::ShortCodeTaken(_))));
+        assert!(results[2].as_ref().is_ok_and(|r| r.created));
+        assert!(
+            results[3]
+                .as_ref()
+                .is_ok_and(|r| !r.created && r.short_code == "first")
+        );
+        assert!(get_link(&pool, "fresh").await.is_ok());
+        assert!(get_link(&pool, "second").await.is_err());
+    }
 }
@@ -0,0 +1,211 @@
+//! Application error type and its HTTP mapping.
+//!
+//! Every database function and request handler returns [`AppError`] on failure.
+//! Axum converts it into a response through its [`IntoResponse`] implementation,
+//! so a missing short code or a failing database never panics the request task.
+//!
+//! # Status Code Mapping
+//!
+//! | Error | HTTP Status |
+//! |-------|-------------|
+//! | [`AppError::BadRequest`] | 400 Bad Request |
+//! | [`AppError::Unauthorized`] | 401 Unauthorized |
+//! | `sqlx::Error::RowNotFound` | 404 Not Found |
+//! | [`AppError::ShortCodeTaken`] | 409 Conflict |
+//! | UNIQUE constraint violation | 409 Conflict |
+//! | [`AppError::Gone`] | 410 Gone |
+//! | [`AppError::TooManyRequests`] | 429 Too Many Requests (with `Retry-After`) |
+//! | Any other database error | 500 Internal Server Error |
+//! | [`AppError::Internal`] | 500 Internal Server Error |
+//! | [`AppError::Unavailable`] | 503 Service Unavailable |
+//!
+//! # Response Format
+//!
+//! ```json
+//! {
+//!   "error": "short code not found"
+//! }
+//! ```
+
+use axum::{
+    Json,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::{fmt, time::Duration};
+
+/// Errors that can be returned by the database layer and the HTTP handlers.
+#[derive(Debug)]
+pub enum AppError {
+    /// The request payload failed validation.
+    BadRequest(String),
+    /// The request lacks a valid API key.
+    Unauthorized(String),
+    /// The requested resource does not exist.
+    NotFound(String),
+    /// The resource conflicts with an existing one (e.g. an already shortened URL).
+    Conflict(String),
+    /// The short code of a new link is already in use. Generated codes are
+    /// retried on this error (see [`crate::codes`]); any other conflict is final.
+    ShortCodeTaken(String),
+    /// The resource existed but is no longer available (e.g. an expired link).
+    Gone(String),
+    /// The client exceeded its rate limit and may retry after the given time.
+    TooManyRequests(Duration),
+    /// Any other database failure. The details are logged, not sent to the client.
+    Database(sqlx::Error),
+    /// Any other server-side failure. The details are logged, not sent to the client.
+    Internal(String),
+    /// A dependency (e.g. the database) cannot be reached right now.
+    Unavailable(String),
+}
+
+/// JSON body returned to the client when a request fails.
+#[derive(Serialize)]
+struct ErrorBody {
+    /// Human readable description of the failure.
+    error: String,
+}
+
+impl AppError {
+    /// Returns the HTTP status code that corresponds to this error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) | AppError::ShortCodeTaken(_) => StatusCode::CONFLICT,
+            AppError::Gone(_) => StatusCode::GONE,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::BadRequest(msg) => write!(f, "{msg}"),
+            AppError::Unauthorized(msg) => write!(f, "{msg}"),
+            AppError::NotFound(msg) => write!(f, "{msg}"),
+            AppError::Conflict(msg) => write!(f, "{msg}"),
+            AppError::ShortCodeTaken(code) => write!(f, "short code '{code}' already exists"),
+            AppError::Gone(msg) => write!(f, "{msg}"),
+            AppError::TooManyRequests(retry_after) => write!(
+                f,
+                "rate limit exceeded, retry in {} seconds",
+                retry_after_secs(*retry_after)
+            ),
+            AppError::Database(err) => write!(f, "database error: {err}"),
+            AppError::Internal(msg) => write!(f, "{msg}"),
+            AppError::Unavailable(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Database(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Converts SQLx errors into their matching application error.
+///
+/// - `RowNotFound` becomes [`AppError::NotFound`]
+/// - UNIQUE constraint violations become [`AppError::Conflict`]
+/// - Everything else is kept as [`AppError::Database`]
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::RowNotFound => AppError::NotFound("resource not found".to_string()),
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                AppError::Conflict("resource already exists".to_string())
+            }
+            _ => AppError::Database(err),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let message = match &self {
+            AppError::Database(err) => {
+                tracing::error!(error = %err, "database error");
+                "internal server error".to_string()
+            }
+            AppError::Internal(msg) => {
+                tracing::error!(error = %msg, "internal error");
+                "internal server error".to_string()
+            }
+            other => other.to_string(),
+        };
+
+        let mut response = (status, Json(ErrorBody { error: message })).into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        if let AppError::TooManyRequests(retry_after) = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after_secs(retry_after).into());
+        }
+        response
+    }
+}
+
+/// Rounds a wait time up to whole seconds (at least one), as `Retry-After`
+/// only takes integers.
+fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs_f64().ceil().max(1.0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn row_not_found_maps_to_404() {
+        let err = AppError::from(sqlx::Error::RowNotFound);
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn other_errors_map_to_500() {
+        let err = AppError::from(sqlx::Error::PoolTimedOut);
+        assert_eq!(err.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn taken_short_code_maps_to_409() {
+        let err = AppError::ShortCodeTaken("launch".to_string());
+        assert_eq!(err.status(), StatusCode::CONFLICT);
+        assert_eq!(err.to_string(), "short code 'launch' already exists");
+    }
+
+    #[tokio::test]
+    async fn unique_violation_maps_to_409() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::query("CREATE TABLE t (v TEXT UNIQUE)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO t (v) VALUES ('a')")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let err = sqlx::query("INSERT INTO t (v) VALUES ('a')")
+            .execute(&pool)
+            .await
+            .unwrap_err();
+
+        assert_eq!(AppError::from(err).status(), StatusCode::CONFLICT);
+    }
+}
@@ -0,0 +1,74 @@
+//! Country lookup of client IPs for redirect rules.
+//!
+//! Reads a local MaxMind database in the `.mmdb` format, e.g. the free
+//! GeoLite2 Country database or the DB-IP "IP to Country Lite" file. The path
+//! comes from `GEOIP_DATABASE` (see [`crate::config`]); without it every
+//! lookup returns `None` and rules that match on a country never apply.
+//!
+//! The file is read into memory once at startup.
+
+use crate::error::AppError;
+use maxminddb::{Reader, geoip2};
+use std::{net::IpAddr, path::Path};
+
+/// Country database, or a stand-in that knows no countries.
+pub struct GeoIp {
+    reader: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    /// Returns a lookup that never finds a country.
+    pub fn disabled() -> Self {
+        GeoIp { reader: None }
+    }
+
+    /// Opens a MaxMind database file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::Internal`] if the file cannot be read or is not a
+    /// valid `.mmdb` database.
+    pub fn open(path: &Path) -> Result<Self, AppError> {
+        let reader = Reader::open_readfile(path).map_err(|err| {
+            AppError::Internal(format!(
+                "cannot open GeoIP database {}: {err}",
+                path.display()
+            ))
+        })?;
+        Ok(GeoIp {
+            reader: Some(reader),
+        })
+    }
+
+    /// Looks up the ISO 3166-1 alpha-2 country code of `ip`, e.g. `DE`.
+    ///
+    /// Returns `None` without a database, for addresses the database does
+    /// not know (such as private networks) and for malformed records.
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        let record = self.reader.as_ref()?.lookup::<geoip2::Country>(ip).ok()?;
+        record
+            .country
+            .and_then(|country| country.iso_code)
+            .map(str::to_ascii_uppercase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_lookup_finds_nothing() {
+        let geoip = GeoIp::disabled();
+        assert_eq!(geoip.country("8.8.8.8".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_databases() {
+        let path = std::env::temp_dir().join(format!("not-a-geoip-{}.mmdb", std::process::id()));
+        std::fs::write(&path, b"not a database").unwrap();
+        let result = GeoIp::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(AppError::Internal(_))));
+    }
+}
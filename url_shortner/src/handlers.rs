@@ -1,76 +1,208 @@
 //! HTTP request handlers for the URL shortener service.
 //!
 //! This module contains all the route handlers that process incoming HTTP requests:
-//! - Root handler for health checks
+//! - Root handler for readiness checks
+//! - Metrics handler for Prometheus scrapes
 //! - Shorten handler for creating short URLs
-//! - Redirect handler for resolving short codes
+//! - Redirect handler for resolving short codes (or previewing them)
+//! - QR code handler for the short URL of a link
+//! - Stats handler for click analytics
+//! - Link management handlers (list, get, update, delete, redirect rules, A/B variants)
+//! - Bulk shorten and export handlers (JSON, CSV and JSON Lines)
+//! - Cache stats handler for the redirect cache counters
 //!
-//! All handlers use Axum's state management to access the shared database pool.
+//! All handlers use Axum's state management to access the shared [`AppState`].
+//! Handlers behind the [`require_api_key`](crate::auth::require_api_key) layer
+//! receive the caller's [`ApiKey`] as an `Extension` and only see links owned
+//! by that key.
+//! Fallible handlers return `Result<_, AppError>`, which Axum turns into a JSON
+//! error response with the matching status code.
 
 use crate::{
-    db,
-    models::{CreateLinkRequest, CreateLinkResponse},
+    blocklist::Blocklist,
+    codes::{self, CodeGenerator},
+    error::AppError,
+    metrics,
+    models::{
+        ApiKey, BulkLinkResult, BulkResponse, CacheStats, ClickEvent, CreateLinkRequest,
+        CreateLinkResponse, CsvLinkRow, ExportFormat, ExportQuery, Link, LinkPreview, LinkResponse,
+        LinkRulesRequest, LinkRulesResponse, LinkStats, LinkUpdate, LinkVariantsRequest,
+        LinkVariantsResponse, ListLinksQuery, ListLinksResponse, NewLink, QrQuery, RedirectQuery,
+        UnlockForm, UpdateLinkRequest,
+    },
+    password, preview, qr,
+    rules::{self, Visitor},
+    state::{self, AppState},
+    store::LinkStore,
+    validation, variants,
 };
 use axum::{
     Json,
-    extract::{Path, State},
-    response::Redirect,
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Extension, Form, Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{Html, IntoResponse, Response},
 };
-use nanoid::nanoid;
-use sqlx::SqlitePool;
+use futures_util::stream;
+use std::{net::SocketAddr, time::Duration};
+use url::Url;
 
-/// Root endpoint handler for health checks and service verification.
+/// Page size of `GET /api/links` when no `limit` is given.
+const DEFAULT_PAGE_SIZE: i64 = 50;
+
+/// Largest page size accepted by `GET /api/links`.
+const MAX_PAGE_SIZE: i64 = 200;
+
+/// Largest number of rows accepted by `POST /api/links/bulk`.
+pub const MAX_BULK_ROWS: usize = 10_000;
+
+/// Request body limit of `POST /api/links/bulk` in bytes (the default limit
+/// of 2 MiB is too small for large imports).
+pub const BULK_BODY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// Number of links read from the database per chunk of an export.
+pub(crate) const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// Longest time the readiness check waits for the database.
+const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Longest time clients may cache a permanent redirect (one day), so a
+/// changed target or a deleted link still reaches returning visitors.
+const PERMANENT_REDIRECT_MAX_AGE: i64 = 24 * 60 * 60;
+
+/// Header row of CSV exports, matching the fields of [`LinkResponse`].
+const EXPORT_CSV_HEADER: [&str; 12] = [
+    "short_code",
+    "long_url",
+    "created_at",
+    "expires_at",
+    "max_clicks",
+    "click_count",
+    "interstitial",
+    "redirect_type",
+    "password_protected",
+    "health",
+    "health_checked_at",
+    "health_detail",
+];
+
+/// Root endpoint handler for readiness checks.
 ///
-/// This is a simple endpoint that confirms the service is running and
-/// that the database connection is accessible.
+/// Pings the database (see [`LinkStore::ping`]),
+/// so load balancers and orchestrators only route traffic to instances that
+/// can serve it. The ping gives up after [`READINESS_TIMEOUT`].
 ///
 /// # Arguments
 ///
-/// * `State(_db_pool)` - The shared SQLite connection pool (unused but verified)
+/// * `State(state)` - The shared application state
 ///
 /// # Returns
 ///
-/// Returns a static string message indicating the server is operational.
+/// Returns `ok` when the database answers, or an [`AppError::Unavailable`].
 ///
 /// # Examples
 ///
 /// ```bash
 /// curl http://localhost:3000/
-/// # Response: "hello from the server"
+/// # Response: "ok"
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: The service is ready
+/// - **503 Service Unavailable**: The database cannot be reached or did not
+///   answer in time
+pub async fn root_handler(State(state): State<AppState>) -> Result<&'static str, AppError> {
+    match tokio::time::timeout(READINESS_TIMEOUT, state.store.ping()).await {
+        Ok(Ok(())) => Ok("ok"),
+        Ok(Err(err)) => {
+            tracing::error!(error = %err, "readiness check failed");
+            Err(AppError::Unavailable("database is unavailable".to_string()))
+        }
+        Err(_) => {
+            tracing::error!("readiness check timed out");
+            Err(AppError::Unavailable("database is unavailable".to_string()))
+        }
+    }
+}
+
+/// Handler for Prometheus scrapes.
+///
+/// Renders the request, redirect and database metrics together with the
+/// current cache and connection pool counters (see [`crate::metrics`]).
+/// Like the readiness check it is public and not rate limited; keep it off
+/// the public internet with the reverse proxy if the counters are private.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+///
+/// # Returns
+///
+/// Returns the metrics in the Prometheus text format.
+///
+/// # Examples
+///
+/// ```bash
+/// curl http://localhost:3000/metrics
+/// # # TYPE redirects_total counter
+/// # redirects_total{status="302"} 1027
 /// ```
-pub async fn root_handler(State(_db_pool): State<SqlitePool>) -> &'static str {
-    println!("Database pool recieved in the handlers succesfully!");
-    "hello from the server"
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: Metrics returned
+/// - **500 Internal Server Error**: The metrics could not be encoded
+pub async fn metrics_handler(State(state): State<AppState>) -> Result<Response, AppError> {
+    let body = state
+        .metrics
+        .render(&state.link_cache.stats(), state.store.pool_stats())?;
+    Ok(([(header::CONTENT_TYPE, metrics::CONTENT_TYPE)], body).into_response())
 }
 
 /// Handler for creating shortened URLs.
 ///
-/// Accepts a JSON payload containing a long URL, generates a unique 7-character
-/// short code using nanoid, stores the mapping in the database, and returns the
-/// short code to the client.
+/// Accepts a JSON payload containing a long URL, generates a random short code
+/// from the configured length and alphabet (or uses the requested `alias`),
+/// stores the mapping in the database, and returns the short code and the
+/// full short URL to the client.
 ///
 /// # Arguments
 ///
-/// * `State(db_pool)` - The shared SQLite connection pool
+/// * `State(state)` - The shared application state
+/// * `Extension(api_key)` - The authenticated caller, recorded as the link owner
 /// * `Json(payload)` - The request payload containing the URL to shorten
 ///
 /// # Returns
 ///
-/// Returns a JSON response containing the generated short code.
+/// Returns a JSON response containing the short code and short URL, or an
+/// [`AppError`] if the link could not be stored.
 ///
 /// # Request Format
 ///
 /// ```json
 /// {
-///   "url": "https://www.rust-lang.org/"
+///   "url": "https://www.rust-lang.org/",
+///   "alias": "rust"
 /// }
 /// ```
 ///
+/// `alias` is optional; when omitted a code is generated with the configured
+/// strategy (see [`crate::codes`]).
+/// `expires_at` (RFC 3339) and `max_clicks` are optional expiry limits.
+///
+/// With `"dedup": true` the handler first looks for another deduplicated link
+/// of the same API key with the same normalized URL and returns its code with
+/// `"created": false`.
+/// The existing link is returned as is; its expiry limits are not changed.
+///
 /// # Response Format
 ///
 /// ```json
 /// {
-///   "short_url": "kN3pL4m"
+///   "short_code": "kN3pL4m",
+///   "short_url": "http://localhost:3000/kN3pL4m",
+///   "created": true
 /// }
 /// ```
 ///
@@ -78,54 +210,241 @@ pub async fn root_handler(State(_db_pool): State<SqlitePool>) -> &'static str {
 ///
 /// ```bash
 /// curl -X POST http://localhost:3000/shorten \
+///   -H "Authorization: Bearer $API_KEY" \
 ///   -H "Content-Type: application/json" \
 ///   -d '{"url": "https://github.com/rust-lang/rust"}'
 /// ```
 ///
-/// # Panics
+/// # Errors
 ///
-/// Panics if:
-/// - Database insertion fails (e.g., connection lost)
-/// - Short code collision occurs (extremely unlikely with nanoid)
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+/// - **400 Bad Request**: The URL fails [`validation::normalize_url`] or is on
+///   the [`blocklist`](crate::blocklist), the alias fails
+///   [`validation::validate_alias`] or the expiry limits fail
+///   [`validation::validate_expiry`]
+/// - **409 Conflict**: The alias is already taken, a deduplicated URL already
+///   exists under a different alias, or every generated code was taken
+///   ([`codes::MAX_CODE_ATTEMPTS`] attempts)
+/// - **500 Internal Server Error**: Database insertion fails (e.g., connection
+///   lost) or the obfuscated code space is used up
 ///
 /// # Notes
 ///
-/// The nanoid library generates cryptographically strong random IDs with
-/// URL-safe characters. With 7 characters, the probability of collision
-/// is negligible for most use cases (~1% after 4 million IDs).
+/// A generated code that is already taken is replaced and the insert retried
+/// (see [`codes::create_link`]). With the default random strategy collisions
+/// are negligible for most use cases (~1% after 4 million codes); shorter
+/// codes or smaller alphabets (see [`crate::config`]) raise it.
 pub async fn shorten_handler(
-    State(db_pool): State<SqlitePool>,
+    State(state): State<AppState>,
+    Extension(api_key): Extension<ApiKey>,
     Json(payload): Json<CreateLinkRequest>,
-) -> Json<CreateLinkResponse> {
-    println!("Recieved Long Url: {}", payload.url);
+) -> Result<Json<CreateLinkResponse>, AppError> {
+    tracing::debug!(url = %payload.url, "shortening");
 
-    let short_code = nanoid!(7);
+    let mut link =
+        prepare_link(&state.base_url, &state.blocklist, Some(&api_key), &payload).await?;
+    if let Some(dedup_key) = &link.dedup_key
+        && let Some(existing) = find_reusable_link(&state, dedup_key).await?
+    {
+        return reuse_link(
+            &state.base_url,
+            existing.short_code,
+            payload.alias.as_deref(),
+        )
+        .map(Json);
+    }
 
-    db::create_short_link(&db_pool, &short_code, &payload.url)
-        .await
-        .expect("failed while saving the short code onto database!");
+    match codes::create_link(state.store.as_ref(), state.codes.as_ref(), &mut link).await {
+        Ok(_) => {}
+        // Another request inserted the same URL between our lookup and insert.
+        Err(AppError::Conflict(_)) if link.dedup_key.is_some() => {
+            let dedup_key = link.dedup_key.as_deref().unwrap_or_default();
+            if let Some(existing) = find_reusable_link(&state, dedup_key).await? {
+                return reuse_link(
+                    &state.base_url,
+                    existing.short_code,
+                    payload.alias.as_deref(),
+                )
+                .map(Json);
+            }
+            codes::create_link(state.store.as_ref(), state.codes.as_ref(), &mut link).await?;
+        }
+        Err(err) => return Err(err),
+    }
+
+    // The alias may belong to a purged link that is still cached.
+    if payload.alias.is_some() {
+        state.link_cache.invalidate(&link.short_code);
+    }
 
     let output = CreateLinkResponse {
-        short_url: short_code,
+        short_url: state.short_url(&link.short_code),
+        short_code: link.short_code,
+        created: true,
+    };
+
+    Ok(Json(output))
+}
+
+/// Validates a create request and turns it into the link to insert.
+///
+/// Shared by [`shorten_handler`], [`bulk_shorten_handler`] and the admin
+/// CLI, so all of them apply the same URL, blocklist, alias, expiry and
+/// password rules. A password is hashed here, which takes a noticeable
+/// moment per link.
+///
+/// Without an alias the short code is left empty; [`codes::create_link`] and
+/// [`codes::create_links`] generate it when the link is inserted.
+///
+/// Password-protected links cannot be deduplicated: the existing link that
+/// `dedup` returns would silently lack the password. Deduplication is scoped
+/// to the owner, so links without one cannot use it either.
+pub(crate) async fn prepare_link(
+    base_url: &Url,
+    blocklist: &Blocklist,
+    owner: Option<&ApiKey>,
+    payload: &CreateLinkRequest,
+) -> Result<NewLink, AppError> {
+    let long_url = validation::normalize_url(&payload.url, base_url)?;
+    blocklist.check(&long_url)?;
+    validation::validate_expiry(payload.expires_at, payload.max_clicks, chrono::Utc::now())?;
+
+    if let Some(alias) = &payload.alias {
+        validation::validate_alias(alias)?;
+    }
+
+    let dedup_key = match owner {
+        Some(owner) => payload.dedup.then(|| dedup_key(owner, &long_url)),
+        None if payload.dedup => {
+            return Err(AppError::BadRequest(
+                "dedup needs an owning API key".to_string(),
+            ));
+        }
+        None => None,
+    };
+
+    let password_hash = match &payload.password {
+        Some(_) if payload.dedup => {
+            return Err(AppError::BadRequest(
+                "password-protected links cannot use dedup".to_string(),
+            ));
+        }
+        Some(password) => {
+            validation::validate_password(password)?;
+            Some(password::hash(password.clone()).await?)
+        }
+        // Exports mark protected links but cannot contain their password.
+        None if payload.password_protected => {
+            return Err(AppError::BadRequest(
+                "password-protected links need a password".to_string(),
+            ));
+        }
+        None => None,
     };
 
-    Json(output)
+    Ok(NewLink {
+        id: None,
+        short_code: payload.alias.clone().unwrap_or_default(),
+        dedup_key,
+        long_url,
+        expires_at: payload.expires_at.map(|expires_at| expires_at.timestamp()),
+        max_clicks: payload.max_clicks,
+        owner_id: owner.map(|owner| owner.id),
+        interstitial: payload.interstitial,
+        redirect_type: payload.redirect_type,
+        password_hash,
+    })
+}
+
+/// Returns the live deduplicated link for `dedup_key`, if any.
+///
+/// An expired owner of the key gives it up so a fresh link can be created.
+async fn find_reusable_link(state: &AppState, dedup_key: &str) -> Result<Option<Link>, AppError> {
+    match state.store.find_by_dedup_key(dedup_key).await? {
+        Some(link) if link.is_expired(chrono::Utc::now().timestamp()) => {
+            state.store.release_dedup_key(&link.short_code).await?;
+            Ok(None)
+        }
+        found => Ok(found),
+    }
+}
+
+/// Builds the response for a reused link.
+///
+/// A request that asked for a specific alias conflicts with an existing link
+/// under a different code.
+fn reuse_link(
+    base_url: &Url,
+    short_code: String,
+    alias: Option<&str>,
+) -> Result<CreateLinkResponse, AppError> {
+    if alias.is_some_and(|alias| alias != short_code) {
+        return Err(AppError::Conflict(format!(
+            "url is already shortened as '{short_code}'"
+        )));
+    }
+
+    Ok(CreateLinkResponse {
+        short_url: state::short_url(base_url, &short_code),
+        short_code,
+        created: false,
+    })
 }
 
 /// Handler for redirecting short codes to their original URLs.
 ///
-/// Looks up the short code in the database and performs an HTTP 303 redirect
-/// to the original long URL. This is the core functionality that makes the
-/// URL shortener work.
+/// Looks up the short code in the database and redirects to the original
+/// long URL with the link's
+/// [`RedirectType`](crate::models::RedirectType): 302 unless the link asks
+/// for 301, 307 or 308. This is the core functionality that makes the URL
+/// shortener work.
+///
+/// Short URLs also accept `POST`, so links with a 307 or 308 redirect can
+/// forward form submissions with their method and body.
+///
+/// Links are looked up in the [`LinkCache`](crate::cache::LinkCache) first and
+/// only loaded from the database on a miss.
+///
+/// Each redirect is queued as a [`ClickEvent`] for the batched analytics
+/// writer, so the response never waits for the insert. Links with a
+/// `max_clicks` limit are still counted synchronously to enforce the limit.
+///
+/// # Previews
+///
+/// Instead of redirecting, the handler returns a [`LinkPreview`] when:
+/// - The path ends with `+` (`/kN3pL4m+`); aliases cannot contain `+`
+/// - The request sends `Accept: application/json` (the preview is JSON)
+/// - The link is `interstitial` and the request has no `?go` parameter
+///
+/// Without `Accept: application/json` the preview is the HTML page of
+/// [`preview::render_page`]. Previews do not count as clicks.
+///
+/// # Password-Protected Links
+///
+/// Links with a password never reveal their target here: browsers get the
+/// form of [`password::render_form`], which posts to [`unlock_handler`], and
+/// JSON clients get a 401.
+///
+/// Links with redirect rules send the visitor to the target of the first
+/// rule that matches (see [`crate::rules`]). Otherwise links with A/B
+/// variants send them to their variant (see [`crate::variants`]), and all
+/// other links to the original URL.
 ///
 /// # Arguments
 ///
-/// * `State(db_pool)` - The shared SQLite connection pool
+/// * `State(state)` - The shared application state
+/// * `ConnectInfo(addr)` - The client's socket address (hashed for analytics)
 /// * `Path(short_code)` - The short code extracted from the URL path
+/// * `Query(query)` - `go` skips the preview page of interstitial links
+/// * `headers` - Request headers (`Referer` and `User-Agent` are recorded,
+///   `Accept` selects the preview format, `User-Agent` and `Accept-Language`
+///   are matched against redirect rules, `Cookie` keeps the A/B variant)
 ///
 /// # Returns
 ///
-/// Returns a `Redirect` response (HTTP 303 See Other) to the original URL.
+/// Returns a redirect to the original URL with a `Cache-Control` header that
+/// fits the redirect type (see [`redirect_response`]), a preview, the
+/// password form, or an [`AppError`] if the short code cannot be resolved.
 ///
 /// # Examples
 ///
@@ -135,37 +454,1580 @@ pub async fn shorten_handler(
 ///
 /// # View redirect without following
 /// curl -I http://localhost:3000/kN3pL4m
-/// # HTTP/1.1 303 See Other
+/// # HTTP/1.1 302 Found
 /// # Location: https://www.rust-lang.org/
+/// # Cache-Control: no-store
+///
+/// # See where the link goes
+/// curl http://localhost:3000/kN3pL4m+ -H "Accept: application/json"
 /// ```
 ///
-/// # Panics
+/// # HTTP Status Codes
+///
+/// - **200 OK**: A preview (HTML or JSON), or the password form
+/// - **301, 302, 307 or 308**: Successful redirect to original URL
+/// - **401 Unauthorized**: JSON request for a password-protected link
+/// - **404 Not Found**: The short code does not exist
+/// - **410 Gone**: The link has expired or reached its click limit
+/// - **500 Internal Server Error**: Database query fails or the connection is lost
+pub async fn redirect_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(short_code): Path<String>,
+    Query(query): Query<RedirectQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let (short_code, preview_requested) = match short_code.strip_suffix('+') {
+        Some(short_code) => (short_code.to_string(), true),
+        None => (short_code, false),
+    };
+    let wants_json = header_value(&headers, header::ACCEPT)
+        .is_some_and(|accept| accept.contains("application/json"));
+
+    let link = resolve_link(&state, &short_code).await?;
+    if link.is_password_protected() {
+        if wants_json {
+            return Err(AppError::Unauthorized(format!(
+                "short code '{short_code}' is password protected"
+            )));
+        }
+        return Ok(password_form_response(
+            StatusCode::OK,
+            &state.short_url(&short_code),
+            false,
+        ));
+    }
+    if preview_requested || wants_json || (link.interstitial && query.go.is_none()) {
+        let short_url = state.short_url(&link.short_code);
+        let preview = LinkPreview::new(link, short_url);
+        let body = if wants_json {
+            Json(preview).into_response()
+        } else {
+            Html(preview::render_page(&preview)).into_response()
+        };
+        return Ok(([(header::VARY, "Accept")], body).into_response());
+    }
+
+    let target = redirect_target(&state, addr, &headers, &link).await?;
+    record_visit(&state, addr, &headers, &link, target.variant.clone()).await?;
+
+    tracing::debug!(short_code = %link.short_code, target = %target.url, "redirecting");
+    let response = redirect_response(&link, &target, chrono::Utc::now().timestamp());
+    state.metrics.count_redirect(response.status());
+    Ok(response)
+}
+
+/// Where a visitor is redirected, chosen by [`redirect_target`].
+struct RedirectTarget {
+    url: String,
+    /// Name of the A/B variant the visitor was assigned to, if any.
+    variant: Option<String>,
+    /// `Set-Cookie` value pinning a newly assigned variant.
+    set_cookie: Option<String>,
+}
+
+/// Chooses where a visitor of `link` is sent: the target of the first
+/// matching redirect rule, else the visitor's A/B variant, else the link's
+/// `long_url`.
+async fn redirect_target(
+    state: &AppState,
+    addr: SocketAddr,
+    headers: &HeaderMap,
+    link: &Link,
+) -> Result<RedirectTarget, AppError> {
+    let to = |url: &str| RedirectTarget {
+        url: url.to_string(),
+        variant: None,
+        set_cookie: None,
+    };
+
+    if link.has_rules {
+        let rules = state.store.get_link_rules(link.id).await?;
+        let visitor = Visitor::from_request(headers, addr.ip(), &state.geoip);
+        if let Some(url) = rules::select_target(&rules, &visitor) {
+            return Ok(to(url));
+        }
+    }
+
+    if link.has_variants {
+        let link_variants = state.store.get_link_variants(link.id).await?;
+        let assignment = variants::assign(
+            &link_variants,
+            &link.short_code,
+            headers,
+            &mut rand::thread_rng(),
+        );
+        if let Some(assignment) = assignment {
+            let name = &assignment.variant.name;
+            return Ok(RedirectTarget {
+                url: assignment.variant.target_url.clone(),
+                variant: Some(name.clone()),
+                set_cookie: assignment
+                    .is_new
+                    .then(|| variants::sticky_cookie(&link.short_code, name)),
+            });
+        }
+    }
+
+    Ok(to(&link.long_url))
+}
+
+/// Counts a visit that is about to be redirected.
+///
+/// Links with a `max_clicks` limit are counted synchronously, so the limit
+/// holds; the click itself is queued for the analytics writer. A link that
+/// turns out to be used up leaves the cache, whose `click_count` is stale.
+async fn record_visit(
+    state: &AppState,
+    addr: SocketAddr,
+    headers: &HeaderMap,
+    link: &Link,
+    variant: Option<String>,
+) -> Result<(), AppError> {
+    let counted = link.max_clicks.is_some();
+    if counted {
+        match state.store.record_click(&link.short_code).await {
+            Ok(()) => {}
+            Err(err @ AppError::Gone(_)) => {
+                state.link_cache.invalidate(&link.short_code);
+                return Err(err);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    state.clicks.record(ClickEvent {
+        short_code: link.short_code.clone(),
+        clicked_at: chrono::Utc::now().timestamp(),
+        referrer: header_value(headers, header::REFERER),
+        user_agent: header_value(headers, header::USER_AGENT),
+        ip_hash: Some(state.clicks.hash_ip(addr.ip())),
+        counted,
+        variant,
+    });
+    Ok(())
+}
+
+/// Handler for the password form of a protected link.
+///
+/// Checks the submitted password and, if it matches, counts the visit and
+/// redirects to the original URL. The redirect is always a 303 See Other,
+/// whatever the link's redirect type, so the browser follows it with a `GET`
+/// and the password is never forwarded to the target.
 ///
-/// Panics if:
-/// - The short code is not found in the database
-/// - Database query fails
-/// - Connection to database is lost
+/// Every attempt takes a token from the `password` rate limiter, keyed by
+/// short code and client IP; a correct password refills the bucket, so only
+/// failed attempts add up.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `ConnectInfo(addr)` - The client's socket address (rate limit key and analytics)
+/// * `Path(short_code)` - The short code extracted from the URL path
+/// * `headers` - Request headers (`Referer` and `User-Agent` are recorded)
+/// * `Form(form)` - The `application/x-www-form-urlencoded` form with `password`
+///
+/// # Examples
+///
+/// ```bash
+/// curl -i http://localhost:3000/kN3pL4m/unlock -d password=hunter22
+/// # HTTP/1.1 303 See Other
+/// # Location: https://intranet.example.com/handbook
+/// ```
 ///
 /// # HTTP Status Codes
 ///
-/// - **303 See Other**: Successful redirect to original URL
-/// - **500 Internal Server Error**: Database error or short code not found (via panic)
+/// - **303 See Other**: The password matches
+/// - **400 Bad Request**: The link has no password
+/// - **403 Forbidden**: Wrong password; the form is shown again
+/// - **404 Not Found**: The short code does not exist
+/// - **410 Gone**: The link has expired or reached its click limit
+/// - **429 Too Many Requests**: Too many failed attempts from this IP
+pub async fn unlock_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(short_code): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<UnlockForm>,
+) -> Result<Response, AppError> {
+    let link = resolve_link(&state, &short_code).await?;
+    let Some(password_hash) = link.password_hash.clone() else {
+        return Err(AppError::BadRequest(format!(
+            "short code '{short_code}' is not password protected"
+        )));
+    };
+
+    let attempt_key = format!("{short_code}:{}", addr.ip());
+    state
+        .rate_limits
+        .password
+        .check(&attempt_key)
+        .map_err(AppError::TooManyRequests)?;
+
+    if !password::verify(form.password, password_hash).await? {
+        return Ok(password_form_response(
+            StatusCode::FORBIDDEN,
+            &state.short_url(&short_code),
+            true,
+        ));
+    }
+    state.rate_limits.password.forget(&attempt_key);
+
+    let target = redirect_target(&state, addr, &headers, &link).await?;
+    record_visit(&state, addr, &headers, &link, target.variant.clone()).await?;
+    let mut response = (
+        StatusCode::SEE_OTHER,
+        [
+            (header::LOCATION, target.url.clone()),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ],
+    )
+        .into_response();
+    set_variant_cookie(&mut response, &target);
+    state.metrics.count_redirect(response.status());
+    Ok(response)
+}
+
+/// Builds the password form response; it is never cached.
+fn password_form_response(status: StatusCode, short_url: &str, failed: bool) -> Response {
+    (
+        status,
+        [(header::CACHE_CONTROL, "no-store")],
+        Html(password::render_form(short_url, failed)),
+    )
+        .into_response()
+}
+
+/// Builds the redirect to `target` with the link's status code.
 ///
-/// # Future Improvements
+/// Temporary redirects are sent with `Cache-Control: no-store`, so every
+/// visit reaches the shortener and is counted. Permanent redirects may be
+/// cached for up to [`PERMANENT_REDIRECT_MAX_AGE`], but never beyond the
+/// link's `expires_at`; links with a `max_clicks` limit are not cached at
+/// all, since cached visits would bypass the limit. Neither are links with
+/// redirect rules or A/B variants, whose target depends on the visitor (and
+/// its IP, which no `Vary` header can express).
+fn redirect_response(link: &Link, target: &RedirectTarget, now: i64) -> Response {
+    let max_age = link
+        .expires_at
+        .map_or(PERMANENT_REDIRECT_MAX_AGE, |expires_at| {
+            (expires_at - now).clamp(0, PERMANENT_REDIRECT_MAX_AGE)
+        });
+    let cacheable = link.redirect_type.is_permanent() && link.max_clicks.is_none();
+    let per_visitor = link.has_rules || link.has_variants;
+    let cache_control = if cacheable && !per_visitor {
+        format!("public, max-age={max_age}")
+    } else {
+        "no-store".to_string()
+    };
+    let mut vary = vec!["Accept"];
+    if link.has_rules {
+        vary.extend(["User-Agent", "Accept-Language"]);
+    }
+    if link.has_variants {
+        vary.push("Cookie");
+    }
+
+    let mut response = (
+        link.redirect_type.status(),
+        [
+            (header::LOCATION, target.url.clone()),
+            (header::CACHE_CONTROL, cache_control),
+            (header::VARY, vary.join(", ")),
+        ],
+    )
+        .into_response();
+    set_variant_cookie(&mut response, target);
+    response
+}
+
+/// Adds the `Set-Cookie` header of a newly assigned A/B variant.
+fn set_variant_cookie(response: &mut Response, target: &RedirectTarget) {
+    if let Some(cookie) = target
+        .set_cookie
+        .as_deref()
+        .and_then(|cookie| HeaderValue::from_str(cookie).ok())
+    {
+        response.headers_mut().append(header::SET_COOKIE, cookie);
+    }
+}
+
+/// Handler for the QR code of a short link.
 ///
-/// Consider returning proper HTTP error codes instead of panicking:
-/// - 404 Not Found for invalid short codes
-/// - 500 Internal Server Error for database failures
-pub async fn redirect_handler(
-    State(db_pool): State<SqlitePool>,
+/// Renders the full short URL (see [`AppState::short_url`]) as a PNG or SVG
+/// image. The link only has to exist; a QR code of an expired link can still
+/// be generated.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `Path(short_code)` - The short code extracted from the URL path
+/// * `Query(query)` - Image `size`, error correction level `ec` and `format`
+///
+/// # Returns
+///
+/// Returns the image with a `Content-Type` of `image/png` or `image/svg+xml`.
+///
+/// # Examples
+///
+/// ```bash
+/// # 512x512 PNG with high error correction
+/// curl -o rust.png "http://localhost:3000/kN3pL4m/qr?size=512&ec=h"
+///
+/// # SVG for print
+/// curl -o rust.svg "http://localhost:3000/kN3pL4m/qr?format=svg"
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: The image
+/// - **400 Bad Request**: `size` is out of range, or `ec` or `format` is unknown
+/// - **404 Not Found**: The short code does not exist
+/// - **500 Internal Server Error**: Database query or image encoding fails
+pub async fn qr_handler(
+    State(state): State<AppState>,
+    Path(short_code): Path<String>,
+    Query(query): Query<QrQuery>,
+) -> Result<Response, AppError> {
+    let link = state.store.get_link(&short_code).await?;
+    let image = qr::render(
+        &state.short_url(&link.short_code),
+        query.size.unwrap_or(qr::DEFAULT_QR_SIZE),
+        query.ec,
+        query.format,
+    )?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, image.content_type),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+        ],
+        image.bytes,
+    )
+        .into_response())
+}
+
+/// Looks up a followable link, trying the cache before the database.
+///
+/// Only active links are cached; a cached link is checked for expiry again on
+/// every hit, since it may have expired while cached.
+///
+/// # Errors
+///
+/// Returns [`AppError::NotFound`] for unknown codes and [`AppError::Gone`] for
+/// expired links, like [`LinkStore::get_active_link`].
+async fn resolve_link(state: &AppState, short_code: &str) -> Result<Link, AppError> {
+    let Some(link) = state.link_cache.get(short_code) else {
+        let link = state.store.get_active_link(short_code).await?;
+        state.link_cache.insert(link.clone());
+        return Ok(link);
+    };
+
+    if link.is_expired(chrono::Utc::now().timestamp()) {
+        state.link_cache.invalidate(short_code);
+        return Err(AppError::Gone(format!(
+            "short code '{short_code}' has expired"
+        )));
+    }
+
+    Ok(link)
+}
+
+/// Handler for the click statistics of a link.
+///
+/// Only the API key that created the link can read its statistics.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `Extension(api_key)` - The authenticated caller
+/// * `Path(short_code)` - The short code extracted from the URL path
+///
+/// # Returns
+///
+/// Returns a JSON [`LinkStats`] with total clicks, unique visitors, a per-day
+/// histogram, the top referrers and the clicks per A/B variant.
+///
+/// # Examples
+///
+/// ```bash
+/// curl http://localhost:3000/api/links/kN3pL4m/stats \
+///   -H "Authorization: Bearer $API_KEY"
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: Statistics returned
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+/// - **404 Not Found**: The short code does not exist or belongs to another key
+/// - **500 Internal Server Error**: Database query fails
+///
+/// # Notes
+///
+/// Clicks are written in batches, so the most recent second of redirects
+/// may not be included yet.
+pub async fn stats_handler(
+    State(state): State<AppState>,
+    Extension(api_key): Extension<ApiKey>,
+    Path(short_code): Path<String>,
+) -> Result<Json<LinkStats>, AppError> {
+    find_owned_link(&state, &api_key, &short_code).await?;
+    let stats = state.store.get_link_stats(&short_code).await?;
+    Ok(Json(stats))
+}
+
+/// Handler for listing the caller's links.
+///
+/// Results are paginated with an opaque cursor: pass the `next_cursor` of a
+/// response to get the following page.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `Extension(api_key)` - The authenticated caller
+/// * `Query(query)` - Pagination and search parameters ([`ListLinksQuery`])
+///
+/// # Returns
+///
+/// Returns a JSON [`ListLinksResponse`] with up to `limit` links, oldest first.
+///
+/// # Examples
+///
+/// ```bash
+/// curl "http://localhost:3000/api/links?limit=20&q=rust" \
+///   -H "Authorization: Bearer $API_KEY"
+///
+/// # Links whose destination the last health probe found dead
+/// curl "http://localhost:3000/api/links?health=dead" \
+///   -H "Authorization: Bearer $API_KEY"
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: Page returned (possibly empty)
+/// - **400 Bad Request**: `limit` is not between 1 and 200, or `health` is
+///   not one of `unchecked`, `ok`, `dead` and `redirect_loop`
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+/// - **500 Internal Server Error**: Database query fails
+pub async fn list_links_handler(
+    State(state): State<AppState>,
+    Extension(api_key): Extension<ApiKey>,
+    Query(query): Query<ListLinksQuery>,
+) -> Result<Json<ListLinksResponse>, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(AppError::BadRequest(format!(
+            "limit must be between 1 and {MAX_PAGE_SIZE}"
+        )));
+    }
+
+    let search = query.q.as_deref().filter(|q| !q.is_empty());
+    // Fetch one extra row to learn whether another page follows.
+    let mut links = state
+        .store
+        .list_links(
+            Some(api_key.id),
+            query.cursor,
+            limit + 1,
+            search,
+            query.health,
+        )
+        .await?;
+
+    let next_cursor = if links.len() as i64 > limit {
+        links.truncate(limit as usize);
+        links.last().map(|link| link.id)
+    } else {
+        None
+    };
+
+    Ok(Json(ListLinksResponse {
+        links: links.into_iter().map(LinkResponse::from).collect(),
+        next_cursor,
+    }))
+}
+
+/// Handler for reading a single link.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `Extension(api_key)` - The authenticated caller
+/// * `Path(short_code)` - The short code extracted from the URL path
+///
+/// # Returns
+///
+/// Returns the link as a JSON [`LinkResponse`].
+///
+/// # Examples
+///
+/// ```bash
+/// curl http://localhost:3000/api/links/kN3pL4m \
+///   -H "Authorization: Bearer $API_KEY"
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: Link returned
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+/// - **404 Not Found**: The short code does not exist or belongs to another key
+/// - **500 Internal Server Error**: Database query fails
+pub async fn get_link_handler(
+    State(state): State<AppState>,
+    Extension(api_key): Extension<ApiKey>,
+    Path(short_code): Path<String>,
+) -> Result<Json<LinkResponse>, AppError> {
+    let link = find_owned_link(&state, &api_key, &short_code).await?;
+    Ok(Json(link.into()))
+}
+
+/// Handler for updating a link.
+///
+/// Changes the destination URL, expiry time or click limit. The new URL is
+/// validated, normalized and checked against the blocklist like on creation;
+/// a deduplicated link keeps being deduplicated under its new URL, and its
+/// health is reset to `unchecked` until the next probe. Setting a password
+/// takes the link out of deduplication (see [`prepare_link`]).
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `Extension(api_key)` - The authenticated caller
+/// * `Path(short_code)` - The short code extracted from the URL path
+/// * `Json(payload)` - The changes ([`UpdateLinkRequest`])
+///
+/// # Returns
+///
+/// Returns the updated link as a JSON [`LinkResponse`].
+///
+/// # Examples
+///
+/// ```bash
+/// curl -X PATCH http://localhost:3000/api/links/kN3pL4m \
+///   -H "Authorization: Bearer $API_KEY" \
+///   -H "Content-Type: application/json" \
+///   -d '{"url": "https://www.rust-lang.org/learn", "expires_at": null}'
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: Link updated
+/// - **400 Bad Request**: Invalid or blocked URL, past `expires_at` or `max_clicks` below 1
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+/// - **404 Not Found**: The short code does not exist or belongs to another key
+/// - **409 Conflict**: The new URL is already shortened by another deduplicated link
+/// - **500 Internal Server Error**: Database query fails
+pub async fn update_link_handler(
+    State(state): State<AppState>,
+    Extension(api_key): Extension<ApiKey>,
+    Path(short_code): Path<String>,
+    Json(payload): Json<UpdateLinkRequest>,
+) -> Result<Json<LinkResponse>, AppError> {
+    find_owned_link(&state, &api_key, &short_code).await?;
+
+    let long_url = payload
+        .url
+        .map(|url| validation::normalize_url(&url, &state.base_url))
+        .transpose()?;
+    if let Some(long_url) = &long_url {
+        state.blocklist.check(long_url)?;
+    }
+    validation::validate_expiry(
+        payload.expires_at.flatten(),
+        payload.max_clicks.flatten(),
+        chrono::Utc::now(),
+    )?;
+
+    let password_hash = match payload.password {
+        Some(Some(password)) => {
+            validation::validate_password(&password)?;
+            Some(Some(password::hash(password).await?))
+        }
+        Some(None) => Some(None),
+        None => None,
+    };
+
+    // Like on creation, a password-protected link must not be handed out by
+    // a later `dedup` request, so setting a password gives up the dedup key.
+    let protect = matches!(password_hash, Some(Some(_)));
+    let update = LinkUpdate {
+        dedup_key: long_url
+            .as_deref()
+            .filter(|_| !protect)
+            .map(|url| dedup_key(&api_key, url)),
+        long_url,
+        expires_at: payload
+            .expires_at
+            .map(|expires_at| expires_at.map(|expires_at| expires_at.timestamp())),
+        max_clicks: payload.max_clicks,
+        interstitial: payload.interstitial,
+        redirect_type: payload.redirect_type,
+        password_hash,
+    };
+
+    let link = state.store.update_link(&short_code, &update).await?;
+    if protect {
+        state.store.release_dedup_key(&short_code).await?;
+    }
+    state.link_cache.invalidate(&short_code);
+    Ok(Json(link.into()))
+}
+
+/// Handler for reading the redirect rules of a link.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `Extension(api_key)` - The authenticated caller
+/// * `Path(short_code)` - The short code extracted from the URL path
+///
+/// # Returns
+///
+/// Returns the rules in evaluation order as a JSON [`LinkRulesResponse`].
+///
+/// # Examples
+///
+/// ```bash
+/// curl http://localhost:3000/api/links/app/rules \
+///   -H "Authorization: Bearer $API_KEY"
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: Rules returned (possibly none)
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+/// - **404 Not Found**: The short code does not exist or belongs to another key
+/// - **500 Internal Server Error**: Database query fails
+pub async fn get_link_rules_handler(
+    State(state): State<AppState>,
+    Extension(api_key): Extension<ApiKey>,
+    Path(short_code): Path<String>,
+) -> Result<Json<LinkRulesResponse>, AppError> {
+    let link = find_owned_link(&state, &api_key, &short_code).await?;
+    let rules = state.store.get_link_rules(link.id).await?;
+    Ok(Json(LinkRulesResponse {
+        short_code: link.short_code,
+        rules,
+    }))
+}
+
+/// Handler for replacing the redirect rules of a link.
+///
+/// The request holds the complete ordered list; it replaces every existing
+/// rule, and an empty list removes them all. Rules are validated with
+/// [`validation::validate_link_rules`] before anything is stored.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `Extension(api_key)` - The authenticated caller
+/// * `Path(short_code)` - The short code extracted from the URL path
+/// * `Json(payload)` - The new rules ([`LinkRulesRequest`])
+///
+/// # Returns
+///
+/// Returns the stored rules as a JSON [`LinkRulesResponse`].
+///
+/// # Examples
+///
+/// ```bash
+/// curl -X PUT http://localhost:3000/api/links/app/rules \
+///   -H "Authorization: Bearer $API_KEY" \
+///   -H "Content-Type: application/json" \
+///   -d '{"rules": [
+///         {"platform": "ios", "target_url": "https://apps.apple.com/app/id123"},
+///         {"platform": "android", "target_url": "https://play.google.com/store/apps/details?id=com.example"}
+///       ]}'
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: Rules replaced
+/// - **400 Bad Request**: A rule has no condition, a malformed language or
+///   country, or an invalid or blocked target URL, or there are too many rules
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+/// - **404 Not Found**: The short code does not exist or belongs to another key
+/// - **500 Internal Server Error**: Database query fails; the old rules are kept
+pub async fn replace_link_rules_handler(
+    State(state): State<AppState>,
+    Extension(api_key): Extension<ApiKey>,
     Path(short_code): Path<String>,
-) -> Redirect {
-    println!("Redirecting code: {}", short_code);
+    Json(payload): Json<LinkRulesRequest>,
+) -> Result<Json<LinkRulesResponse>, AppError> {
+    let link = find_owned_link(&state, &api_key, &short_code).await?;
+    let new_rules = validation::validate_link_rules(&payload.rules, &state.base_url)?;
+    for rule in &new_rules {
+        state.blocklist.check(&rule.target_url)?;
+    }
+
+    let rules = state.store.replace_link_rules(link.id, &new_rules).await?;
+    state.link_cache.invalidate(&short_code);
+    Ok(Json(LinkRulesResponse {
+        short_code: link.short_code,
+        rules,
+    }))
+}
+
+/// Handler for reading the A/B variants of a link.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `Extension(api_key)` - The authenticated caller
+/// * `Path(short_code)` - The short code extracted from the URL path
+///
+/// # Returns
+///
+/// Returns the variants as a JSON [`LinkVariantsResponse`].
+///
+/// # Examples
+///
+/// ```bash
+/// curl http://localhost:3000/api/links/spring/variants \
+///   -H "Authorization: Bearer $API_KEY"
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: Variants returned (possibly none)
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+/// - **404 Not Found**: The short code does not exist or belongs to another key
+/// - **500 Internal Server Error**: Database query fails
+pub async fn get_link_variants_handler(
+    State(state): State<AppState>,
+    Extension(api_key): Extension<ApiKey>,
+    Path(short_code): Path<String>,
+) -> Result<Json<LinkVariantsResponse>, AppError> {
+    let link = find_owned_link(&state, &api_key, &short_code).await?;
+    let variants = state.store.get_link_variants(link.id).await?;
+    Ok(Json(LinkVariantsResponse {
+        short_code: link.short_code,
+        variants,
+    }))
+}
+
+/// Handler for replacing the A/B variants of a link.
+///
+/// The request holds the complete list; it replaces every existing variant,
+/// and an empty list ends the split so visitors go to the link's URL again.
+/// Visitors keep their variant while a variant of the same name exists;
+/// visitors of removed variants are assigned anew.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `Extension(api_key)` - The authenticated caller
+/// * `Path(short_code)` - The short code extracted from the URL path
+/// * `Json(payload)` - The new variants ([`LinkVariantsRequest`])
+///
+/// # Returns
+///
+/// Returns the stored variants as a JSON [`LinkVariantsResponse`].
+///
+/// # Examples
+///
+/// ```bash
+/// curl -X PUT http://localhost:3000/api/links/spring/variants \
+///   -H "Authorization: Bearer $API_KEY" \
+///   -H "Content-Type: application/json" \
+///   -d '{"variants": [
+///         {"name": "a", "target_url": "https://example.com/landing-a", "weight": 70},
+///         {"name": "b", "target_url": "https://example.com/landing-b", "weight": 30}
+///       ]}'
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: Variants replaced
+/// - **400 Bad Request**: A name is malformed or used twice, a weight is out of
+///   range, a target URL is invalid or blocked, or there are too many variants
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+/// - **404 Not Found**: The short code does not exist or belongs to another key
+/// - **500 Internal Server Error**: Database query fails; the old variants are kept
+pub async fn replace_link_variants_handler(
+    State(state): State<AppState>,
+    Extension(api_key): Extension<ApiKey>,
+    Path(short_code): Path<String>,
+    Json(payload): Json<LinkVariantsRequest>,
+) -> Result<Json<LinkVariantsResponse>, AppError> {
+    let link = find_owned_link(&state, &api_key, &short_code).await?;
+    let new_variants = validation::validate_link_variants(&payload.variants, &state.base_url)?;
+    for variant in &new_variants {
+        state.blocklist.check(&variant.target_url)?;
+    }
+
+    let variants = state
+        .store
+        .replace_link_variants(link.id, &new_variants)
+        .await?;
+    state.link_cache.invalidate(&short_code);
+    Ok(Json(LinkVariantsResponse {
+        short_code: link.short_code,
+        variants,
+    }))
+}
+
+/// Handler for deleting a link.
+///
+/// The link's recorded clicks are deleted with it, and its short code becomes
+/// available again.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `Extension(api_key)` - The authenticated caller
+/// * `Path(short_code)` - The short code extracted from the URL path
+///
+/// # Examples
+///
+/// ```bash
+/// curl -X DELETE http://localhost:3000/api/links/kN3pL4m \
+///   -H "Authorization: Bearer $API_KEY"
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **204 No Content**: Link deleted
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+/// - **404 Not Found**: The short code does not exist or belongs to another key
+/// - **500 Internal Server Error**: Database query fails
+pub async fn delete_link_handler(
+    State(state): State<AppState>,
+    Extension(api_key): Extension<ApiKey>,
+    Path(short_code): Path<String>,
+) -> Result<StatusCode, AppError> {
+    find_owned_link(&state, &api_key, &short_code).await?;
+    state.store.delete_link(&short_code).await?;
+    state.link_cache.invalidate(&short_code);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Handler for shortening many URLs in one request.
+///
+/// Accepts either a JSON array of create requests (the same objects as
+/// `POST /shorten`), with `Content-Type: application/x-ndjson` one such
+/// object per line, or with `Content-Type: text/csv` a CSV file with a
+/// header row (see [`CsvLinkRow`]). Every row is validated on its own and
+/// all valid rows are inserted in a single transaction; rows whose generated
+/// code was taken are retried with new codes in another one.
+///
+/// Files from `GET /api/links/export` are accepted as well: `long_url` and
+/// `short_code` stand in for `url` and `alias`, and other exported columns
+/// are ignored. Exported password-protected links fail, because the export
+/// does not contain their password.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `Extension(api_key)` - The authenticated caller, recorded as the owner
+/// * `headers` - Request headers (`Content-Type` selects JSON, JSON Lines or CSV)
+/// * `body` - The raw request body
+///
+/// # Returns
+///
+/// Returns a JSON [`BulkResponse`] with one [`BulkLinkResult`] per row. A
+/// failing row does not fail the request.
+///
+/// # Examples
+///
+/// ```bash
+/// curl -X POST http://localhost:3000/api/links/bulk \
+///   -H "Authorization: Bearer $API_KEY" \
+///   -H "Content-Type: application/json" \
+///   -d '[{"url": "https://www.rust-lang.org/"}, {"url": "https://docs.rs/", "alias": "docs"}]'
+///
+/// curl -X POST http://localhost:3000/api/links/bulk \
+///   -H "Authorization: Bearer $API_KEY" \
+///   -H "Content-Type: text/csv" \
+///   --data-binary @links.csv
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: Rows processed; check each result for errors
+/// - **400 Bad Request**: The body is not a JSON array or a CSV file with a
+///   `url` column, is empty, or has more than [`MAX_BULK_ROWS`] rows
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+/// - **413 Payload Too Large**: The body exceeds [`BULK_BODY_LIMIT`]
+/// - **500 Internal Server Error**: The transaction fails; nothing is inserted
+pub async fn bulk_shorten_handler(
+    State(state): State<AppState>,
+    Extension(api_key): Extension<ApiKey>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<BulkResponse>, AppError> {
+    let content_type = header_value(&headers, header::CONTENT_TYPE).unwrap_or_default();
+    let rows = if content_type.starts_with("text/csv") {
+        parse_csv_rows(&body)?
+    } else if content_type.starts_with("application/x-ndjson") {
+        parse_jsonl_rows(&body)
+    } else {
+        parse_json_rows(&body)?
+    };
+
+    if rows.is_empty() {
+        return Err(AppError::BadRequest("no rows to shorten".to_string()));
+    }
+    if rows.len() > MAX_BULK_ROWS {
+        return Err(AppError::BadRequest(format!(
+            "at most {MAX_BULK_ROWS} rows can be shortened at once"
+        )));
+    }
+
+    let response = shorten_rows(
+        state.store.as_ref(),
+        &state.base_url,
+        &state.blocklist,
+        state.codes.as_ref(),
+        Some(&api_key),
+        rows,
+    )
+    .await?;
+
+    // New aliases may belong to purged links that are still cached.
+    for result in &response.results {
+        if result.created == Some(true)
+            && let Some(short_code) = &result.short_code
+        {
+            state.link_cache.invalidate(short_code);
+        }
+    }
+    Ok(Json(response))
+}
+
+/// Validates parsed bulk rows and inserts the valid ones in one transaction.
+///
+/// Shared by [`bulk_shorten_handler`] and the admin CLI's `import`. Rows are
+/// numbered from 1 in the results.
+///
+/// # Errors
+///
+/// Returns an error only if the transaction fails; invalid rows are
+/// reported in the [`BulkResponse`].
+pub(crate) async fn shorten_rows(
+    store: &dyn LinkStore,
+    base_url: &Url,
+    blocklist: &Blocklist,
+    codes: &dyn CodeGenerator,
+    owner: Option<&ApiKey>,
+    rows: Vec<Result<CreateLinkRequest, AppError>>,
+) -> Result<BulkResponse, AppError> {
+    let row_count = rows.len();
+    let mut outcomes = Vec::with_capacity(row_count);
+    let mut aliases = Vec::with_capacity(row_count);
+    let mut links = Vec::new();
+    for row in rows {
+        aliases.push(row.as_ref().ok().and_then(|payload| payload.alias.clone()));
+        let prepared = match row {
+            Ok(payload) => prepare_link(base_url, blocklist, owner, &payload).await,
+            Err(err) => Err(err),
+        };
+        match prepared {
+            Ok(link) => {
+                links.push(link);
+                outcomes.push(None);
+            }
+            Err(err) => outcomes.push(Some(Err(err))),
+        }
+    }
+
+    let mut inserted = codes::create_links(store, codes, &mut links)
+        .await?
+        .into_iter();
+    let mut results = Vec::with_capacity(row_count);
+    for (index, (outcome, alias)) in outcomes.into_iter().zip(aliases).enumerate() {
+        let outcome = match outcome {
+            Some(err) => err,
+            None => match inserted.next() {
+                Some(Ok(link)) if !link.created => {
+                    reuse_link(base_url, link.short_code, alias.as_deref())
+                }
+                Some(result) => result.map(|link| CreateLinkResponse {
+                    short_url: state::short_url(base_url, &link.short_code),
+                    short_code: link.short_code,
+                    created: true,
+                }),
+                None => Err(AppError::Internal("missing bulk insert result".to_string())),
+            },
+        };
+
+        results.push(match outcome {
+            Ok(response) => BulkLinkResult {
+                row: index + 1,
+                short_code: Some(response.short_code),
+                short_url: Some(response.short_url),
+                created: Some(response.created),
+                error: None,
+            },
+            Err(err) => BulkLinkResult {
+                row: index + 1,
+                short_code: None,
+                short_url: None,
+                created: None,
+                error: Some(err.to_string()),
+            },
+        });
+    }
+
+    let failed = results
+        .iter()
+        .filter(|result| result.error.is_some())
+        .count();
+    Ok(BulkResponse {
+        succeeded: results.len() - failed,
+        failed,
+        results,
+    })
+}
+
+/// Parses a JSON array body into create requests, one result per element.
+pub(crate) fn parse_json_rows(
+    body: &[u8],
+) -> Result<Vec<Result<CreateLinkRequest, AppError>>, AppError> {
+    let values: Vec<serde_json::Value> = serde_json::from_slice(body)
+        .map_err(|err| AppError::BadRequest(format!("expected a JSON array: {err}")))?;
+
+    Ok(values
+        .into_iter()
+        .map(|value| {
+            serde_json::from_value(value)
+                .map_err(|err| AppError::BadRequest(format!("invalid row: {err}")))
+        })
+        .collect())
+}
+
+/// Parses a JSON Lines body into create requests, one result per non-empty
+/// line.
+pub(crate) fn parse_jsonl_rows(body: &[u8]) -> Vec<Result<CreateLinkRequest, AppError>> {
+    body.split(|byte| *byte == b'\n')
+        .filter(|line| !line.trim_ascii().is_empty())
+        .map(|line| {
+            serde_json::from_slice(line)
+                .map_err(|err| AppError::BadRequest(format!("invalid row: {err}")))
+        })
+        .collect()
+}
+
+/// Parses a CSV body with a header row into create requests, one result per
+/// data row.
+///
+/// The header must contain a `url` column, or the `long_url` column of an
+/// export.
+pub(crate) fn parse_csv_rows(
+    body: &[u8],
+) -> Result<Vec<Result<CreateLinkRequest, AppError>>, AppError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body);
+
+    let has_url_column = reader
+        .headers()
+        .map_err(|err| AppError::BadRequest(format!("invalid CSV header: {err}")))?
+        .iter()
+        .any(|column| column == "url" || column == "long_url");
+    if !has_url_column {
+        return Err(AppError::BadRequest(
+            "CSV header must contain a 'url' or 'long_url' column".to_string(),
+        ));
+    }
+
+    Ok(reader
+        .deserialize::<CsvLinkRow>()
+        .map(|row| {
+            row.map(CreateLinkRequest::from)
+                .map_err(|err| AppError::BadRequest(format!("invalid row: {err}")))
+        })
+        .collect())
+}
+
+/// Handler for exporting all of the caller's links.
+///
+/// The response is streamed: links are read from the database in chunks of
+/// 500 while the body is being sent, so exports of any size use constant
+/// memory.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+/// * `Extension(api_key)` - The authenticated caller
+/// * `Query(query)` - The export format ([`ExportQuery`])
+///
+/// # Returns
+///
+/// Returns a `text/csv` file with a header row, or an `application/x-ndjson`
+/// file with one [`LinkResponse`] object per line.
+///
+/// # Examples
+///
+/// ```bash
+/// curl "http://localhost:3000/api/links/export?format=csv" \
+///   -H "Authorization: Bearer $API_KEY" -o links.csv
+///
+/// curl "http://localhost:3000/api/links/export?format=jsonl" \
+///   -H "Authorization: Bearer $API_KEY"
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: Export streamed
+/// - **400 Bad Request**: Unknown `format`
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+///
+/// # Notes
+///
+/// A database error after the first chunk aborts the stream, so clients
+/// should treat a truncated download as failed.
+pub async fn export_links_handler(
+    State(state): State<AppState>,
+    Extension(api_key): Extension<ApiKey>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let format = query.format;
+    let (content_type, file_name) = match format {
+        ExportFormat::Csv => ("text/csv", "links.csv"),
+        ExportFormat::Jsonl => ("application/x-ndjson", "links.jsonl"),
+    };
+
+    // State: (cursor of the last exported link, first chunk?, finished?)
+    let chunks = stream::try_unfold((None, true, false), move |(cursor, first, done)| {
+        let store = state.store.clone();
+        async move {
+            if done {
+                return Ok(None);
+            }
 
-    let long_url = db::get_long_url(&db_pool, &short_code)
+            let links = store
+                .list_links(Some(api_key.id), cursor, EXPORT_PAGE_SIZE, None, None)
+                .await?;
+            let next_cursor = links.last().map(|link| link.id);
+            let finished = (links.len() as i64) < EXPORT_PAGE_SIZE;
+            let chunk = encode_export_chunk(format, links, first)?;
+
+            Ok::<_, AppError>(Some((chunk, (next_cursor.or(cursor), false, finished))))
+        }
+    });
+
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{file_name}\""),
+            ),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response()
+}
+
+/// Encodes one chunk of an export.
+///
+/// The CSV header row is written before the first chunk, even if the caller
+/// has no links. Also used by the admin CLI's `export`.
+pub(crate) fn encode_export_chunk(
+    format: ExportFormat,
+    links: Vec<Link>,
+    first: bool,
+) -> Result<Vec<u8>, AppError> {
+    let export_error =
+        |err: &dyn std::fmt::Display| AppError::Internal(format!("export failed: {err}"));
+
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(Vec::new());
+            if first {
+                writer
+                    .write_record(EXPORT_CSV_HEADER)
+                    .map_err(|err| export_error(&err))?;
+            }
+            for link in links {
+                writer
+                    .serialize(LinkResponse::from(link))
+                    .map_err(|err| export_error(&err))?;
+            }
+            writer.into_inner().map_err(|err| export_error(&err))
+        }
+        ExportFormat::Jsonl => {
+            let mut buffer = Vec::new();
+            for link in links {
+                serde_json::to_writer(&mut buffer, &LinkResponse::from(link))
+                    .map_err(|err| export_error(&err))?;
+                buffer.push(b'\n');
+            }
+            Ok(buffer)
+        }
+    }
+}
+
+/// Handler for the redirect cache counters.
+///
+/// Used to size the cache: a low hit ratio with a full cache suggests
+/// raising `LINK_CACHE_CAPACITY`.
+///
+/// # Arguments
+///
+/// * `State(state)` - The shared application state
+///
+/// # Returns
+///
+/// Returns a JSON [`CacheStats`] with the hit and miss counters since startup.
+///
+/// # Examples
+///
+/// ```bash
+/// curl http://localhost:3000/api/cache/stats \
+///   -H "Authorization: Bearer $API_KEY"
+/// # {"hits":9120,"misses":880,"entries":312,"capacity":10000,"ttl_secs":60}
+/// ```
+///
+/// # HTTP Status Codes
+///
+/// - **200 OK**: Counters returned
+/// - **401 Unauthorized**: Missing, unknown or revoked API key
+pub async fn cache_stats_handler(State(state): State<AppState>) -> Json<CacheStats> {
+    Json(state.link_cache.stats())
+}
+
+/// Builds the dedup key of a URL, scoped to the API key that shortened it.
+fn dedup_key(api_key: &ApiKey, long_url: &str) -> String {
+    format!("{}:{long_url}", api_key.id)
+}
+
+/// Loads a link and checks that it belongs to the caller.
+///
+/// Links of other keys are reported as not found, so their existence is not
+/// leaked.
+async fn find_owned_link(
+    state: &AppState,
+    api_key: &ApiKey,
+    short_code: &str,
+) -> Result<Link, AppError> {
+    let link = state.store.get_link(short_code).await?;
+    if !link.is_owned_by(api_key) {
+        return Err(AppError::NotFound(format!(
+            "short code '{short_code}' not found"
+        )));
+    }
+    Ok(link)
+}
+
+/// Returns a header as an owned string, if present and valid UTF-8.
+fn header_value(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        analytics,
+        cache::LinkCache,
+        config::CodeConfig,
+        geoip::GeoIp,
+        metrics::Metrics,
+        models::{LinkHealth, RedirectType},
+        rate_limit::{RateLimit, RateLimiter, RateLimiters},
+        store::MemoryStore,
+    };
+    use std::sync::Arc;
+
+    fn test_state() -> AppState {
+        let store: Arc<dyn LinkStore> = Arc::new(MemoryStore::new());
+        let (clicks, _writer) = analytics::spawn_click_writer(store.clone(), "salt");
+        let unlimited = RateLimit {
+            per_minute: 0,
+            burst: 0,
+        };
+        AppState {
+            store,
+            clicks,
+            link_cache: LinkCache::new(10, Duration::from_secs(60)),
+            base_url: Url::parse("http://localhost:3000/").unwrap(),
+            codes: codes::from_config(&CodeConfig::default()),
+            rate_limits: RateLimiters {
+                shorten: RateLimiter::new(unlimited),
+                redirect: RateLimiter::new(unlimited),
+                api: RateLimiter::new(unlimited),
+                password: RateLimiter::new(unlimited),
+            },
+            geoip: Arc::new(GeoIp::disabled()),
+            blocklist: Arc::new(Blocklist::disabled()),
+            metrics: Arc::new(Metrics::new()),
+        }
+    }
+
+    async fn shorten_dedup(state: &AppState, api_key: &ApiKey, url: &str) -> CreateLinkResponse {
+        let payload = serde_json::from_value(serde_json::json!({"url": url, "dedup": true}));
+        let Json(response) = shorten_handler(
+            State(state.clone()),
+            Extension(api_key.clone()),
+            Json(payload.unwrap()),
+        )
         .await
-        .expect("not entry found with the short_code!");
+        .unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn used_up_and_realiased_links_leave_the_cache() {
+        let state = test_state();
+        let api_key = state.store.create_api_key("test", "hash").await.unwrap();
+        let limited = NewLink {
+            short_code: "flash".to_string(),
+            long_url: "https://example.com/old".to_string(),
+            max_clicks: Some(1),
+            ..Default::default()
+        };
+        state.store.create_short_link(&limited).await.unwrap();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 4000));
+        let headers = HeaderMap::new();
+
+        let link = resolve_link(&state, "flash").await.unwrap();
+        record_visit(&state, addr, &headers, &link, None)
+            .await
+            .unwrap();
+        // The cached copy still has a click left.
+        let cached = resolve_link(&state, "flash").await.unwrap();
+        assert!(matches!(
+            record_visit(&state, addr, &headers, &cached, None).await,
+            Err(AppError::Gone(_))
+        ));
+        assert!(state.link_cache.get("flash").is_none());
+
+        // A stale entry must not outlive a new link under the same alias.
+        state.link_cache.insert(cached);
+        state
+            .store
+            .purge_expired_links(chrono::Utc::now().timestamp())
+            .await
+            .unwrap();
+        let payload = serde_json::json!({"url": "https://example.com/new", "alias": "flash"});
+        let Json(created) = shorten_handler(
+            State(state.clone()),
+            Extension(api_key),
+            Json(serde_json::from_value(payload).unwrap()),
+        )
+        .await
+        .unwrap();
+        assert!(created.created);
+        let link = resolve_link(&state, "flash").await.unwrap();
+        assert_eq!(link.long_url, "https://example.com/new");
+    }
+
+    #[tokio::test]
+    async fn setting_a_password_stops_deduplication() {
+        let state = test_state();
+        let api_key = state.store.create_api_key("test", "hash").await.unwrap();
+        let url = "https://www.rust-lang.org/";
+        let first = shorten_dedup(&state, &api_key, url).await;
+        assert!(first.created);
+
+        let update = serde_json::from_value(serde_json::json!({"password": "correct horse"}));
+        let Json(updated) = update_link_handler(
+            State(state.clone()),
+            Extension(api_key.clone()),
+            Path(first.short_code.clone()),
+            Json(update.unwrap()),
+        )
+        .await
+        .unwrap();
+        assert!(updated.password_protected);
+
+        let second = shorten_dedup(&state, &api_key, url).await;
+        assert!(second.created);
+        assert_ne!(second.short_code, first.short_code);
+        assert!(!shorten_dedup(&state, &api_key, url).await.created);
+    }
+
+    #[test]
+    fn csv_rows_allow_empty_cells() {
+        let body = b"url,alias,expires_at,max_clicks,dedup\n\
+            https://www.rust-lang.org/,rust,,,\n\
+            https://docs.rs/,,2030-01-01T00:00:00Z,100,true\n\
+            https://crates.io/,,,not-a-number,\n";
+        let rows = parse_csv_rows(body).unwrap();
+
+        assert_eq!(rows.len(), 3);
+        let first = rows[0].as_ref().unwrap();
+        assert_eq!(first.alias.as_deref(), Some("rust"));
+        assert!(!first.dedup);
+        let second = rows[1].as_ref().unwrap();
+        assert_eq!(second.max_clicks, Some(100));
+        assert!(second.expires_at.is_some() && second.dedup);
+        assert!(matches!(rows[2], Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn csv_requires_url_column() {
+        assert!(matches!(
+            parse_csv_rows(b"link\nhttps://docs.rs/\n"),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn json_rows_fail_individually() {
+        let rows = parse_json_rows(br#"[{"url": "https://docs.rs/"}, {"alias": "x"}]"#).unwrap();
+        assert!(rows[0].is_ok());
+        assert!(rows[1].is_err());
+        assert!(parse_json_rows(br#"{"url": "https://docs.rs/"}"#).is_err());
+    }
+
+    #[test]
+    fn jsonl_rows_read_export_lines() {
+        let body = b"{\"short_code\": \"rust\", \"long_url\": \"https://www.rust-lang.org/\", \"redirect_type\": 301}\n\
+            \n\
+            not json\n";
+        let rows = parse_jsonl_rows(body);
+
+        assert_eq!(rows.len(), 2);
+        let first = rows[0].as_ref().unwrap();
+        assert_eq!(first.url, "https://www.rust-lang.org/");
+        assert_eq!(first.alias.as_deref(), Some("rust"));
+        assert_eq!(first.redirect_type, RedirectType::MovedPermanently);
+        assert!(matches!(rows[1], Err(AppError::BadRequest(_))));
+    }
+
+    #[test]
+    fn csv_export_starts_with_header() {
+        let chunk = encode_export_chunk(ExportFormat::Csv, Vec::new(), true).unwrap();
+        assert_eq!(
+            String::from_utf8(chunk).unwrap(),
+            "short_code,long_url,created_at,expires_at,max_clicks,click_count,interstitial,redirect_type,password_protected,health,health_checked_at,health_detail\n"
+        );
+    }
+
+    fn link(redirect_type: RedirectType) -> Link {
+        Link {
+            id: 1,
+            short_code: "kN3pL4m".to_string(),
+            long_url: "https://www.rust-lang.org/".to_string(),
+            expires_at: None,
+            max_clicks: None,
+            click_count: 0,
+            owner_id: None,
+            created_at: None,
+            interstitial: false,
+            redirect_type,
+            password_hash: None,
+            has_rules: false,
+            has_variants: false,
+            health: LinkHealth::Unchecked,
+            health_checked_at: None,
+            health_detail: None,
+        }
+    }
+
+    const TARGET: &str = "https://www.rust-lang.org/";
+
+    fn target(url: &str) -> RedirectTarget {
+        RedirectTarget {
+            url: url.to_string(),
+            variant: None,
+            set_cookie: None,
+        }
+    }
+
+    fn cache_control(response: &Response) -> &str {
+        response.headers()[header::CACHE_CONTROL].to_str().unwrap()
+    }
+
+    #[test]
+    fn redirect_uses_the_link_status() {
+        for (redirect_type, status) in [
+            (
+                RedirectType::MovedPermanently,
+                StatusCode::MOVED_PERMANENTLY,
+            ),
+            (RedirectType::Found, StatusCode::FOUND),
+            (
+                RedirectType::TemporaryRedirect,
+                StatusCode::TEMPORARY_REDIRECT,
+            ),
+            (
+                RedirectType::PermanentRedirect,
+                StatusCode::PERMANENT_REDIRECT,
+            ),
+        ] {
+            let response = redirect_response(&link(redirect_type), &target(TARGET), 0);
+            assert_eq!(response.status(), status);
+            assert_eq!(
+                response.headers()[header::LOCATION],
+                "https://www.rust-lang.org/"
+            );
+        }
+    }
+
+    #[test]
+    fn only_permanent_redirects_are_cacheable() {
+        let temporary =
+            redirect_response(&link(RedirectType::TemporaryRedirect), &target(TARGET), 0);
+        assert_eq!(cache_control(&temporary), "no-store");
+
+        let permanent =
+            redirect_response(&link(RedirectType::MovedPermanently), &target(TARGET), 0);
+        assert_eq!(cache_control(&permanent), "public, max-age=86400");
+
+        let mut expiring = link(RedirectType::PermanentRedirect);
+        expiring.expires_at = Some(1_000);
+        let response = redirect_response(&expiring, &target(TARGET), 400);
+        assert_eq!(cache_control(&response), "public, max-age=600");
+
+        let mut limited = link(RedirectType::PermanentRedirect);
+        limited.max_clicks = Some(10);
+        let response = redirect_response(&limited, &target(TARGET), 0);
+        assert_eq!(cache_control(&response), "no-store");
+    }
+
+    #[test]
+    fn redirects_with_rules_are_never_cached() {
+        let mut ruled = link(RedirectType::MovedPermanently);
+        ruled.has_rules = true;
+        let response = redirect_response(&ruled, &target("https://apps.apple.com/app/id1"), 0);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://apps.apple.com/app/id1"
+        );
+        assert_eq!(cache_control(&response), "no-store");
+        assert_eq!(
+            response.headers()[header::VARY],
+            "Accept, User-Agent, Accept-Language"
+        );
+    }
+
+    #[test]
+    fn new_variant_assignments_set_a_cookie() {
+        let mut split = link(RedirectType::Found);
+        split.has_variants = true;
+        let assigned = RedirectTarget {
+            variant: Some("b".to_string()),
+            set_cookie: Some(variants::sticky_cookie("kN3pL4m", "b")),
+            ..target("https://example.com/b")
+        };
+
+        let response = redirect_response(&split, &assigned, 0);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://example.com/b"
+        );
+        assert_eq!(response.headers()[header::VARY], "Accept, Cookie");
+        assert!(
+            response.headers()[header::SET_COOKIE]
+                .to_str()
+                .unwrap()
+                .starts_with("usv_kN3pL4m=b;")
+        );
 
-    println!("Found long URL: {}", long_url);
-    Redirect::to(&long_url)
+        let returning = RedirectTarget {
+            set_cookie: None,
+            ..assigned
+        };
+        let response = redirect_response(&split, &returning, 0);
+        assert!(!response.headers().contains_key(header::SET_COOKIE));
+    }
 }
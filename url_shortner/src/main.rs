@@ -5,94 +5,423 @@
 //! ## Overview
 //!
 //! This service provides a simple REST API for creating shortened URLs and redirecting
-//! short codes to their original long URLs. It uses SQLite for persistence and generates
-//! 7-character unique identifiers using nanoid.
+//! short codes to their original long URLs. It uses SQLite (or Postgres) for persistence and generates
+//! unique short codes using nanoid (7 URL-safe characters by default), a base62
+//! counter or obfuscated row ids.
 //!
 //! ## Features
 //!
 //! - Create short URLs from long URLs
-//! - Automatic redirection from short codes to original URLs
-//! - SQLite database for persistent storage
+//! - Random, base62 counter or obfuscated short codes, retried when taken
+//! - URL validation and normalization (http/https only, no redirect loops)
+//! - Custom aliases (vanity short codes) such as `/launch2026`
+//! - Link expiry by time (`expires_at`) or click count (`max_clicks`)
+//! - Click analytics (referrers, unique visitors, daily histogram)
+//! - API key authentication with per-key link ownership
+//! - Link management (list, search, update, delete)
+//! - Bulk shortening and CSV / JSON Lines import and export
+//! - Automatic redirection from short codes to original URLs, with a per-link status code
+//! - QR codes of short URLs as PNG or SVG
+//! - Link previews and optional per-link interstitial pages
+//! - Password-protected links (Argon2 hashes, rate limited attempts)
+//! - Redirect rules: per-platform, per-language and per-country targets
+//! - A/B split redirects across weighted targets, sticky per visitor
+//! - Destination blocklist (domains and URL prefixes), reloaded when the file changes
+//! - Background health probes that flag dead and redirect-looping destinations
+//! - In-process LRU cache for hot redirects
+//! - Structured logging with `tracing`, a request id per request and Prometheus metrics
+//! - Per-client token bucket rate limiting (429 with `Retry-After`)
+//! - Graceful shutdown: in-flight requests finish and queued clicks are written
+//! - Admin subcommands to create, inspect, import, export and clean up links offline
+//! - Configuration from a TOML file, environment variables or flags
+//! - Pluggable storage: SQLite (default), Postgres (cargo feature) or in-memory
 //! - Async/await architecture for high performance
 //!
 //! ## Architecture
 //!
-//! The application is organized into four main modules:
+//! The application is organized into twenty-eight main modules:
 //!
 //! - `main`: Application entry point and routing configuration
-//! - `db`: Database operations and connection management
+//! - `analytics`: Batched click recording and IP hashing
+//! - `auth`: API key generation and the bearer token middleware
+//! - `blocklist`: Hot-reloaded blocklist of destinations that must not be shortened
+//! - `cache`: In-process LRU cache of links for redirects
+//! - `cli`: Command line arguments and admin subcommands
+//! - `codes`: Short code strategies (random, base62 counter, obfuscated id) and collision retries
+//! - `config`: Layered configuration (TOML file, environment, flags)
+//! - `db`: SQLite queries and connection management
+//! - `error`: Application error type and its HTTP status mapping
+//! - `geoip`: Country lookup of client IPs in a local MaxMind database
 //! - `handlers`: HTTP request handlers for each endpoint
+//! - `metrics`: Prometheus metrics of requests, redirects, queries, the cache and the pool
+//! - `migrations`: Versioned SQLite schema migrations tracked in `schema_version`
 //! - `models`: Data structures for API requests and responses
+//! - `password`: Argon2 hashing and the password form of protected links
+//! - `preview`: HTML preview page shown for `/{short_code}+` and interstitial links
+//! - `probe`: Health probes of link destinations (dead links, redirect loops)
+//! - `qr`: QR code rendering (PNG and SVG) of short URLs
+//! - `rate_limit`: Token bucket rate limiting per API key or client IP
+//! - `rules`: Redirect rules by platform, language and country
+//! - `shutdown`: Graceful shutdown on `SIGTERM` / `Ctrl-C` with a drain timeout
+//! - `state`: Shared application state passed to handlers
+//! - `store`: The `LinkStore` trait and its SQLite, Postgres and in-memory backends
+//! - `tasks`: Background tasks (purging expired links, probing destinations,
+//!   reloading the blocklist)
+//! - `telemetry`: Log subscriber setup and the request id / tracing middleware
+//! - `validation`: Input validation rules (URLs, custom aliases, expiry)
+//! - `variants`: Weighted A/B split redirects with sticky cookie assignment
 //!
 //! ## API Endpoints
 //!
-//! - `GET /` - Health check endpoint
-//! - `POST /shorten` - Create a new short URL
-//! - `GET /{short_code}` - Redirect to the original URL
+//! - `GET /` - Readiness check; 503 when the database cannot be reached
+//! - `GET /metrics` - Prometheus metrics
+//! - `POST /shorten` - Create a new short URL (API key required)
+//! - `GET /{short_code}` - Redirect to the original URL (301/302/307/308, per link; `POST` too)
+//! - `GET /{short_code}+` - Preview where a short URL goes (HTML, or JSON with `Accept: application/json`)
+//! - `GET /{short_code}/qr?size=&ec=&format=png|svg` - QR code of the short URL
+//! - `POST /{short_code}/unlock` - Check the password of a protected link and redirect
+//! - `GET /api/links?q=&health=` - List, search and filter your links by health, paginated (API key required)
+//! - `POST /api/links/bulk` - Shorten many URLs from a JSON array, JSON Lines or CSV file (API key required)
+//! - `GET /api/links/export?format=csv|jsonl` - Stream all your links (API key required)
+//! - `GET /api/links/{code}` - Read a link (API key required)
+//! - `PATCH /api/links/{code}` - Update a link's URL, expiry or click limit (API key required)
+//! - `DELETE /api/links/{code}` - Delete a link and its clicks (API key required)
+//! - `GET /api/links/{code}/stats` - Click statistics for a link (API key required)
+//! - `GET|PUT /api/links/{code}/rules` - Read or replace a link's redirect rules (API key required)
+//! - `GET|PUT /api/links/{code}/variants` - Read or replace a link's A/B variants (API key required)
+//! - `GET /api/cache/stats` - Redirect cache hit and miss counters (API key required)
 //!
 //! ## Example Usage
 //!
 //! ```bash
+//! # Create an API key (printed once)
+//! cargo run -- keys create --name me
+//!
 //! # Create a short URL
 //! curl -X POST http://localhost:3000/shorten \
+//!   -H "Authorization: Bearer $API_KEY" \
 //!   -H "Content-Type: application/json" \
 //!   -d '{"url": "https://www.rust-lang.org/"}'
 //!
 //! # Access the short URL (redirects to original)
 //! curl -L http://localhost:3000/abc1234
+//!
+//! # Inspect it without the API
+//! cargo run -- get abc1234
 //! ```
 
 use axum::{
     Router,
+    extract::DefaultBodyLimit,
+    middleware,
     routing::{get, post},
 };
+use blocklist::Blocklist;
+use cache::LinkCache;
+use clap::Parser;
+use cli::{Cli, Command};
+use config::Config;
 use dotenvy::dotenv;
-use std::env;
+use geoip::GeoIp;
+use metrics::Metrics;
+use nanoid::nanoid;
+use probe::Prober;
+use rate_limit::{RateLimiter, RateLimiters};
+use state::AppState;
+use store::{LinkStore, MeteredStore};
+mod analytics;
+mod auth;
+mod blocklist;
+mod cache;
+mod cli;
+mod codes;
+mod config;
 mod db;
+mod error;
+mod geoip;
 mod handlers;
+mod metrics;
+mod migrations;
 mod models;
-use std::net::SocketAddr;
+mod password;
+mod preview;
+mod probe;
+mod qr;
+mod rate_limit;
+mod rules;
+mod shutdown;
+mod state;
+mod store;
+mod tasks;
+mod telemetry;
+mod validation;
+mod variants;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Application entry point.
 ///
 /// This function:
-/// 1. Loads environment variables from `.env` file
-/// 2. Establishes database connection
-/// 3. Initializes the database schema
-/// 4. Sets up HTTP routes
-/// 5. Starts the Axum server on localhost:3000
+/// 1. Parses command line arguments and loads environment variables from `.env` file
+/// 2. Merges the config file, the environment and the flags into a [`Config`]
+///    and installs the log subscriber
+/// 3. Establishes database connection and wraps it in a [`MeteredStore`]
+/// 4. Applies pending schema migrations (and exits here with `--migrate-only`
+///    or after running an admin subcommand such as `keys create`)
+/// 5. Spawns the background purge, probe and blocklist reload tasks and the
+///    click analytics writer
+/// 6. Sets up HTTP routes
+/// 7. Starts the Axum server on the configured bind address
+/// 8. On `SIGTERM` or `Ctrl-C`, drains the open connections, stops the
+///    background tasks, writes the queued clicks and closes the database
+///    (see [`shutdown`])
 ///
 /// # Panics
 ///
 /// Panics if:
-/// - `DATABASE_URL` environment variable is not set
-/// - Database connection fails
-/// - Database initialization fails
-/// - Server binding fails
+/// - The configuration is invalid (see [`Config::load`]), e.g. no database
+///   URL is set or a setting cannot be parsed
+/// - Database connection fails, or the URL scheme is not supported
+/// - A migration fails, or the database schema is newer than this binary
+/// - The GeoIP database or the blocklist file cannot be read
+/// - An admin subcommand fails
+/// - Server binding fails, or the server fails before shutdown
 ///
-/// # Environment Variables
+/// # Configuration
 ///
-/// - `DATABASE_URL`: SQLite database connection string (required)
-///   Example: `sqlite://urls.db`
+/// Every setting can be given in a TOML file, as an environment variable or
+/// (for the server, code and database settings) as a flag. See
+/// [`config`] for the full list, for example:
+///
+/// - `DATABASE_URL` / `--database-url`: Database connection string; the scheme
+///   selects the backend (required)
+///   Example: `sqlite://urls.db`, `postgres://user@localhost/urls` (with the
+///   `postgres` feature) or `memory:`
+/// - `BIND_ADDRESS` / `--bind`: Address to listen on
+///   Default: `127.0.0.1:3000`
+/// - `PUBLIC_BASE_URL` / `--base-url`: Public URL of the service, used to build
+///   short URLs and to reject redirect loops
+///   Default: `http://localhost:3000`
+/// - `SHORT_CODE_STRATEGY`: `random`, `counter` or `obfuscated` (with
+///   `SHORT_CODE_SECRET`)
+///   Default: `random`
+/// - `SHORT_CODE_LENGTH` / `SHORT_CODE_ALPHABET`: Shape of generated codes
+///   Default: 7 characters from `A-Za-z0-9_-`
+/// - `IP_HASH_SALT`: Secret salt for hashing client IPs
+///   Default: a random salt per process, so unique visitors reset on restart
+/// - `SHUTDOWN_TIMEOUT_SECS`: Time in-flight requests get to finish after
+///   `SIGTERM` or `Ctrl-C`
+///   Default: 30 seconds
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
     dotenv().ok();
-    let db_url = env::var("DATABASE_URL").expect("Database url must be set in dotenv file");
+    let config =
+        Config::load(&cli.config).unwrap_or_else(|err| panic!("Invalid configuration: {err}"));
+    telemetry::init(config.log_format, &config.log_filter).unwrap_or_else(|err| panic!("{err}"));
 
-    let database = db::db_connection(&db_url).await.unwrap();
-    db::init_db(&database)
+    let store = store::connect(&config.database)
+        .await
+        .unwrap_or_else(|err| panic!("Failed to connect to database: {err}"));
+    let schema_version = store
+        .migrate()
         .await
-        .expect("Failed to create database table!");
-    println!("Database is Ready!");
+        .unwrap_or_else(|err| panic!("Failed to migrate database: {err}"));
+    tracing::info!("Database is Ready! (schema version {schema_version})");
 
-    let app = Router::new()
-        .route("/", get(handlers::root_handler))
-        .route("/shorten", post(handlers::shorten_handler))
-        .route("/{short_code}", get(handlers::redirect_handler))
-        .with_state(database);
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
+    match cli.command {
+        None | Some(Command::Serve) => {}
+        Some(command) => {
+            let result = cli::run(store.as_ref(), &config, cli.output, command).await;
+            store.close().await;
+            if let Err(err) = result {
+                eprintln!("Error: {err}");
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+    if cli.migrate_only {
+        return;
+    }
+
+    let metrics = Arc::new(Metrics::new());
+    let store: Arc<dyn LinkStore> = Arc::new(MeteredStore::new(store, metrics.clone()));
+
+    let mut background_tasks = vec![tasks::spawn_purge_task(
+        store.clone(),
+        Duration::from_secs(config.purge_interval_secs),
+    )];
+
+    if config.probe_interval_secs > 0 {
+        let prober = Prober::new(Duration::from_secs(config.probe_timeout_secs))
+            .unwrap_or_else(|err| panic!("{err}"));
+        background_tasks.push(tasks::spawn_probe_task(
+            store.clone(),
+            prober,
+            Duration::from_secs(config.probe_interval_secs),
+            Duration::from_secs(config.probe_recheck_after_secs),
+            config.probe_batch_size,
+        ));
+    }
+
+    let blocklist = match &config.blocklist_file {
+        Some(path) => {
+            let blocklist = Arc::new(Blocklist::open(path).unwrap_or_else(|err| panic!("{err}")));
+            tracing::info!(
+                "Loaded blocklist {} ({} entries)",
+                path.display(),
+                blocklist.len()
+            );
+            background_tasks.push(tasks::spawn_blocklist_reload_task(
+                blocklist.clone(),
+                Duration::from_secs(config.blocklist_reload_interval_secs),
+            ));
+            blocklist
+        }
+        None => Arc::new(Blocklist::disabled()),
+    };
+
+    let ip_hash_salt = config.ip_hash_salt.clone().unwrap_or_else(|| {
+        tracing::warn!("IP_HASH_SALT not set, unique visitor counts will reset on restart");
+        nanoid!(32)
+    });
+    let (clicks, click_writer) = analytics::spawn_click_writer(store.clone(), &ip_hash_salt);
+
+    let link_cache = LinkCache::new(
+        config.cache_capacity,
+        Duration::from_secs(config.cache_ttl_secs),
+    );
+
+    let geoip = match &config.geoip_database {
+        Some(path) => GeoIp::open(path).unwrap_or_else(|err| panic!("{err}")),
+        None => GeoIp::disabled(),
+    };
+
+    let state = AppState {
+        store: store.clone(),
+        clicks,
+        link_cache,
+        base_url: config.base_url.clone(),
+        codes: codes::from_config(&config.codes),
+        rate_limits: RateLimiters {
+            shorten: RateLimiter::new(config.shorten_limit),
+            redirect: RateLimiter::new(config.redirect_limit),
+            api: RateLimiter::new(config.api_limit),
+            password: RateLimiter::new(config.password_limit),
+        },
+        geoip: Arc::new(geoip),
+        blocklist,
+        metrics,
+    };
 
-    axum::serve(tokio::net::TcpListener::bind(addr).await.unwrap(), app)
+    let app = app_router(state);
+    let listener = tokio::net::TcpListener::bind(config.bind_address)
         .await
-        .unwrap();
+        .unwrap_or_else(|err| panic!("Failed to bind {}: {err}", config.bind_address));
+    tracing::info!(
+        "Listening on {} (short URLs use {})",
+        listener.local_addr().unwrap_or(config.bind_address),
+        config.base_url
+    );
+
+    shutdown::serve(
+        listener,
+        app,
+        Duration::from_secs(config.shutdown_timeout_secs),
+        shutdown::signal(),
+    )
+    .await
+    .unwrap_or_else(|err| panic!("Server error: {err}"));
+
+    for task in background_tasks {
+        task.abort();
+    }
+    click_writer.shutdown().await;
+    store.close().await;
+    tracing::info!("Shutdown complete");
+}
+
+/// Builds the application router.
+///
+/// Link creation and management routes sit behind the
+/// [`auth::require_api_key`] layer. The readiness check, the metrics and
+/// redirects are public.
+///
+/// Each route group has its own [`rate_limit::limit`] layer: `shorten` and
+/// `api` run after authentication and count requests per API key, `redirect`
+/// counts them per client IP. The readiness check and the metrics are not
+/// limited.
+///
+/// Every route, including rejected requests, goes through
+/// [`telemetry::track_request`], which assigns the request id and records
+/// the request metrics.
+fn app_router(state: AppState) -> Router {
+    let limits = &state.rate_limits;
+    let shorten = Router::new()
+        .route("/shorten", post(handlers::shorten_handler))
+        .route(
+            "/api/links/bulk",
+            post(handlers::bulk_shorten_handler)
+                .layer(DefaultBodyLimit::max(handlers::BULK_BODY_LIMIT)),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            limits.shorten.clone(),
+            rate_limit::limit,
+        ));
+
+    let api = Router::new()
+        .route("/api/links", get(handlers::list_links_handler))
+        .route("/api/links/export", get(handlers::export_links_handler))
+        .route(
+            "/api/links/{code}",
+            get(handlers::get_link_handler)
+                .patch(handlers::update_link_handler)
+                .delete(handlers::delete_link_handler),
+        )
+        .route("/api/links/{code}/stats", get(handlers::stats_handler))
+        .route(
+            "/api/links/{code}/rules",
+            get(handlers::get_link_rules_handler).put(handlers::replace_link_rules_handler),
+        )
+        .route(
+            "/api/links/{code}/variants",
+            get(handlers::get_link_variants_handler).put(handlers::replace_link_variants_handler),
+        )
+        .route("/api/cache/stats", get(handlers::cache_stats_handler))
+        .route_layer(middleware::from_fn_with_state(
+            limits.api.clone(),
+            rate_limit::limit,
+        ));
+
+    let authenticated = shorten
+        .merge(api)
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_api_key,
+        ));
+
+    let redirects = Router::new()
+        .route(
+            "/{short_code}",
+            get(handlers::redirect_handler).post(handlers::redirect_handler),
+        )
+        .route("/{short_code}/qr", get(handlers::qr_handler))
+        .route("/{short_code}/unlock", post(handlers::unlock_handler))
+        .route_layer(middleware::from_fn_with_state(
+            limits.redirect.clone(),
+            rate_limit::limit,
+        ));
+
+    let metrics = state.metrics.clone();
+    Router::new()
+        .route("/", get(handlers::root_handler))
+        .route("/metrics", get(handlers::metrics_handler))
+        .merge(redirects)
+        .merge(authenticated)
+        .layer(middleware::from_fn_with_state(
+            metrics,
+            telemetry::track_request,
+        ))
+        .with_state(state)
 }
@@ -0,0 +1,298 @@
+//! Prometheus metrics.
+//!
+//! [`Metrics`] holds the counters and histograms of the service, and
+//! `GET /metrics` renders them in the Prometheus text format:
+//!
+//! | Metric | Type | Labels |
+//! |--------|------|--------|
+//! | `http_requests_total` | counter | `method`, `route`, `status` |
+//! | `http_request_duration_seconds` | histogram | `method`, `route` |
+//! | `db_query_duration_seconds` | histogram | `operation` |
+//! | `redirects_total` | counter | `status` |
+//! | `link_cache_hits_total` / `link_cache_misses_total` | counter | |
+//! | `link_cache_entries` | gauge | |
+//! | `db_pool_connections` | gauge | `state` (`idle` or `in_use`) |
+//! | `db_pool_max_connections` | gauge | |
+//!
+//! `route` is the route pattern (e.g. `/api/links/{code}`) rather than the
+//! request path, so short codes do not each create a time series.
+//! `operation` is the name of the [`LinkStore`](crate::store::LinkStore)
+//! method, timed by [`MeteredStore`](crate::store::MeteredStore).
+//!
+//! The cache and pool metrics are snapshots taken when the metrics are
+//! rendered; the pool metrics are missing for the in-memory backend.
+//!
+//! # Examples
+//!
+//! ```bash
+//! curl http://localhost:3000/metrics
+//! # http_requests_total{method="GET",route="/{short_code}",status="302"} 1027
+//! # redirects_total{status="302"} 1027
+//! # link_cache_hits_total 912
+//! ```
+
+use crate::{error::AppError, models::CacheStats, store::PoolStats};
+use axum::http::StatusCode;
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+    proto::{Counter, Gauge, LabelPair, Metric, MetricFamily, MetricType},
+};
+use std::time::Duration;
+
+/// `Content-Type` of the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Buckets of `db_query_duration_seconds`, from half a millisecond to a second.
+const DB_QUERY_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+];
+
+/// The metrics registry of the service.
+///
+/// Shared behind an `Arc` by the request middleware, the handlers and the
+/// [`MeteredStore`](crate::store::MeteredStore).
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    db_query_duration: HistogramVec,
+    redirects: IntCounterVec,
+}
+
+impl Metrics {
+    /// Creates and registers every metric, all starting at zero.
+    pub fn new() -> Self {
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled."),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric definition");
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to answer HTTP requests.",
+            ),
+            &["method", "route"],
+        )
+        .expect("valid metric definition");
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "db_query_duration_seconds",
+                "Time taken by storage operations.",
+            )
+            .buckets(DB_QUERY_BUCKETS.to_vec()),
+            &["operation"],
+        )
+        .expect("valid metric definition");
+        let redirects = IntCounterVec::new(
+            Opts::new("redirects_total", "Visitors redirected to a link target."),
+            &["status"],
+        )
+        .expect("valid metric definition");
+
+        let registry = Registry::new();
+        for collector in [
+            Box::new(http_requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(http_request_duration.clone()),
+            Box::new(db_query_duration.clone()),
+            Box::new(redirects.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metric names are unique");
+        }
+
+        Metrics {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_query_duration,
+            redirects,
+        }
+    }
+
+    /// Records an answered HTTP request.
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - The request method, e.g. `GET`
+    /// * `route` - The matched route pattern, e.g. `/api/links/{code}`
+    /// * `status` - The response status
+    /// * `elapsed` - Time from receiving the request to returning the response
+    pub fn observe_request(
+        &self,
+        method: &str,
+        route: &str,
+        status: StatusCode,
+        elapsed: Duration,
+    ) {
+        self.http_requests
+            .with_label_values(&[method, route, status.as_str()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[method, route])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records the duration of a storage operation, e.g. `get_link`.
+    pub fn observe_query(&self, operation: &str, elapsed: Duration) {
+        self.db_query_duration
+            .with_label_values(&[operation])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Counts a redirect sent to a visitor with the given status.
+    pub fn count_redirect(&self, status: StatusCode) {
+        self.redirects.with_label_values(&[status.as_str()]).inc();
+    }
+
+    /// Renders every metric in the Prometheus text format.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - Current counters of the redirect cache
+    /// * `pool` - Current counters of the database pool, if the backend has one
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::Internal`] if the metrics cannot be encoded.
+    pub fn render(&self, cache: &CacheStats, pool: Option<PoolStats>) -> Result<String, AppError> {
+        let mut families = self.registry.gather();
+        families.extend([
+            family(
+                "link_cache_hits_total",
+                "Redirects served from the link cache.",
+                MetricType::COUNTER,
+                vec![counter(cache.hits)],
+            ),
+            family(
+                "link_cache_misses_total",
+                "Redirects that had to load the link from the database.",
+                MetricType::COUNTER,
+                vec![counter(cache.misses)],
+            ),
+            family(
+                "link_cache_entries",
+                "Links currently in the link cache.",
+                MetricType::GAUGE,
+                vec![gauge(cache.entries as f64, None)],
+            ),
+        ]);
+        if let Some(pool) = pool {
+            families.extend([
+                family(
+                    "db_pool_connections",
+                    "Open database connections by state.",
+                    MetricType::GAUGE,
+                    vec![
+                        gauge(f64::from(pool.idle), Some(("state", "idle"))),
+                        gauge(
+                            f64::from(pool.size.saturating_sub(pool.idle)),
+                            Some(("state", "in_use")),
+                        ),
+                    ],
+                ),
+                family(
+                    "db_pool_max_connections",
+                    "Largest number of connections the database pool may open.",
+                    MetricType::GAUGE,
+                    vec![gauge(f64::from(pool.max), None)],
+                ),
+            ]);
+        }
+        families.sort_by(|a, b| a.name().cmp(b.name()));
+
+        TextEncoder::new()
+            .encode_to_string(&families)
+            .map_err(|err| AppError::Internal(format!("cannot encode metrics: {err}")))
+    }
+}
+
+/// Builds a metric family from snapshot values.
+fn family(name: &str, help: &str, field_type: MetricType, metrics: Vec<Metric>) -> MetricFamily {
+    let mut family = MetricFamily::default();
+    family.set_name(name.to_string());
+    family.set_help(help.to_string());
+    family.set_field_type(field_type);
+    family.set_metric(metrics);
+    family
+}
+
+fn counter(value: u64) -> Metric {
+    let mut counter = Counter::default();
+    counter.set_value(value as f64);
+    let mut metric = Metric::default();
+    metric.set_counter(counter);
+    metric
+}
+
+fn gauge(value: f64, label: Option<(&str, &str)>) -> Metric {
+    let mut gauge = Gauge::default();
+    gauge.set_value(value);
+    let mut metric = Metric::from_gauge(gauge);
+    if let Some((name, value)) = label {
+        let mut pair = LabelPair::default();
+        pair.set_name(name.to_string());
+        pair.set_value(value.to_string());
+        metric.set_label(vec![pair]);
+    }
+    metric
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_stats() -> CacheStats {
+        CacheStats {
+            hits: 7,
+            misses: 2,
+            entries: 1,
+            capacity: 10,
+            ttl_secs: 60,
+        }
+    }
+
+    #[test]
+    fn renders_recorded_and_snapshot_metrics() {
+        let metrics = Metrics::new();
+        metrics.observe_request(
+            "GET",
+            "/{short_code}",
+            StatusCode::FOUND,
+            Duration::from_millis(3),
+        );
+        metrics.observe_query("get_link", Duration::from_micros(800));
+        metrics.count_redirect(StatusCode::FOUND);
+
+        let pool = PoolStats {
+            size: 3,
+            idle: 2,
+            max: 10,
+        };
+        let text = metrics.render(&cache_stats(), Some(pool)).unwrap();
+        for line in [
+            r#"http_requests_total{method="GET",route="/{short_code}",status="302"} 1"#,
+            r#"http_request_duration_seconds_count{method="GET",route="/{short_code}"} 1"#,
+            r#"db_query_duration_seconds_bucket{operation="get_link",le="0.0005"} 0"#,
+            r#"db_query_duration_seconds_bucket{operation="get_link",le="0.001"} 1"#,
+            r#"redirects_total{status="302"} 1"#,
+            "# TYPE link_cache_hits_total counter",
+            "link_cache_hits_total 7",
+            "link_cache_misses_total 2",
+            "link_cache_entries 1",
+            r#"db_pool_connections{state="idle"} 2"#,
+            r#"db_pool_connections{state="in_use"} 1"#,
+            "db_pool_max_connections 10",
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line} missing in\n{text}");
+        }
+    }
+
+    #[test]
+    fn omits_pool_metrics_without_a_pool() {
+        let text = Metrics::new().render(&cache_stats(), None).unwrap();
+        assert!(text.contains("link_cache_entries 1"));
+        assert!(!text.contains("db_pool"));
+    }
+}
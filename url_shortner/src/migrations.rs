@@ -0,0 +1,257 @@
+//! Versioned schema migrations.
+//!
+//! The schema is built up by an ordered list of [`MIGRATIONS`]. Each one is
+//! applied exactly once, inside its own transaction, and recorded in the
+//! `schema_version` table. Adding a column therefore means adding a new
+//! migration file instead of editing an existing `CREATE TABLE`, so existing
+//! `urls.db` files keep working.
+//!
+//! # Adding a Migration
+//!
+//! 1. Create `migrations/NNNN_description.sql` with the next version number
+//! 2. Append a [`Migration`] entry for it to [`MIGRATIONS`]
+//!
+//! Never edit a migration that has already been released.
+//!
+//! # Version Table
+//!
+//! ```sql
+//! CREATE TABLE IF NOT EXISTS schema_version (
+//!     version INTEGER PRIMARY KEY,
+//!     name TEXT NOT NULL,
+//!     applied_at INTEGER NOT NULL
+//! );
+//! ```
+
+use crate::error::AppError;
+use sqlx::{Executor, SqlitePool};
+
+/// A single schema change.
+pub struct Migration {
+    /// Strictly increasing version number.
+    pub version: i64,
+    /// Short description, matching the SQL file name.
+    pub name: &'static str,
+    /// The SQL statements to execute. May contain several statements.
+    pub sql: &'static str,
+}
+
+/// Every migration known to this binary, in the order they must be applied.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_links",
+        sql: include_str!("../migrations/0001_create_links.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "link_expiry",
+        sql: include_str!("../migrations/0002_link_expiry.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "create_clicks",
+        sql: include_str!("../migrations/0003_create_clicks.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "link_dedup",
+        sql: include_str!("../migrations/0004_link_dedup.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "api_keys",
+        sql: include_str!("../migrations/0005_api_keys.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "link_created_at",
+        sql: include_str!("../migrations/0006_link_created_at.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "link_interstitial",
+        sql: include_str!("../migrations/0007_link_interstitial.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "link_redirect_type",
+        sql: include_str!("../migrations/0008_link_redirect_type.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "link_password",
+        sql: include_str!("../migrations/0009_link_password.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "link_rules",
+        sql: include_str!("../migrations/0010_link_rules.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "link_variants",
+        sql: include_str!("../migrations/0011_link_variants.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "link_health",
+        sql: include_str!("../migrations/0012_link_health.sql"),
+    },
+];
+
+/// SQL query to create the table that tracks applied migrations.
+const CREATE_SCHEMA_VERSION_SQL: &str = "
+    CREATE TABLE IF NOT EXISTS schema_version (
+        version INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        applied_at INTEGER NOT NULL
+    )
+";
+
+/// Returns the newest schema version this binary knows about.
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Returns the schema version currently recorded in the database.
+///
+/// # Returns
+///
+/// Returns `0` for a database that has never been migrated.
+///
+/// # Errors
+///
+/// Returns an error if the `schema_version` table cannot be created or read.
+pub async fn current_version(pool: &SqlitePool) -> Result<i64, AppError> {
+    sqlx::query(CREATE_SCHEMA_VERSION_SQL).execute(pool).await?;
+
+    let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(version.unwrap_or(0))
+}
+
+/// Applies every pending migration in order.
+///
+/// Each migration runs in its own transaction together with the insert into
+/// `schema_version`, so a failing migration leaves the database at the
+/// previous version.
+///
+/// # Arguments
+///
+/// * `pool` - Reference to the SQLite connection pool
+///
+/// # Returns
+///
+/// Returns the versions that were applied by this call (empty if the schema
+/// was already up to date).
+///
+/// # Errors
+///
+/// Returns an error if:
+/// - The database schema is newer than this binary ([`AppError::Internal`]);
+///   the service refuses to start rather than run against an unknown schema
+/// - A migration fails to execute
+pub async fn run(pool: &SqlitePool) -> Result<Vec<i64>, AppError> {
+    let current = current_version(pool).await?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(AppError::Internal(format!(
+            "database schema version {current} is newer than this binary supports ({latest})"
+        )));
+    }
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let mut tx = pool.begin().await?;
+        tx.execute(sqlx::raw_sql(migration.sql)).await?;
+        sqlx::query("INSERT INTO schema_version (version, name, applied_at) VALUES (?, ?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(chrono::Utc::now().timestamp())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        tracing::info!(
+            "Applied migration {:04}_{}",
+            migration.version,
+            migration.name
+        );
+        applied.push(migration.version);
+    }
+
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn empty_pool() -> SqlitePool {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn versions_are_strictly_increasing() {
+        assert!(
+            MIGRATIONS
+                .windows(2)
+                .all(|pair| pair[0].version < pair[1].version)
+        );
+    }
+
+    #[tokio::test]
+    async fn applies_all_migrations_once() {
+        let pool = empty_pool().await;
+
+        assert_eq!(run(&pool).await.unwrap().len(), MIGRATIONS.len());
+        assert!(run(&pool).await.unwrap().is_empty());
+        assert_eq!(current_version(&pool).await.unwrap(), latest_version());
+    }
+
+    #[tokio::test]
+    async fn upgrades_a_pre_migration_database() {
+        let pool = empty_pool().await;
+        sqlx::raw_sql(MIGRATIONS[0].sql)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO links (short_code, long_url) VALUES ('old', 'https://a.example')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        run(&pool).await.unwrap();
+
+        let click_count: i64 =
+            sqlx::query_scalar("SELECT click_count FROM links WHERE short_code = 'old'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(click_count, 0);
+    }
+
+    #[tokio::test]
+    async fn refuses_newer_database() {
+        let pool = empty_pool().await;
+        run(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO schema_version (version, name, applied_at) VALUES (?, 'future', 0)",
+        )
+        .bind(latest_version() + 1)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert!(matches!(run(&pool).await, Err(AppError::Internal(_))));
+    }
+}
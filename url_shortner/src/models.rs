@@ -3,10 +3,13 @@
 //! This module defines the data structures used for:
 //! - Deserializing incoming JSON requests
 //! - Serializing outgoing JSON responses
+//! - Mapping rows of the `links` table
 //!
 //! All models use Serde for automatic JSON serialization/deserialization.
 
-use serde::{Deserialize, Serialize};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
 
 /// Request payload for creating a new short URL.
 ///
@@ -16,12 +19,25 @@ use serde::{Deserialize, Serialize};
 /// # Fields
 ///
 /// * `url` - The long URL to be shortened
+/// * `alias` - Optional custom short code (vanity URL)
+/// * `expires_at` - Optional RFC 3339 timestamp after which the link is gone
+/// * `max_clicks` - Optional number of redirects after which the link is gone
+/// * `dedup` - Reuse an existing short code for the same URL instead of creating a new one
+/// * `interstitial` - Show a preview page before every redirect
+/// * `redirect_type` - HTTP status of the redirect: 301, 302 (default), 307 or 308
+/// * `password` - Optional password visitors must enter before being redirected
 ///
 /// # JSON Format
 ///
 /// ```json
 /// {
-///   "url": "https://www.example.com/very/long/path"
+///   "url": "https://www.example.com/very/long/path",
+///   "alias": "launch2026",
+///   "expires_at": "2026-12-31T23:59:59Z",
+///   "max_clicks": 1000,
+///   "dedup": true,
+///   "interstitial": false,
+///   "redirect_type": 301
 /// }
 /// ```
 ///
@@ -38,9 +54,111 @@ use serde::{Deserialize, Serialize};
 pub struct CreateLinkRequest {
     /// The original long URL that needs to be shortened.
     ///
-    /// Should be a valid URL starting with http:// or https://.
-    /// No validation is currently performed on the URL format.
+    /// Must be an absolute http:// or https:// URL that does not point back
+    /// at the shortener. It is normalized by
+    /// [`normalize_url`](crate::validation::normalize_url) before being stored.
+    /// `long_url` is accepted as well, so exported links can be imported.
+    #[serde(alias = "long_url")]
     pub url: String,
+
+    /// Optional custom short code to use instead of a generated one.
+    ///
+    /// Must pass [`validate_alias`](crate::validation::validate_alias):
+    /// 3-32 characters from `[A-Za-z0-9_-]` and not a reserved word.
+    /// A 409 Conflict is returned if the alias is already taken.
+    /// `short_code` is accepted as well, so exported links keep their code.
+    #[serde(default, alias = "short_code")]
+    pub alias: Option<String>,
+
+    /// Optional point in time after which redirects return 410 Gone.
+    ///
+    /// Must be in the future when the link is created.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Optional number of redirects after which the link returns 410 Gone.
+    ///
+    /// Must be at least 1.
+    #[serde(default)]
+    pub max_clicks: Option<i64>,
+
+    /// Opt-in deduplication.
+    ///
+    /// When `true`, and another deduplicated link already points at the same
+    /// normalized URL, that link's code is returned instead of a new one.
+    #[serde(default)]
+    pub dedup: bool,
+
+    /// Show the preview page instead of redirecting right away, so visitors
+    /// see the destination before they follow the link.
+    #[serde(default)]
+    pub interstitial: bool,
+
+    /// HTTP status code of the redirect; see [`RedirectType`].
+    #[serde(default)]
+    pub redirect_type: RedirectType,
+
+    /// Optional password that visitors must enter on a form before they are
+    /// redirected.
+    ///
+    /// Must pass [`validate_password`](crate::validation::validate_password).
+    /// Only its Argon2 hash is stored.
+    #[serde(default)]
+    pub password: Option<String>,
+
+    /// Set by exports (see [`LinkResponse`]). A `true` without a `password`
+    /// is rejected, so an imported link never loses its protection.
+    #[serde(default)]
+    pub password_protected: bool,
+}
+
+/// One data row of a CSV body sent to `POST /api/links/bulk`.
+///
+/// Columns are matched by header name; only `url` is required and empty
+/// cells count as missing. The columns of an export are understood too
+/// (`long_url`, `short_code`, `password_protected`; the rest is ignored).
+///
+/// ```text
+/// url,alias,expires_at,max_clicks,dedup,interstitial,redirect_type,password
+/// https://www.rust-lang.org/,rust,,,,,301,
+/// https://docs.rs/,,2030-01-01T00:00:00Z,100,true,true,,hunter22
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct CsvLinkRow {
+    #[serde(alias = "long_url")]
+    pub url: String,
+    #[serde(default, alias = "short_code")]
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub max_clicks: Option<i64>,
+    #[serde(default)]
+    pub dedup: Option<bool>,
+    #[serde(default)]
+    pub interstitial: Option<bool>,
+    #[serde(default)]
+    pub redirect_type: Option<RedirectType>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub password_protected: Option<bool>,
+}
+
+impl From<CsvLinkRow> for CreateLinkRequest {
+    fn from(row: CsvLinkRow) -> Self {
+        CreateLinkRequest {
+            url: row.url,
+            alias: row.alias,
+            expires_at: row.expires_at,
+            max_clicks: row.max_clicks,
+            dedup: row.dedup.unwrap_or(false),
+            interstitial: row.interstitial.unwrap_or(false),
+            redirect_type: row.redirect_type.unwrap_or_default(),
+            password: row.password,
+            password_protected: row.password_protected.unwrap_or(false),
+        }
+    }
 }
 
 /// Response payload when a short URL is successfully created.
@@ -50,38 +168,984 @@ pub struct CreateLinkRequest {
 ///
 /// # Fields
 ///
-/// * `short_url` - The generated 7-character short code
+/// * `short_code` - The generated short code, or the requested alias
+/// * `short_url` - The full short URL: the public base URL followed by the code
+/// * `created` - `false` when an existing link was reused through `dedup`
 ///
 /// # JSON Format
 ///
 /// ```json
 /// {
-///   "short_url": "kN3pL4m"
+///   "short_code": "kN3pL4m",
+///   "short_url": "https://sho.rt/kN3pL4m",
+///   "created": true
 /// }
 /// ```
 ///
-/// # Usage
-///
-/// The client should construct the full short URL by appending
-/// this code to the base URL:
-/// ```text
-/// http://localhost:3000/{short_url}
-/// ```
-///
 /// # Examples
 ///
 /// ```
 /// use serde_json::json;
 ///
 /// let response = json!({
-///     "short_url": "abc1234"
+///     "short_code": "abc1234",
+///     "short_url": "http://localhost:3000/abc1234",
+///     "created": true
 /// });
 /// ```
 #[derive(Serialize)]
 pub struct CreateLinkResponse {
-    /// The generated short code (nanoid-generated, 7 characters).
+    /// The short code (randomly generated, or the custom alias).
     ///
     /// This is a URL-safe string that uniquely identifies the shortened URL.
-    /// Append this to the service's base URL to create the complete short URL.
+    pub short_code: String,
+
+    /// The fully qualified short URL, built from the configured public base
+    /// URL (see [`Config::base_url`](crate::config::Config::base_url)).
+    pub short_url: String,
+
+    /// Whether a new link was created (`true`) or an existing one was reused
+    /// because the request asked for `dedup` (`false`).
+    pub created: bool,
+}
+
+/// Outcome of inserting one link through
+/// [`LinkStore::create_short_links`](crate::store::LinkStore::create_short_links).
+///
+/// The store does not know the public base URL, so handlers turn this into a
+/// [`CreateLinkResponse`].
+#[derive(Debug)]
+pub struct CreatedLink {
+    /// The short code of the new or reused link.
+    pub short_code: String,
+    /// `false` when an existing link was reused through its dedup key.
+    pub created: bool,
+}
+
+/// A link that is about to be inserted into the `links` table.
+///
+/// Built by the handlers from a validated [`CreateLinkRequest`] and passed
+/// to [`LinkStore::create_short_link`](crate::store::LinkStore::create_short_link).
+#[derive(Clone, Default)]
+pub struct NewLink {
+    /// Row id reserved for the link by
+    /// [`LinkStore::reserve_link_ids`](crate::store::LinkStore::reserve_link_ids),
+    /// or `None` to let the database pick the next one.
+    pub id: Option<i64>,
+    /// The unique short code: the custom alias, or empty until
+    /// [`codes::assign_codes`](crate::codes::assign_codes) generates one.
+    pub short_code: String,
+    /// The destination URL.
+    pub long_url: String,
+    /// Optional expiry as a Unix timestamp in seconds.
+    pub expires_at: Option<i64>,
+    /// Optional maximum number of redirects.
+    pub max_clicks: Option<i64>,
+    /// Unique deduplication key, set only when the client asked for `dedup`.
+    pub dedup_key: Option<String>,
+    /// The API key that created the link.
+    pub owner_id: Option<i64>,
+    /// Whether redirects show the preview page first.
+    pub interstitial: bool,
+    /// HTTP status code of the redirect.
+    pub redirect_type: RedirectType,
+    /// Argon2 hash of the link's password, if it has one.
+    pub password_hash: Option<String>,
+}
+
+/// A row of the `links` table.
+///
+/// # Fields
+///
+/// * `id` - Auto-incrementing primary key, used as the pagination cursor
+/// * `short_code` - The unique short code
+/// * `long_url` - The destination URL
+/// * `expires_at` - Optional expiry as a Unix timestamp in seconds
+/// * `max_clicks` - Optional maximum number of redirects
+/// * `click_count` - Number of redirects served so far
+/// * `owner_id` - The API key that created the link (`None` for legacy links)
+/// * `created_at` - Unix timestamp (seconds) of creation (`None` for legacy links)
+/// * `interstitial` - Whether redirects show the preview page first
+/// * `redirect_type` - HTTP status code of the redirect, stored as the number
+/// * `password_hash` - Argon2 hash (PHC string) of the password visitors must
+///   enter, if the link is password protected
+/// * `has_rules` - Whether the link has [`LinkRule`]s (computed, not a column)
+/// * `has_variants` - Whether the link has [`LinkVariant`]s (computed, not a column)
+/// * `health` - Result of the latest probe of `long_url` (see [`crate::probe`])
+/// * `health_checked_at` - Unix timestamp (seconds) of the latest probe
+/// * `health_detail` - Why the latest probe failed, e.g. `HTTP 404`
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Link {
+    pub id: i64,
+    pub short_code: String,
+    pub long_url: String,
+    pub expires_at: Option<i64>,
+    pub max_clicks: Option<i64>,
+    pub click_count: i64,
+    pub owner_id: Option<i64>,
+    pub created_at: Option<i64>,
+    pub interstitial: bool,
+    #[sqlx(try_from = "i32")]
+    pub redirect_type: RedirectType,
+    pub password_hash: Option<String>,
+    pub has_rules: bool,
+    pub has_variants: bool,
+    #[sqlx(try_from = "String")]
+    pub health: LinkHealth,
+    pub health_checked_at: Option<i64>,
+    pub health_detail: Option<String>,
+}
+
+impl Link {
+    /// Returns `true` once the link has passed its expiry time or used up
+    /// its click limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current Unix timestamp in seconds
+    pub fn is_expired(&self, now: i64) -> bool {
+        let time_expired = self.expires_at.is_some_and(|expires_at| expires_at <= now);
+        let clicks_exhausted = self
+            .max_clicks
+            .is_some_and(|max_clicks| self.click_count >= max_clicks);
+
+        time_expired || clicks_exhausted
+    }
+
+    /// Returns `true` if visitors have to enter a password before they are
+    /// redirected.
+    pub fn is_password_protected(&self) -> bool {
+        self.password_hash.is_some()
+    }
+
+    /// Returns `true` if the link belongs to the given API key.
+    ///
+    /// Legacy links without an owner are not owned by any key.
+    pub fn is_owned_by(&self, api_key: &ApiKey) -> bool {
+        self.owner_id == Some(api_key.id)
+    }
+}
+
+/// HTTP status code a link redirects with.
+///
+/// Permanent redirects (301, 308) tell browsers and search engines that the
+/// short URL stands for the target for good, so they may cache the redirect;
+/// temporary ones (302, 307) are followed through the shortener every time.
+/// 307 and 308 keep the request method and body, so a form posted to the
+/// short URL is posted to the target as well. In JSON, CSV and the database
+/// the type is the bare status code, e.g. `"redirect_type": 308`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "i32", into = "i32")]
+pub enum RedirectType {
+    /// `301 Moved Permanently`; clients may turn a POST into a GET.
+    MovedPermanently,
+    /// `302 Found`; clients may turn a POST into a GET.
+    #[default]
+    Found,
+    /// `307 Temporary Redirect`; keeps the method and body.
+    TemporaryRedirect,
+    /// `308 Permanent Redirect`; keeps the method and body.
+    PermanentRedirect,
+}
+
+impl RedirectType {
+    /// The HTTP status of the redirect response.
+    pub fn status(self) -> StatusCode {
+        match self {
+            RedirectType::MovedPermanently => StatusCode::MOVED_PERMANENTLY,
+            RedirectType::Found => StatusCode::FOUND,
+            RedirectType::TemporaryRedirect => StatusCode::TEMPORARY_REDIRECT,
+            RedirectType::PermanentRedirect => StatusCode::PERMANENT_REDIRECT,
+        }
+    }
+
+    /// Returns `true` for 301 and 308, which clients may cache.
+    pub fn is_permanent(self) -> bool {
+        matches!(
+            self,
+            RedirectType::MovedPermanently | RedirectType::PermanentRedirect
+        )
+    }
+}
+
+impl TryFrom<i32> for RedirectType {
+    type Error = String;
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            301 => Ok(RedirectType::MovedPermanently),
+            302 => Ok(RedirectType::Found),
+            307 => Ok(RedirectType::TemporaryRedirect),
+            308 => Ok(RedirectType::PermanentRedirect),
+            _ => Err(format!(
+                "redirect_type must be 301, 302, 307 or 308, got {code}"
+            )),
+        }
+    }
+}
+
+impl From<RedirectType> for i32 {
+    fn from(redirect_type: RedirectType) -> Self {
+        i32::from(redirect_type.status().as_u16())
+    }
+}
+
+/// Whether the destination of a link still works, as found by the link
+/// health probe (see [`crate::probe`]).
+///
+/// Stored as text in `links.health`; in JSON and in the `health` filter of
+/// `GET /api/links` the snake case name is used, e.g. `"redirect_loop"`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkHealth {
+    /// Not probed yet, or the URL changed since the latest probe.
+    #[default]
+    Unchecked,
+    /// The destination answered (possibly after redirects).
+    Ok,
+    /// The destination is unreachable or answered 404, 410 or a server error.
+    Dead,
+    /// The destination's redirects go in a circle or never end.
+    RedirectLoop,
+}
+
+impl LinkHealth {
+    /// The name used in JSON and in `links.health`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkHealth::Unchecked => "unchecked",
+            LinkHealth::Ok => "ok",
+            LinkHealth::Dead => "dead",
+            LinkHealth::RedirectLoop => "redirect_loop",
+        }
+    }
+}
+
+impl TryFrom<String> for LinkHealth {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        match name.as_str() {
+            "unchecked" => Ok(LinkHealth::Unchecked),
+            "ok" => Ok(LinkHealth::Ok),
+            "dead" => Ok(LinkHealth::Dead),
+            "redirect_loop" => Ok(LinkHealth::RedirectLoop),
+            _ => Err(format!("unknown link health '{name}'")),
+        }
+    }
+}
+
+/// Result of one health probe of a link's destination, stored by
+/// [`LinkStore::set_link_health`](crate::store::LinkStore::set_link_health).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheck {
+    pub health: LinkHealth,
+    /// Why the probe failed, e.g. `HTTP 404`; `None` for healthy links.
+    pub detail: Option<String>,
+}
+
+/// Operating system of a visitor, detected from the `User-Agent` header by
+/// [`rules::detect_platform`](crate::rules::detect_platform).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    /// iPhone, iPad and iPod.
+    Ios,
+    /// Android phones and tablets.
+    Android,
+    Windows,
+    Macos,
+    /// Desktop Linux (Android is reported as `android`).
+    Linux,
+}
+
+impl Platform {
+    /// The name used in JSON and in `link_rules.platform`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Platform::Ios => "ios",
+            Platform::Android => "android",
+            Platform::Windows => "windows",
+            Platform::Macos => "macos",
+            Platform::Linux => "linux",
+        }
+    }
+}
+
+/// A row of the `link_rules` table: an alternative target of a link.
+///
+/// A rule applies when every condition it sets matches the visitor; unset
+/// conditions match anyone. The rules of a link are tried in order (the
+/// `position` column) and the first one that applies wins (see [`crate::rules`]).
+///
+/// # JSON Format
+///
+/// ```json
+/// { "platform": "ios", "language": null, "country": null,
+///   "target_url": "https://apps.apple.com/app/id123" }
+/// ```
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct LinkRule {
+    /// Platform name (see [`Platform::as_str`]).
+    pub platform: Option<String>,
+    /// Language tag compared with the visitor's preferred language, e.g. `de`
+    /// or `pt-br`.
+    pub language: Option<String>,
+    /// ISO 3166-1 alpha-2 country code, e.g. `DE`.
+    pub country: Option<String>,
+    /// Where matching visitors are redirected.
+    pub target_url: String,
+}
+
+/// A validated rule about to be stored by
+/// [`LinkStore::replace_link_rules`](crate::store::LinkStore::replace_link_rules).
+#[derive(Debug, Clone, Default)]
+pub struct NewLinkRule {
+    pub platform: Option<Platform>,
+    /// Lowercase language tag.
+    pub language: Option<String>,
+    /// Uppercase country code.
+    pub country: Option<String>,
+    /// Normalized target URL.
+    pub target_url: String,
+}
+
+/// One rule of `PUT /api/links/{code}/rules`.
+///
+/// At least one of `platform`, `language` and `country` must be set.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LinkRuleRequest {
+    /// `ios`, `android`, `windows`, `macos` or `linux`.
+    #[serde(default)]
+    pub platform: Option<Platform>,
+    /// Language tag such as `de` (matches `de`, `de-CH`, ...) or `pt-BR`.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// ISO 3166-1 alpha-2 country code such as `DE`; needs `GEOIP_DATABASE`.
+    #[serde(default)]
+    pub country: Option<String>,
+    /// Where matching visitors are redirected; validated like a long URL.
+    pub target_url: String,
+}
+
+/// Request payload of `PUT /api/links/{code}/rules`, replacing every rule of
+/// the link. An empty list removes all rules.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "rules": [
+///     { "platform": "ios", "target_url": "https://apps.apple.com/app/id123" },
+///     { "platform": "android", "target_url": "https://play.google.com/store/apps/details?id=com.example" },
+///     { "language": "de", "country": "AT", "target_url": "https://example.com/de-at" }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct LinkRulesRequest {
+    pub rules: Vec<LinkRuleRequest>,
+}
+
+/// Response payload of `GET` and `PUT /api/links/{code}/rules`.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "short_code": "app",
+///   "rules": [
+///     { "platform": "ios", "language": null, "country": null,
+///       "target_url": "https://apps.apple.com/app/id123" }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Serialize)]
+pub struct LinkRulesResponse {
+    pub short_code: String,
+    /// The rules in evaluation order.
+    pub rules: Vec<LinkRule>,
+}
+
+/// A row of the `link_variants` table: one destination of an A/B split.
+///
+/// Visitors of a link with variants are spread across them in proportion to
+/// their `weight` and kept on the same variant by a cookie (see
+/// [`crate::variants`]).
+///
+/// # JSON Format
+///
+/// ```json
+/// { "name": "b", "target_url": "https://example.com/landing-b", "weight": 30 }
+/// ```
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct LinkVariant {
+    /// Name of the variant, unique per link; reported in the stats.
+    pub name: String,
+    /// Where visitors assigned to the variant are redirected.
+    pub target_url: String,
+    /// Relative share of new visitors.
+    pub weight: i64,
+}
+
+/// A validated variant about to be stored by
+/// [`LinkStore::replace_link_variants`](crate::store::LinkStore::replace_link_variants).
+#[derive(Debug, Clone)]
+pub struct NewLinkVariant {
+    pub name: String,
+    /// Normalized target URL.
+    pub target_url: String,
+    pub weight: i64,
+}
+
+/// One variant of `PUT /api/links/{code}/variants`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LinkVariantRequest {
+    /// Letters, digits, `-` and `_`, e.g. `control` or `b`.
+    pub name: String,
+    /// Where the variant's visitors are redirected; validated like a long URL.
+    pub target_url: String,
+    /// Relative share of new visitors, 1 to 1000.
+    pub weight: i64,
+}
+
+/// Request payload of `PUT /api/links/{code}/variants`, replacing every
+/// variant of the link. An empty list ends the split.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "variants": [
+///     { "name": "a", "target_url": "https://example.com/landing-a", "weight": 70 },
+///     { "name": "b", "target_url": "https://example.com/landing-b", "weight": 30 }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct LinkVariantsRequest {
+    pub variants: Vec<LinkVariantRequest>,
+}
+
+/// Response payload of `GET` and `PUT /api/links/{code}/variants`.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "short_code": "spring",
+///   "variants": [
+///     { "name": "a", "target_url": "https://example.com/landing-a", "weight": 70 },
+///     { "name": "b", "target_url": "https://example.com/landing-b", "weight": 30 }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Serialize)]
+pub struct LinkVariantsResponse {
+    pub short_code: String,
+    pub variants: Vec<LinkVariant>,
+}
+
+/// A single redirect, queued for the analytics writer.
+///
+/// # Fields
+///
+/// * `short_code` - The code that was followed
+/// * `clicked_at` - Unix timestamp (seconds) of the redirect
+/// * `referrer` - Value of the `Referer` header, if any
+/// * `user_agent` - Value of the `User-Agent` header, if any
+/// * `ip_hash` - Salted hash of the client IP (see [`crate::analytics::hash_ip`])
+/// * `counted` - Whether `links.click_count` was already incremented synchronously
+///   (links with a `max_clicks` limit); otherwise the writer increments it
+/// * `variant` - Name of the [`LinkVariant`] the visitor was sent to, if any
+#[derive(Debug, Clone)]
+pub struct ClickEvent {
+    pub short_code: String,
+    pub clicked_at: i64,
+    pub referrer: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip_hash: Option<String>,
+    pub counted: bool,
+    pub variant: Option<String>,
+}
+
+/// Response payload of `GET /api/links/{code}/stats`.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "short_code": "kN3pL4m",
+///   "total_clicks": 42,
+///   "unique_visitors": 17,
+///   "daily": [{ "date": "2026-10-18", "clicks": 42 }],
+///   "top_referrers": [{ "referrer": "https://news.ycombinator.com/", "clicks": 30 }],
+///   "variants": [{ "variant": "a", "clicks": 29 }, { "variant": "b", "clicks": 13 }]
+/// }
+/// ```
+#[derive(Debug, Serialize)]
+pub struct LinkStats {
+    /// The short code the statistics belong to.
+    pub short_code: String,
+    /// Number of recorded redirects.
+    pub total_clicks: i64,
+    /// Number of distinct hashed client IPs.
+    pub unique_visitors: i64,
+    /// Clicks per UTC day, oldest first.
+    pub daily: Vec<DailyClicks>,
+    /// Most frequent referrers, most clicks first.
+    pub top_referrers: Vec<ReferrerCount>,
+    /// Clicks per A/B variant, by variant name; empty for links that never
+    /// had variants.
+    pub variants: Vec<VariantClicks>,
+}
+
+/// Number of clicks sent to one A/B variant.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct VariantClicks {
+    /// The variant's name at the time of the clicks.
+    pub variant: String,
+    pub clicks: i64,
+}
+
+/// Number of clicks on a single UTC day.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct DailyClicks {
+    /// Day in `YYYY-MM-DD` format.
+    pub date: String,
+    /// Clicks on that day.
+    pub clicks: i64,
+}
+
+/// Number of clicks coming from a single referrer.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ReferrerCount {
+    /// The `Referer` header value.
+    pub referrer: String,
+    /// Clicks with that referrer.
+    pub clicks: i64,
+}
+
+/// A row of the `api_keys` table (without the key hash).
+///
+/// # Fields
+///
+/// * `id` - Auto-incrementing primary key, stored as `links.owner_id`
+/// * `name` - Human readable label given on creation
+/// * `created_at` - Unix timestamp (seconds) of creation
+/// * `revoked_at` - Unix timestamp (seconds) of revocation, if revoked
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub created_at: i64,
+    pub revoked_at: Option<i64>,
+}
+
+/// A link as returned by the management API.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "short_code": "kN3pL4m",
+///   "long_url": "https://www.rust-lang.org/",
+///   "created_at": "2026-10-18T09:30:00Z",
+///   "expires_at": null,
+///   "max_clicks": 100,
+///   "click_count": 42,
+///   "interstitial": false,
+///   "redirect_type": 302,
+///   "password_protected": false,
+///   "health": "dead",
+///   "health_checked_at": "2026-10-18T10:00:00Z",
+///   "health_detail": "HTTP 404"
+/// }
+/// ```
+#[derive(Debug, Serialize)]
+pub struct LinkResponse {
+    pub short_code: String,
+    pub long_url: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_clicks: Option<i64>,
+    pub click_count: i64,
+    pub interstitial: bool,
+    pub redirect_type: RedirectType,
+    pub password_protected: bool,
+    pub health: LinkHealth,
+    pub health_checked_at: Option<DateTime<Utc>>,
+    pub health_detail: Option<String>,
+}
+
+impl From<Link> for LinkResponse {
+    fn from(link: Link) -> Self {
+        LinkResponse {
+            password_protected: link.is_password_protected(),
+            short_code: link.short_code,
+            long_url: link.long_url,
+            created_at: link
+                .created_at
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            expires_at: link
+                .expires_at
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            max_clicks: link.max_clicks,
+            click_count: link.click_count,
+            interstitial: link.interstitial,
+            redirect_type: link.redirect_type,
+            health: link.health,
+            health_checked_at: link
+                .health_checked_at
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            health_detail: link.health_detail,
+        }
+    }
+}
+
+/// Where a link goes, returned by `GET /{short_code}+` and by redirects that
+/// ask for `Accept: application/json`, instead of redirecting.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "short_code": "kN3pL4m",
+///   "short_url": "http://localhost:3000/kN3pL4m",
+///   "long_url": "https://www.rust-lang.org/",
+///   "created_at": "2026-10-18T09:30:00Z",
+///   "expires_at": null,
+///   "max_clicks": 100,
+///   "click_count": 42,
+///   "interstitial": false,
+///   "redirect_type": 302
+/// }
+/// ```
+#[derive(Debug, Serialize)]
+pub struct LinkPreview {
+    pub short_code: String,
     pub short_url: String,
+    pub long_url: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_clicks: Option<i64>,
+    pub click_count: i64,
+    pub interstitial: bool,
+    pub redirect_type: RedirectType,
+}
+
+impl LinkPreview {
+    /// Builds the preview of `link`, reachable at `short_url`.
+    pub fn new(link: Link, short_url: String) -> Self {
+        let link = LinkResponse::from(link);
+        LinkPreview {
+            short_code: link.short_code,
+            short_url,
+            long_url: link.long_url,
+            created_at: link.created_at,
+            expires_at: link.expires_at,
+            max_clicks: link.max_clicks,
+            click_count: link.click_count,
+            interstitial: link.interstitial,
+            redirect_type: link.redirect_type,
+        }
+    }
+}
+
+/// Query parameters of `GET /{short_code}`.
+#[derive(Debug, Deserialize)]
+pub struct RedirectQuery {
+    /// Present (e.g. `?go=1`) to skip the preview page of an interstitial
+    /// link; set by the page's "Continue" button.
+    pub go: Option<String>,
+}
+
+/// Form body of `POST /{short_code}/unlock`, sent by the password form.
+#[derive(Deserialize)]
+pub struct UnlockForm {
+    /// The password entered by the visitor.
+    pub password: String,
+}
+
+/// Query parameters of `GET /api/links`.
+///
+/// # Fields
+///
+/// * `cursor` - `next_cursor` of the previous page; omit for the first page
+/// * `limit` - Page size (default 50, at most 200)
+/// * `q` - Only return links whose short code or long URL contains this text
+/// * `health` - Only return links whose latest probe had this result, e.g.
+///   `dead` or `redirect_loop`
+#[derive(Debug, Deserialize)]
+pub struct ListLinksQuery {
+    pub cursor: Option<i64>,
+    pub limit: Option<i64>,
+    pub q: Option<String>,
+    pub health: Option<LinkHealth>,
+}
+
+/// Response payload of `GET /api/links`.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "links": [{ "short_code": "kN3pL4m", "long_url": "https://www.rust-lang.org/", ... }],
+///   "next_cursor": 42
+/// }
+/// ```
+#[derive(Debug, Serialize)]
+pub struct ListLinksResponse {
+    /// The links on this page, oldest first.
+    pub links: Vec<LinkResponse>,
+    /// Cursor for the next page, or `null` on the last page.
+    pub next_cursor: Option<i64>,
+}
+
+/// Request payload of `PATCH /api/links/{code}`.
+///
+/// Every field is optional; omitted fields are left unchanged. `expires_at`
+/// and `max_clicks` can be set to `null` to remove the limit, and `password`
+/// to `null` to remove the password protection.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "url": "https://www.rust-lang.org/learn",
+///   "expires_at": null,
+///   "max_clicks": 500,
+///   "interstitial": true,
+///   "redirect_type": 308,
+///   "password": null
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct UpdateLinkRequest {
+    /// New destination URL, validated like on creation.
+    pub url: Option<String>,
+    /// New expiry time; `null` removes it.
+    #[serde(default, deserialize_with = "double_option")]
+    pub expires_at: Option<Option<DateTime<Utc>>>,
+    /// New click limit; `null` removes it.
+    #[serde(default, deserialize_with = "double_option")]
+    pub max_clicks: Option<Option<i64>>,
+    /// Turns the preview page before redirects on or off.
+    pub interstitial: Option<bool>,
+    /// New HTTP status code of the redirect.
+    pub redirect_type: Option<RedirectType>,
+    /// New password; `null` removes the password protection.
+    #[serde(default, deserialize_with = "double_option")]
+    pub password: Option<Option<String>>,
+}
+
+/// A validated change to an existing link, applied by
+/// [`LinkStore::update_link`](crate::store::LinkStore::update_link).
+///
+/// `None` leaves a column unchanged; `Some(None)` sets it to `NULL`.
+#[derive(Debug, Default)]
+pub struct LinkUpdate {
+    pub long_url: Option<String>,
+    /// Replacement dedup key for the new URL. Only applied to links that were
+    /// created with deduplication, so other links stay out of the index.
+    pub dedup_key: Option<String>,
+    pub expires_at: Option<Option<i64>>,
+    pub max_clicks: Option<Option<i64>>,
+    pub interstitial: Option<bool>,
+    pub redirect_type: Option<RedirectType>,
+    /// Replacement password hash; `Some(None)` removes the password.
+    pub password_hash: Option<Option<String>>,
+}
+
+/// Outcome of one row of `POST /api/links/bulk`.
+///
+/// Either `short_code` and `short_url`, or `error` is set.
+///
+/// # JSON Format
+///
+/// ```json
+/// { "row": 1, "short_code": "kN3pL4m", "short_url": "https://sho.rt/kN3pL4m", "created": true }
+/// { "row": 2, "error": "short code 'launch' already exists" }
+/// ```
+#[derive(Debug, Serialize)]
+pub struct BulkLinkResult {
+    /// 1-based position of the row in the request (CSV header not counted).
+    pub row: usize,
+    /// The short code, if the row succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_code: Option<String>,
+    /// The full short URL, if the row succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_url: Option<String>,
+    /// Whether the link was created or reused through `dedup`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<bool>,
+    /// Why the row failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Response payload of `POST /api/links/bulk`.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "succeeded": 1,
+///   "failed": 1,
+///   "results": [
+///     { "row": 1, "short_code": "kN3pL4m", "short_url": "https://sho.rt/kN3pL4m", "created": true },
+///     { "row": 2, "error": "url must use http or https" }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Serialize)]
+pub struct BulkResponse {
+    /// Number of rows that produced a short code (created or reused).
+    pub succeeded: usize,
+    /// Number of rows that failed.
+    pub failed: usize,
+    /// One entry per request row, in request order.
+    pub results: Vec<BulkLinkResult>,
+}
+
+/// File format of `GET /api/links/export`.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Comma separated values with a header row.
+    #[default]
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+/// Query parameters of `GET /api/links/export`.
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// `csv` (default) or `jsonl`.
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Image format of `GET /{short_code}/qr`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrFormat {
+    /// Black and white PNG image.
+    #[default]
+    Png,
+    /// Scalable SVG image.
+    Svg,
+}
+
+/// Error correction level of a QR code: the share of the code that can be
+/// damaged or covered and still be read.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QrErrorCorrection {
+    /// About 7% can be restored.
+    L,
+    /// About 15% can be restored.
+    #[default]
+    M,
+    /// About 25% can be restored.
+    Q,
+    /// About 30% can be restored; use it when a logo covers the code.
+    H,
+}
+
+/// Query parameters of `GET /{short_code}/qr`.
+///
+/// # Fields
+///
+/// * `size` - Width and height of the image in pixels (default 256)
+/// * `ec` - Error correction level: `l`, `m` (default), `q` or `h`
+/// * `format` - `png` (default) or `svg`
+#[derive(Debug, Deserialize)]
+pub struct QrQuery {
+    pub size: Option<u32>,
+    #[serde(default)]
+    pub ec: QrErrorCorrection,
+    #[serde(default)]
+    pub format: QrFormat,
+}
+
+/// Counters of the redirect cache, returned by `GET /api/cache/stats`.
+///
+/// # JSON Format
+///
+/// ```json
+/// {
+///   "hits": 9120,
+///   "misses": 880,
+///   "entries": 312,
+///   "capacity": 10000,
+///   "ttl_secs": 60
+/// }
+/// ```
+#[derive(Debug, Default, Serialize)]
+pub struct CacheStats {
+    /// Redirects served from the cache since startup.
+    pub hits: u64,
+    /// Redirects that had to query the database since startup.
+    pub misses: u64,
+    /// Links currently cached.
+    pub entries: usize,
+    /// Maximum number of cached links (`0` when the cache is disabled).
+    pub capacity: usize,
+    /// How long a link is served from the cache before it is reloaded.
+    pub ttl_secs: u64,
+}
+
+/// Deserializes a field that distinguishes "absent" from "null".
+///
+/// Used with `#[serde(default)]`: an absent field stays `None`, an explicit
+/// `null` becomes `Some(None)`.
+fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_request_distinguishes_null_from_absent() {
+        let update: UpdateLinkRequest =
+            serde_json::from_str(r#"{"expires_at": null, "max_clicks": 5}"#).unwrap();
+        assert_eq!(update.expires_at, Some(None));
+        assert_eq!(update.max_clicks, Some(Some(5)));
+        assert!(update.url.is_none());
+
+        let empty: UpdateLinkRequest = serde_json::from_str("{}").unwrap();
+        assert!(empty.expires_at.is_none());
+        assert!(empty.max_clicks.is_none());
+    }
+
+    #[test]
+    fn redirect_type_is_the_status_code() {
+        let request: CreateLinkRequest =
+            serde_json::from_str(r#"{"url": "https://docs.rs/", "redirect_type": 308}"#).unwrap();
+        assert_eq!(request.redirect_type, RedirectType::PermanentRedirect);
+        assert_eq!(
+            serde_json::to_string(&RedirectType::MovedPermanently).unwrap(),
+            "301"
+        );
+
+        let default: CreateLinkRequest =
+            serde_json::from_str(r#"{"url": "https://docs.rs/"}"#).unwrap();
+        assert_eq!(default.redirect_type, RedirectType::Found);
+
+        let invalid = serde_json::from_str::<CreateLinkRequest>(
+            r#"{"url": "https://docs.rs/", "redirect_type": 303}"#,
+        );
+        assert!(invalid.is_err());
+    }
 }
@@ -0,0 +1,128 @@
+//! Password-protected links.
+//!
+//! A link created with a `password` stores only its Argon2 hash (a PHC
+//! string in `links.password_hash`). Visiting such a link shows the form of
+//! [`render_form`] instead of redirecting; the form posts the password to
+//! `POST /{short_code}/unlock`, which redirects once the password matches.
+//!
+//! Failed attempts are limited per short code and client IP by the
+//! `password` [`RateLimiter`](crate::rate_limit::RateLimiter).
+//!
+//! Argon2 is deliberately slow, so hashing and verification run on the
+//! blocking thread pool instead of stalling the async workers.
+
+use crate::{error::AppError, preview::escape_html};
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+
+/// Hashes a link password with Argon2id and a random salt.
+///
+/// # Returns
+///
+/// Returns the PHC string, e.g. `$argon2id$v=19$m=19456,t=2,p=1$...`.
+///
+/// # Errors
+///
+/// Returns [`AppError::Internal`] if hashing fails.
+pub async fn hash(password: String) -> Result<String, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|err| AppError::Internal(format!("cannot hash password: {err}")))
+    })
+    .await
+    .map_err(|err| AppError::Internal(format!("password hashing task failed: {err}")))?
+}
+
+/// Checks `password` against a hash produced by [`hash`].
+///
+/// # Returns
+///
+/// Returns `Ok(true)` if the password matches.
+///
+/// # Errors
+///
+/// Returns [`AppError::Internal`] if the stored hash cannot be parsed.
+pub async fn verify(password: String, password_hash: String) -> Result<bool, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let parsed = PasswordHash::new(&password_hash)
+            .map_err(|err| AppError::Internal(format!("invalid password hash: {err}")))?;
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok())
+    })
+    .await
+    .map_err(|err| AppError::Internal(format!("password check task failed: {err}")))?
+}
+
+/// Renders the password form of a protected link.
+///
+/// # Arguments
+///
+/// * `short_url` - The full short URL; the form posts to `{short_url}/unlock`
+/// * `failed` - Whether to tell the visitor that the last password was wrong
+pub fn render_form(short_url: &str, failed: bool) -> String {
+    let action = escape_html(&format!("{short_url}/unlock"));
+    let short_url = escape_html(short_url);
+    let message = if failed {
+        r#"<p class="error" role="alert">Wrong password, please try again.</p>"#
+    } else {
+        ""
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>Password required</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 28rem; margin: 4rem auto; padding: 0 1rem; color: #222; }}
+input {{ font-size: 1rem; padding: 0.6rem; width: 100%; box-sizing: border-box; margin: 0.5rem 0 1rem; }}
+button {{ padding: 0.7rem 1.4rem; background: #1f6feb; color: #fff; border: 0; border-radius: 6px; font-size: 1rem; }}
+.error {{ color: #b42318; }}
+</style>
+</head>
+<body>
+<main>
+<h1>Password required</h1>
+<p>The link {short_url} is password protected.</p>
+{message}
+<form method="post" action="{action}">
+<label for="password">Password</label>
+<input id="password" name="password" type="password" autocomplete="current-password" required autofocus>
+<button type="submit">Continue</button>
+</form>
+</main>
+</body>
+</html>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn verifies_only_the_right_password() {
+        let hash = hash("hunter22".to_string()).await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify("hunter22".to_string(), hash.clone()).await.unwrap());
+        assert!(!verify("hunter23".to_string(), hash).await.unwrap());
+    }
+
+    #[test]
+    fn form_posts_to_the_unlock_route() {
+        let page = render_form("http://localhost:3000/kN3pL4m", true);
+        assert!(page.contains(r#"action="http://localhost:3000/kN3pL4m/unlock""#));
+        assert!(page.contains("Wrong password"));
+        assert!(!render_form("http://localhost:3000/kN3pL4m", false).contains("Wrong password"));
+    }
+}
@@ -0,0 +1,129 @@
+//! HTML preview page of a link.
+//!
+//! `GET /{short_code}+` shows where a short link goes without redirecting,
+//! and links flagged as `interstitial` show the same page before every
+//! redirect. The page is a single self-contained HTML document; every value
+//! taken from the link is HTML-escaped, since long URLs are user input.
+
+use crate::models::LinkPreview;
+use chrono::{DateTime, Utc};
+
+/// Renders the preview page of a link.
+///
+/// The "Continue" button points at the short URL with `?go=1`, which
+/// redirects even for interstitial links and counts the click.
+pub fn render_page(preview: &LinkPreview) -> String {
+    let short_url = escape_html(&preview.short_url);
+    let long_url = escape_html(&preview.long_url);
+    let continue_url = escape_html(&format!("{}?go=1", preview.short_url));
+    let created = preview
+        .created_at
+        .map_or_else(|| "unknown".to_string(), format_time);
+    let expiry = escape_html(&describe_expiry(preview.expires_at, preview.max_clicks));
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>Preview of {short_url}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 40rem; margin: 4rem auto; padding: 0 1rem; color: #222; }}
+.target {{ font-size: 1.2rem; word-break: break-all; padding: 1rem; background: #f4f4f4; border-radius: 6px; }}
+dl {{ display: grid; grid-template-columns: max-content 1fr; gap: 0.4rem 1rem; }}
+dt {{ font-weight: 600; }}
+.continue {{ display: inline-block; margin-top: 1.5rem; padding: 0.7rem 1.4rem; background: #1f6feb; color: #fff; border-radius: 6px; text-decoration: none; }}
+</style>
+</head>
+<body>
+<main>
+<h1>This link goes to</h1>
+<p class="target">{long_url}</p>
+<dl>
+<dt>Short URL</dt><dd>{short_url}</dd>
+<dt>Created</dt><dd>{created}</dd>
+<dt>Clicks</dt><dd>{clicks}</dd>
+<dt>Expires</dt><dd>{expiry}</dd>
+</dl>
+<a class="continue" href="{continue_url}" rel="noreferrer">Continue</a>
+</main>
+</body>
+</html>
+"#,
+        clicks = preview.click_count,
+    )
+}
+
+/// Describes when a link stops redirecting, e.g. `after 100 clicks`.
+fn describe_expiry(expires_at: Option<DateTime<Utc>>, max_clicks: Option<i64>) -> String {
+    match (expires_at, max_clicks) {
+        (None, None) => "never".to_string(),
+        (Some(expires_at), None) => format_time(expires_at),
+        (None, Some(max_clicks)) => format!("after {max_clicks} clicks"),
+        (Some(expires_at), Some(max_clicks)) => format!(
+            "{} or after {max_clicks} clicks, whichever comes first",
+            format_time(expires_at)
+        ),
+    }
+}
+
+/// Formats a time as `YYYY-MM-DD HH:MM UTC`.
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+/// Escapes the characters that are special in HTML text and attributes.
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::RedirectType;
+
+    fn preview(long_url: &str) -> LinkPreview {
+        LinkPreview {
+            short_code: "kN3pL4m".to_string(),
+            short_url: "http://localhost:3000/kN3pL4m".to_string(),
+            long_url: long_url.to_string(),
+            created_at: DateTime::from_timestamp(1_790_000_000, 0),
+            expires_at: None,
+            max_clicks: Some(100),
+            click_count: 42,
+            interstitial: true,
+            redirect_type: RedirectType::Found,
+        }
+    }
+
+    #[test]
+    fn shows_target_and_continue_link() {
+        let page = render_page(&preview("https://www.rust-lang.org/?a=1&b=2"));
+        assert!(page.contains("https://www.rust-lang.org/?a=1&amp;b=2"));
+        assert!(page.contains(r#"href="http://localhost:3000/kN3pL4m?go=1""#));
+        assert!(page.contains("<dd>42</dd>"));
+        assert!(page.contains("after 100 clicks"));
+    }
+
+    #[test]
+    fn escapes_user_input() {
+        let page = render_page(&preview(
+            "https://evil.example/\"><script>alert(1)</script>",
+        ));
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("&quot;&gt;&lt;script&gt;"));
+    }
+}
@@ -0,0 +1,335 @@
+//! Link health probing.
+//!
+//! The probe task (see [`crate::tasks::spawn_probe_task`]) periodically sends
+//! a `HEAD` request to the `long_url` of every link and stores the outcome in
+//! `links.health`:
+//!
+//! | Health | When |
+//! |--------|------|
+//! | `ok` | The destination answered, after at most [`MAX_REDIRECTS`] redirects |
+//! | `dead` | The connection failed or timed out, or the answer was 404, 410 or a 5xx error |
+//! | `redirect_loop` | A redirect led back to a URL of the chain, or the chain was too long |
+//!
+//! Other client errors (401, 403, 405, 429, ...) count as `ok`: the server is
+//! there, it just does not let the prober in. Links whose destination was
+//! never probed come first; after that each link is probed again once its
+//! result is older than the recheck interval. `GET /api/links?health=dead`
+//! lists the broken links of an API key.
+
+use crate::{
+    error::AppError,
+    models::{HealthCheck, LinkHealth},
+    store::LinkStore,
+};
+use futures_util::{StreamExt, stream};
+use reqwest::{StatusCode, header, redirect};
+use std::{collections::HashSet, time::Duration};
+use url::Url;
+
+/// Default interval between two probe runs, in seconds.
+pub const DEFAULT_INTERVAL_SECS: u64 = 60 * 60;
+
+/// Default age after which a probe result is refreshed, in seconds (a day).
+pub const DEFAULT_RECHECK_AFTER_SECS: u64 = 24 * 60 * 60;
+
+/// Default number of links probed per run.
+pub const DEFAULT_BATCH_SIZE: i64 = 100;
+
+/// Default timeout of one probe request, in seconds.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// Longest redirect chain a healthy destination may have.
+pub const MAX_REDIRECTS: usize = 10;
+
+/// Number of links probed at the same time.
+const CONCURRENCY: usize = 8;
+
+/// `User-Agent` of probe requests, so site owners can tell them apart.
+const USER_AGENT: &str = concat!("url_shortner-link-check/", env!("CARGO_PKG_VERSION"));
+
+/// HTTP client that probes destinations.
+pub struct Prober {
+    client: reqwest::Client,
+}
+
+/// Outcome of one probe run.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ProbeSummary {
+    /// Number of links probed.
+    pub probed: usize,
+    /// Number of links found `dead` or in a `redirect_loop`.
+    pub unhealthy: usize,
+}
+
+impl Prober {
+    /// Creates a prober whose requests give up after `timeout`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::Internal`] if the HTTP client cannot be built
+    /// (e.g. the TLS backend fails to initialize).
+    pub fn new(timeout: Duration) -> Result<Self, AppError> {
+        let client = reqwest::Client::builder()
+            .redirect(redirect::Policy::none())
+            .timeout(timeout)
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|err| AppError::Internal(format!("cannot build HTTP client: {err}")))?;
+        Ok(Prober { client })
+    }
+
+    /// Probes a destination, following its redirects one by one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let check = prober.probe("https://www.rust-lang.org/").await;
+    /// assert_eq!(check.health, LinkHealth::Ok);
+    /// ```
+    pub async fn probe(&self, url: &str) -> HealthCheck {
+        let dead = |detail: String| HealthCheck {
+            health: LinkHealth::Dead,
+            detail: Some(detail),
+        };
+        let Ok(mut current) = Url::parse(url) else {
+            return dead(format!("invalid url '{url}'"));
+        };
+
+        let mut visited = HashSet::new();
+        for _ in 0..=MAX_REDIRECTS {
+            if !visited.insert(current.clone()) {
+                return HealthCheck {
+                    health: LinkHealth::RedirectLoop,
+                    detail: Some(format!("redirects back to {current}")),
+                };
+            }
+
+            let response = match self.client.head(current.clone()).send().await {
+                Ok(response) => response,
+                Err(err) if err.is_timeout() => return dead("request timed out".to_string()),
+                Err(err) if err.is_connect() => return dead("connection failed".to_string()),
+                Err(err) => return dead(format!("request failed: {}", err.without_url())),
+            };
+
+            let status = response.status();
+            if !status.is_redirection() {
+                let is_dead = matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE)
+                    || status.is_server_error();
+                return if is_dead {
+                    dead(format!("HTTP {}", status.as_u16()))
+                } else {
+                    HealthCheck {
+                        health: LinkHealth::Ok,
+                        detail: None,
+                    }
+                };
+            }
+
+            let Some(location) = response.headers().get(header::LOCATION) else {
+                return dead(format!(
+                    "HTTP {} without a Location header",
+                    status.as_u16()
+                ));
+            };
+            let next = location
+                .to_str()
+                .ok()
+                .and_then(|location| current.join(location).ok());
+            let Some(next) = next else {
+                return dead(format!("HTTP {} with an invalid Location", status.as_u16()));
+            };
+            // App links (e.g. `itms-apps:`) cannot be followed; the redirect
+            // itself shows the destination is there.
+            if !matches!(next.scheme(), "http" | "https") {
+                return HealthCheck {
+                    health: LinkHealth::Ok,
+                    detail: None,
+                };
+            }
+            current = next;
+        }
+
+        HealthCheck {
+            health: LinkHealth::RedirectLoop,
+            detail: Some(format!("more than {MAX_REDIRECTS} redirects")),
+        }
+    }
+}
+
+/// Probes one batch of links that are due and stores the results.
+///
+/// # Arguments
+///
+/// * `store` - The storage backend holding the links
+/// * `prober` - The HTTP client used for the probes
+/// * `now` - Current Unix timestamp in seconds, stored as `health_checked_at`
+/// * `recheck_after` - Seconds after which a probe result is refreshed
+/// * `batch_size` - Maximum number of links to probe
+///
+/// # Errors
+///
+/// Returns an error if the links cannot be loaded or a result cannot be
+/// stored.
+pub async fn probe_due_links(
+    store: &dyn LinkStore,
+    prober: &Prober,
+    now: i64,
+    recheck_after: i64,
+    batch_size: i64,
+) -> Result<ProbeSummary, AppError> {
+    let links = store
+        .links_due_for_probe(now.saturating_sub(recheck_after), batch_size)
+        .await?;
+
+    let results: Vec<_> = stream::iter(links)
+        .map(|link| async move {
+            let check = prober.probe(&link.long_url).await;
+            (link, check)
+        })
+        .buffer_unordered(CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut summary = ProbeSummary::default();
+    for (link, check) in results {
+        store
+            .set_link_health(link.id, &link.long_url, &check, now)
+            .await?;
+        summary.probed += 1;
+        if check.health != LinkHealth::Ok {
+            summary.unhealthy += 1;
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::NewLink,
+        store::{LinkStore, MemoryStore},
+    };
+    use axum::{
+        Router,
+        extract::Path,
+        response::{IntoResponse, Redirect},
+        routing::get,
+    };
+
+    /// Starts a stub destination server and returns its base URL.
+    async fn stub_server() -> String {
+        let app = Router::new()
+            .route("/ok", get(|| async { "fine" }))
+            .route("/missing", get(|| async { StatusCode::NOT_FOUND }))
+            .route("/broken", get(|| async { StatusCode::SERVICE_UNAVAILABLE }))
+            .route("/private", get(|| async { StatusCode::FORBIDDEN }))
+            .route("/moved", get(|| async { Redirect::permanent("/ok") }))
+            .route(
+                "/app",
+                get(|| async { Redirect::temporary("itms-apps://app/1") }),
+            )
+            .route("/ping", get(|| async { Redirect::temporary("/pong") }))
+            .route("/pong", get(|| async { Redirect::temporary("/ping") }))
+            .route(
+                "/chain/{step}",
+                get(|Path(step): Path<u32>| async move {
+                    Redirect::temporary(&format!("/chain/{}", step + 1)).into_response()
+                }),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{address}")
+    }
+
+    /// Returns a URL on a port nothing listens on.
+    async fn closed_port_url() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+        format!("http://{address}/")
+    }
+
+    #[tokio::test]
+    async fn classifies_destinations() {
+        let server = stub_server().await;
+        let prober = Prober::new(Duration::from_secs(5)).unwrap();
+
+        let cases = [
+            ("/ok", LinkHealth::Ok, None),
+            ("/moved", LinkHealth::Ok, None),
+            ("/app", LinkHealth::Ok, None),
+            ("/private", LinkHealth::Ok, None),
+            ("/missing", LinkHealth::Dead, Some("HTTP 404".to_string())),
+            ("/broken", LinkHealth::Dead, Some("HTTP 503".to_string())),
+            (
+                "/ping",
+                LinkHealth::RedirectLoop,
+                Some(format!("redirects back to {server}/ping")),
+            ),
+            (
+                "/chain/0",
+                LinkHealth::RedirectLoop,
+                Some(format!("more than {MAX_REDIRECTS} redirects")),
+            ),
+        ];
+        for (path, health, detail) in cases {
+            let check = prober.probe(&format!("{server}{path}")).await;
+            assert_eq!(check, HealthCheck { health, detail }, "{path}");
+        }
+
+        let check = prober.probe(&closed_port_url().await).await;
+        assert_eq!(check.health, LinkHealth::Dead);
+        assert_eq!(check.detail.as_deref(), Some("connection failed"));
+    }
+
+    #[tokio::test]
+    async fn stores_the_results_of_due_links() {
+        let server = stub_server().await;
+        let prober = Prober::new(Duration::from_secs(5)).unwrap();
+        let store = MemoryStore::new();
+        let owner = store.create_api_key("owner", "hash").await.unwrap();
+        for (short_code, path) in [("fine", "/ok"), ("gone", "/missing"), ("loop", "/ping")] {
+            store
+                .create_short_link(&NewLink {
+                    short_code: short_code.to_string(),
+                    long_url: format!("{server}{path}"),
+                    owner_id: Some(owner.id),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        let summary = probe_due_links(&store, &prober, 1_000, 100, 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            summary,
+            ProbeSummary {
+                probed: 3,
+                unhealthy: 2
+            }
+        );
+        assert_eq!(store.get_link("fine").await.unwrap().health, LinkHealth::Ok);
+        let dead = store
+            .list_links(Some(owner.id), None, 10, None, Some(LinkHealth::Dead))
+            .await
+            .unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].short_code, "gone");
+        assert_eq!(dead[0].health_checked_at, Some(1_000));
+
+        // Fresh results are not probed again until they are `recheck_after` old.
+        let summary = probe_due_links(&store, &prober, 1_050, 100, 10)
+            .await
+            .unwrap();
+        assert_eq!(summary.probed, 0);
+        let summary = probe_due_links(&store, &prober, 1_200, 100, 10)
+            .await
+            .unwrap();
+        assert_eq!(summary.probed, 3);
+    }
+}
@@ -0,0 +1,162 @@
+//! QR code rendering for short links.
+//!
+//! `GET /{short_code}/qr` encodes the full short URL with [`render`] and
+//! returns it as a PNG or SVG image, so printed material can link to the
+//! short URL without a separate QR code tool.
+//!
+//! Both formats include the standard quiet zone of four light modules around
+//! the code, which scanners need to find it.
+
+use crate::{
+    error::AppError,
+    models::{QrErrorCorrection, QrFormat},
+};
+use qrcode::{Color, EcLevel, QrCode, render::svg};
+
+/// Image size used when the request does not give one, in pixels.
+pub const DEFAULT_QR_SIZE: u32 = 256;
+
+/// Smallest accepted image size, in pixels.
+pub const MIN_QR_SIZE: u32 = 64;
+
+/// Largest accepted image size, in pixels.
+pub const MAX_QR_SIZE: u32 = 2048;
+
+/// Width of the light border around the code, in modules.
+const QUIET_ZONE: usize = 4;
+
+/// A rendered QR code image.
+pub struct QrImage {
+    /// `Content-Type` of `bytes`.
+    pub content_type: &'static str,
+    /// The encoded image.
+    pub bytes: Vec<u8>,
+}
+
+/// Renders `data` as a QR code image.
+///
+/// # Arguments
+///
+/// * `data` - The text to encode, e.g. `https://sho.rt/kN3pL4m`
+/// * `size` - Width and height in pixels. PNG images have exactly this size
+///   (or the smallest size with one pixel per module, if that is larger);
+///   SVG images are at least this large
+/// * `ec` - Error correction level
+/// * `format` - PNG or SVG
+///
+/// # Errors
+///
+/// Returns [`AppError::BadRequest`] if `size` is outside
+/// [`MIN_QR_SIZE`]..=[`MAX_QR_SIZE`] or `data` does not fit in a QR code at
+/// the requested error correction level, and [`AppError::Internal`] if PNG
+/// encoding fails.
+pub fn render(
+    data: &str,
+    size: u32,
+    ec: QrErrorCorrection,
+    format: QrFormat,
+) -> Result<QrImage, AppError> {
+    if !(MIN_QR_SIZE..=MAX_QR_SIZE).contains(&size) {
+        return Err(AppError::BadRequest(format!(
+            "size must be between {MIN_QR_SIZE} and {MAX_QR_SIZE} pixels"
+        )));
+    }
+
+    let ec_level = match ec {
+        QrErrorCorrection::L => EcLevel::L,
+        QrErrorCorrection::M => EcLevel::M,
+        QrErrorCorrection::Q => EcLevel::Q,
+        QrErrorCorrection::H => EcLevel::H,
+    };
+    let code = QrCode::with_error_correction_level(data, ec_level).map_err(|err| {
+        AppError::BadRequest(format!("cannot encode the short URL as a QR code: {err}"))
+    })?;
+
+    match format {
+        QrFormat::Png => Ok(QrImage {
+            content_type: "image/png",
+            bytes: render_png(&code, size)?,
+        }),
+        QrFormat::Svg => Ok(QrImage {
+            content_type: "image/svg+xml",
+            bytes: code
+                .render::<svg::Color>()
+                .min_dimensions(size, size)
+                .build()
+                .into_bytes(),
+        }),
+    }
+}
+
+/// Draws the code into a grayscale PNG, centred on a `size` x `size` canvas.
+///
+/// Modules are scaled by a whole number of pixels so they stay sharp.
+fn render_png(code: &QrCode, size: u32) -> Result<Vec<u8>, AppError> {
+    let modules = code.width() + 2 * QUIET_ZONE;
+    let scale = (size as usize / modules).max(1);
+    let dimension = (size as usize).max(modules * scale);
+    let offset = (dimension - modules * scale) / 2;
+
+    let colors = code.to_colors();
+    let mut pixels = vec![u8::MAX; dimension * dimension];
+    for (index, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue;
+        }
+        let left = offset + (index % code.width() + QUIET_ZONE) * scale;
+        let top = offset + (index / code.width() + QUIET_ZONE) * scale;
+        for y in top..top + scale {
+            pixels[y * dimension + left..y * dimension + left + scale].fill(0);
+        }
+    }
+
+    let encoding_failed = |err: png::EncodingError| AppError::Internal(err.to_string());
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, dimension as u32, dimension as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(encoding_failed)?;
+    writer.write_image_data(&pixels).map_err(encoding_failed)?;
+    writer.finish().map_err(encoding_failed)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "http://localhost:3000/kN3pL4m";
+
+    #[test]
+    fn png_has_the_requested_size() {
+        let image = render(URL, 300, QrErrorCorrection::M, QrFormat::Png).unwrap();
+        assert_eq!(image.content_type, "image/png");
+
+        let decoder = png::Decoder::new(std::io::Cursor::new(image.bytes));
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        assert_eq!((info.width, info.height), (300, 300));
+    }
+
+    #[test]
+    fn svg_is_an_svg_document() {
+        let image = render(URL, 256, QrErrorCorrection::H, QrFormat::Svg).unwrap();
+        assert_eq!(image.content_type, "image/svg+xml");
+        assert!(String::from_utf8(image.bytes).unwrap().contains("<svg"));
+    }
+
+    #[test]
+    fn higher_error_correction_needs_more_modules() {
+        let low = QrCode::with_error_correction_level(URL, EcLevel::L).unwrap();
+        let high = QrCode::with_error_correction_level(URL, EcLevel::H).unwrap();
+        assert!(high.width() > low.width());
+    }
+
+    #[test]
+    fn rejects_sizes_out_of_range() {
+        for size in [MIN_QR_SIZE - 1, MAX_QR_SIZE + 1] {
+            let result = render(URL, size, QrErrorCorrection::M, QrFormat::Png);
+            assert!(matches!(result, Err(AppError::BadRequest(_))));
+        }
+    }
+}
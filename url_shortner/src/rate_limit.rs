@@ -0,0 +1,412 @@
+//! Per-client rate limiting.
+//!
+//! Every route group (`shorten`, `redirect`, `api`) has its own
+//! [`RateLimiter`], installed with [`limit`] as an Axum middleware layer.
+//! Each client gets a token bucket:
+//!
+//! - The bucket holds up to `burst` tokens and starts full
+//! - Every request takes one token
+//! - Tokens refill continuously at `per_minute / 60` per second
+//!
+//! A request that finds the bucket empty is rejected with
+//! `429 Too Many Requests` and a `Retry-After` header saying when the next
+//! token is available.
+//!
+//! # Client Keys
+//!
+//! Requests that passed [`auth::require_api_key`](crate::auth::require_api_key)
+//! are counted against their API key, so a key shares one budget across all
+//! its clients. Anonymous requests (redirects) are counted against the client
+//! IP address.
+//!
+//! # Time
+//!
+//! Buckets read the time from a [`Clock`], so tests can drive the limiter
+//! with a mocked clock instead of sleeping.
+
+use crate::{error::AppError, models::ApiKey};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Default limit of the `shorten` group (`/shorten`, `/api/links/bulk`).
+pub const DEFAULT_SHORTEN_LIMIT: RateLimit = RateLimit {
+    per_minute: 60,
+    burst: 10,
+};
+
+/// Default limit of the `redirect` group (`/{short_code}`, `/{short_code}/qr`,
+/// `/{short_code}/unlock`).
+pub const DEFAULT_REDIRECT_LIMIT: RateLimit = RateLimit {
+    per_minute: 600,
+    burst: 50,
+};
+
+/// Default limit of the `api` group (the other `/api/...` routes).
+pub const DEFAULT_API_LIMIT: RateLimit = RateLimit {
+    per_minute: 300,
+    burst: 30,
+};
+
+/// Default limit of failed password attempts per link and client IP.
+pub const DEFAULT_PASSWORD_LIMIT: RateLimit = RateLimit {
+    per_minute: 5,
+    burst: 5,
+};
+
+/// Number of tracked clients above which idle buckets are dropped.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Token bucket settings of one route group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Sustained number of requests per minute; `0` disables the limit.
+    pub per_minute: u32,
+    /// Number of requests a client can make at once after being idle.
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// Returns `true` if the limit is switched off.
+    pub fn is_disabled(&self) -> bool {
+        self.per_minute == 0
+    }
+
+    /// Tokens added to a bucket per second.
+    fn refill_per_sec(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+
+    /// Maximum number of tokens in a bucket (at least one).
+    fn capacity(&self) -> f64 {
+        f64::from(self.burst.max(1))
+    }
+}
+
+/// Source of the current time.
+pub trait Clock: Send + Sync {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+}
+
+/// [`Clock`] backed by [`Instant::now`].
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// The token bucket of one client.
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+struct Buckets {
+    by_client: HashMap<String, Bucket>,
+    /// Size at which idle buckets are pruned next.
+    prune_at: usize,
+}
+
+/// Token bucket rate limiter for one route group.
+///
+/// Cheap to clone; all clones share the same buckets.
+#[derive(Clone)]
+pub struct RateLimiter {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    limit: RateLimit,
+    clock: Arc<dyn Clock>,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter that reads the system clock.
+    pub fn new(limit: RateLimit) -> Self {
+        RateLimiter::with_clock(limit, Arc::new(SystemClock))
+    }
+
+    /// Creates a limiter that reads the time from `clock`.
+    pub fn with_clock(limit: RateLimit, clock: Arc<dyn Clock>) -> Self {
+        RateLimiter {
+            inner: Arc::new(Inner {
+                limit,
+                clock,
+                buckets: Mutex::new(Buckets {
+                    by_client: HashMap::new(),
+                    prune_at: PRUNE_THRESHOLD,
+                }),
+            }),
+        }
+    }
+
+    /// Takes a token from the bucket of `client`.
+    ///
+    /// # Errors
+    ///
+    /// Returns how long the client has to wait for the next token if its
+    /// bucket is empty.
+    pub fn check(&self, client: &str) -> Result<(), Duration> {
+        let limit = self.inner.limit;
+        if limit.is_disabled() {
+            return Ok(());
+        }
+
+        let now = self.inner.clock.now();
+        let mut buckets = self
+            .inner
+            .buckets
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if buckets.by_client.len() >= buckets.prune_at {
+            buckets.prune(limit, now);
+        }
+
+        let bucket = buckets
+            .by_client
+            .entry(client.to_string())
+            .or_insert(Bucket {
+                tokens: limit.capacity(),
+                updated_at: now,
+            });
+        bucket.refill(limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / limit.refill_per_sec();
+            Err(Duration::from_secs_f64(wait))
+        }
+    }
+
+    /// Drops the bucket of `client`, giving it a full burst again.
+    ///
+    /// Used to stop counting attempts that turned out to be legitimate,
+    /// e.g. a correct password.
+    pub fn forget(&self, client: &str) {
+        self.inner
+            .buckets
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .by_client
+            .remove(client);
+    }
+}
+
+impl Bucket {
+    /// Adds the tokens earned since the last update.
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.refill_per_sec()).min(limit.capacity());
+        self.updated_at = now;
+    }
+}
+
+impl Buckets {
+    /// Drops the buckets that have refilled completely; a full bucket behaves
+    /// exactly like a missing one.
+    fn prune(&mut self, limit: RateLimit, now: Instant) {
+        self.by_client.retain(|_, bucket| {
+            bucket.refill(limit, now);
+            bucket.tokens < limit.capacity()
+        });
+        self.prune_at = (self.by_client.len() * 2).max(PRUNE_THRESHOLD);
+    }
+}
+
+/// The rate limiters of all route groups.
+#[derive(Clone)]
+pub struct RateLimiters {
+    /// Link creation: `POST /shorten` and `POST /api/links/bulk`.
+    pub shorten: RateLimiter,
+    /// Public redirects, QR codes and password forms: `/{short_code}`,
+    /// `GET /{short_code}/qr` and `POST /{short_code}/unlock`.
+    pub redirect: RateLimiter,
+    /// The remaining authenticated `/api/...` routes.
+    pub api: RateLimiter,
+    /// Password attempts on protected links, keyed by short code and client
+    /// IP; applied by the unlock handler rather than as a layer.
+    pub password: RateLimiter,
+}
+
+/// Middleware that rejects clients that exceeded the limiter's rate.
+///
+/// Install it with `middleware::from_fn_with_state(limiter, limit)`. On
+/// authenticated routes it must run after
+/// [`auth::require_api_key`](crate::auth::require_api_key), so requests are
+/// counted against their API key.
+///
+/// # Errors
+///
+/// Returns [`AppError::TooManyRequests`] (429, with `Retry-After`) if the
+/// client's bucket is empty.
+pub async fn limit(
+    State(limiter): State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    limiter
+        .check(&client_key(&request))
+        .map_err(AppError::TooManyRequests)?;
+    Ok(next.run(request).await)
+}
+
+/// Returns the key a request is counted against: its API key if it was
+/// authenticated, otherwise the client IP.
+fn client_key(request: &Request) -> String {
+    if let Some(api_key) = request.extensions().get::<ApiKey>() {
+        return format!("key:{}", api_key.id);
+    }
+    match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "unknown".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        body::Body,
+        extract::connect_info::MockConnectInfo,
+        http::{StatusCode, header},
+        middleware,
+        routing::get,
+    };
+    use tower::ServiceExt;
+
+    /// Clock that only moves when a test advances it.
+    struct MockClock(Mutex<Instant>);
+
+    impl MockClock {
+        fn new() -> Arc<Self> {
+            Arc::new(MockClock(Mutex::new(Instant::now())))
+        }
+
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    const LIMIT: RateLimit = RateLimit {
+        per_minute: 60,
+        burst: 3,
+    };
+
+    #[test]
+    fn allows_a_burst_then_refills_over_time() {
+        let clock = MockClock::new();
+        let limiter = RateLimiter::with_clock(LIMIT, clock.clone());
+
+        for _ in 0..3 {
+            assert!(limiter.check("ip:1.2.3.4").is_ok());
+        }
+        assert_eq!(limiter.check("ip:1.2.3.4"), Err(Duration::from_secs(1)));
+
+        clock.advance(Duration::from_millis(400));
+        let wait = limiter.check("ip:1.2.3.4").unwrap_err();
+        assert!((wait.as_secs_f64() - 0.6).abs() < 1e-6);
+
+        clock.advance(Duration::from_millis(600));
+        assert!(limiter.check("ip:1.2.3.4").is_ok());
+        assert!(limiter.check("ip:1.2.3.4").is_err());
+
+        clock.advance(Duration::from_secs(3600));
+        for _ in 0..3 {
+            assert!(limiter.check("ip:1.2.3.4").is_ok());
+        }
+        assert!(limiter.check("ip:1.2.3.4").is_err());
+    }
+
+    #[test]
+    fn clients_have_separate_buckets() {
+        let limiter = RateLimiter::with_clock(LIMIT, MockClock::new());
+        for _ in 0..3 {
+            assert!(limiter.check("key:1").is_ok());
+        }
+        assert!(limiter.check("key:1").is_err());
+        assert!(limiter.check("key:2").is_ok());
+    }
+
+    #[test]
+    fn forget_refills_the_bucket() {
+        let limiter = RateLimiter::with_clock(LIMIT, MockClock::new());
+        for _ in 0..3 {
+            assert!(limiter.check("kN3pL4m:1.2.3.4").is_ok());
+        }
+        assert!(limiter.check("kN3pL4m:1.2.3.4").is_err());
+
+        limiter.forget("kN3pL4m:1.2.3.4");
+        assert!(limiter.check("kN3pL4m:1.2.3.4").is_ok());
+    }
+
+    #[test]
+    fn zero_rate_disables_the_limit() {
+        let limit = RateLimit {
+            per_minute: 0,
+            burst: 0,
+        };
+        let limiter = RateLimiter::with_clock(limit, MockClock::new());
+        for _ in 0..1000 {
+            assert!(limiter.check("ip:1.2.3.4").is_ok());
+        }
+    }
+
+    #[test]
+    fn prunes_refilled_buckets() {
+        let clock = MockClock::new();
+        let limiter = RateLimiter::with_clock(LIMIT, clock.clone());
+        for client in 0..PRUNE_THRESHOLD {
+            limiter.check(&format!("ip:{client}")).unwrap();
+        }
+
+        clock.advance(Duration::from_secs(1));
+        limiter.check("ip:new").unwrap();
+        let buckets = limiter.inner.buckets.lock().unwrap();
+        assert_eq!(buckets.by_client.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn middleware_answers_429_with_retry_after() {
+        let clock = MockClock::new();
+        let limiter = RateLimiter::with_clock(LIMIT, clock.clone());
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn_with_state(limiter, limit))
+            .layer(MockConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))));
+        let request = || Request::builder().uri("/").body(Body::empty()).unwrap();
+
+        for _ in 0..3 {
+            let response = app.clone().oneshot(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+
+        clock.advance(Duration::from_secs(1));
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,268 @@
+//! Redirect rules: per-visitor targets of a link.
+//!
+//! A link can carry an ordered list of [`LinkRule`]s, e.g. to send iOS users
+//! to the App Store, Android users to Google Play and everyone else to the
+//! website. Each rule sets one or more conditions:
+//!
+//! | Condition | Compared with |
+//! |-----------|---------------|
+//! | `platform` | The operating system in `User-Agent` (see [`detect_platform`]) |
+//! | `language` | The preferred language in `Accept-Language` (see [`primary_language`]) |
+//! | `country` | The client IP's country in the GeoIP database (see [`crate::geoip`]) |
+//!
+//! A rule applies when all of its conditions match; the first rule that
+//! applies replaces `long_url` as the target (see [`select_target`]). When
+//! no rule applies, the visitor goes to `long_url`.
+//!
+//! A language rule matches the tag itself and every more specific tag, so
+//! `de` matches `de`, `de-CH` and `de-AT`, while `pt-br` matches only
+//! Brazilian Portuguese.
+
+use crate::{
+    geoip::GeoIp,
+    models::{LinkRule, Platform},
+};
+use axum::http::{HeaderMap, header};
+use std::net::IpAddr;
+
+/// What is known about a visitor when choosing a redirect target.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Visitor {
+    pub platform: Option<Platform>,
+    /// Lowercase language tag, e.g. `de-ch`.
+    pub language: Option<String>,
+    /// Uppercase country code, e.g. `CH`.
+    pub country: Option<String>,
+}
+
+impl Visitor {
+    /// Describes the visitor of a request.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - Request headers (`User-Agent` and `Accept-Language`)
+    /// * `ip` - The client IP, looked up in `geoip`
+    /// * `geoip` - The country database (may be disabled)
+    pub fn from_request(headers: &HeaderMap, ip: IpAddr, geoip: &GeoIp) -> Self {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        Visitor {
+            platform: header(header::USER_AGENT).and_then(detect_platform),
+            language: header(header::ACCEPT_LANGUAGE).and_then(primary_language),
+            country: geoip.country(ip),
+        }
+    }
+}
+
+/// Detects the operating system from a `User-Agent` header.
+///
+/// Mobile platforms are checked first: iPads and iPhones also claim to be
+/// "like Mac OS X" and Android user agents contain "Linux".
+///
+/// # Examples
+///
+/// ```
+/// let ua = "Mozilla/5.0 (iPhone; CPU iPhone OS 17_4 like Mac OS X) AppleWebKit/605.1.15";
+/// assert_eq!(detect_platform(ua), Some(Platform::Ios));
+/// ```
+pub fn detect_platform(user_agent: &str) -> Option<Platform> {
+    let ua = user_agent.to_ascii_lowercase();
+    if ["iphone", "ipad", "ipod"]
+        .iter()
+        .any(|device| ua.contains(device))
+    {
+        Some(Platform::Ios)
+    } else if ua.contains("android") {
+        Some(Platform::Android)
+    } else if ua.contains("windows") {
+        Some(Platform::Windows)
+    } else if ua.contains("macintosh") || ua.contains("mac os x") {
+        Some(Platform::Macos)
+    } else if ua.contains("linux") || ua.contains("x11") {
+        Some(Platform::Linux)
+    } else {
+        None
+    }
+}
+
+/// Picks the preferred language of an `Accept-Language` header.
+///
+/// Returns the lowercased tag with the highest quality value; on a tie the
+/// first one wins. The wildcard `*` and tags with `q=0` are ignored.
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(primary_language("en;q=0.8, de-CH, de;q=0.9").as_deref(), Some("de-ch"));
+/// ```
+pub fn primary_language(accept_language: &str) -> Option<String> {
+    let mut best: Option<(&str, f32)> = None;
+    for entry in accept_language.split(',') {
+        let mut parts = entry.split(';');
+        let tag = parts.next().unwrap_or_default().trim();
+        let quality = parts
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok());
+
+        let Some(quality) = quality else { continue };
+        if tag.is_empty() || tag == "*" || quality <= 0.0 {
+            continue;
+        }
+        if best.is_none_or(|(_, best_quality)| quality > best_quality) {
+            best = Some((tag, quality));
+        }
+    }
+    best.map(|(tag, _)| tag.to_ascii_lowercase())
+}
+
+/// Returns `true` if every condition of `rule` matches `visitor`.
+pub fn matches(rule: &LinkRule, visitor: &Visitor) -> bool {
+    let platform = rule.platform.as_deref().is_none_or(|platform| {
+        visitor
+            .platform
+            .is_some_and(|visitor_platform| visitor_platform.as_str() == platform)
+    });
+    let language = rule.language.as_deref().is_none_or(|language| {
+        visitor
+            .language
+            .as_deref()
+            .is_some_and(|visitor_language| language_matches(language, visitor_language))
+    });
+    let country = rule.country.as_deref().is_none_or(|country| {
+        visitor
+            .country
+            .as_deref()
+            .is_some_and(|visitor_country| visitor_country.eq_ignore_ascii_case(country))
+    });
+    platform && language && country
+}
+
+/// Returns the target of the first rule that matches `visitor`, if any.
+pub fn select_target<'a>(rules: &'a [LinkRule], visitor: &Visitor) -> Option<&'a str> {
+    rules
+        .iter()
+        .find(|rule| matches(rule, visitor))
+        .map(|rule| rule.target_url.as_str())
+}
+
+/// Returns `true` if the visitor's tag equals the rule's tag or is a more
+/// specific form of it (`de` matches `de-ch`, but not `den`).
+fn language_matches(rule_language: &str, visitor_language: &str) -> bool {
+    let (rule_language, visitor_language) = (
+        rule_language.to_ascii_lowercase(),
+        visitor_language.to_ascii_lowercase(),
+    );
+    visitor_language
+        .strip_prefix(&rule_language)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(platform: Option<&str>, language: Option<&str>, country: Option<&str>) -> LinkRule {
+        LinkRule {
+            platform: platform.map(str::to_string),
+            language: language.map(str::to_string),
+            country: country.map(str::to_string),
+            target_url: format!("https://example.com/{platform:?}/{language:?}/{country:?}"),
+        }
+    }
+
+    #[test]
+    fn detects_platforms() {
+        let cases = [
+            (
+                "Mozilla/5.0 (iPad; CPU OS 17_4 like Mac OS X) AppleWebKit/605.1.15",
+                Some(Platform::Ios),
+            ),
+            (
+                "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36",
+                Some(Platform::Android),
+            ),
+            (
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36",
+                Some(Platform::Windows),
+            ),
+            (
+                "Mozilla/5.0 (Macintosh; Intel Mac OS X 14_4) AppleWebKit/605.1.15",
+                Some(Platform::Macos),
+            ),
+            (
+                "Mozilla/5.0 (X11; Linux x86_64; rv:125.0) Gecko/20100101 Firefox/125.0",
+                Some(Platform::Linux),
+            ),
+            ("curl/8.7.1", None),
+        ];
+        for (user_agent, platform) in cases {
+            assert_eq!(detect_platform(user_agent), platform, "{user_agent}");
+        }
+    }
+
+    #[test]
+    fn picks_the_preferred_language() {
+        assert_eq!(primary_language("de-CH").as_deref(), Some("de-ch"));
+        assert_eq!(
+            primary_language("fr;q=0.5, en-GB;q=0.9, en;q=0.9").as_deref(),
+            Some("en-gb")
+        );
+        assert_eq!(primary_language("*, de;q=0").as_deref(), None);
+        assert_eq!(primary_language("").as_deref(), None);
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = [
+            rule(Some("ios"), None, None),
+            rule(None, Some("de"), Some("AT")),
+            rule(None, Some("de"), None),
+        ];
+        let visitor = |platform, language: &str, country: Option<&str>| Visitor {
+            platform,
+            language: Some(language.to_string()),
+            country: country.map(str::to_string),
+        };
+
+        let ios_in_austria = visitor(Some(Platform::Ios), "de-at", Some("AT"));
+        assert_eq!(
+            select_target(&rules, &ios_in_austria),
+            Some(rules[0].target_url.as_str())
+        );
+
+        let austrian = visitor(Some(Platform::Windows), "de-at", Some("AT"));
+        assert_eq!(
+            select_target(&rules, &austrian),
+            Some(rules[1].target_url.as_str())
+        );
+
+        let swiss = visitor(None, "de-ch", Some("CH"));
+        assert_eq!(
+            select_target(&rules, &swiss),
+            Some(rules[2].target_url.as_str())
+        );
+
+        let danish = visitor(Some(Platform::Android), "den", None);
+        assert_eq!(select_target(&rules, &danish), None);
+    }
+
+    #[test]
+    fn visitor_reads_the_request_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::USER_AGENT,
+            "Mozilla/5.0 (Linux; Android 14)".parse().unwrap(),
+        );
+        headers.insert(header::ACCEPT_LANGUAGE, "pt-BR,pt;q=0.8".parse().unwrap());
+
+        let visitor =
+            Visitor::from_request(&headers, "127.0.0.1".parse().unwrap(), &GeoIp::disabled());
+        assert_eq!(
+            visitor,
+            Visitor {
+                platform: Some(Platform::Android),
+                language: Some("pt-br".to_string()),
+                country: None,
+            }
+        );
+    }
+}
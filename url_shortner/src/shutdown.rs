@@ -0,0 +1,208 @@
+//! Graceful shutdown.
+//!
+//! On `SIGTERM` or `Ctrl-C` the service shuts down in this order:
+//!
+//! 1. The listener stops accepting connections and idle keep-alive
+//!    connections are closed
+//! 2. In-flight requests get up to the drain timeout to finish
+//!    (`SHUTDOWN_TIMEOUT_SECS`, see [`crate::config`]); connections still
+//!    open after that are cut off
+//! 3. The background tasks are stopped
+//! 4. The queued click events are written
+//!    (see [`ClickWriter::shutdown`](crate::analytics::ClickWriter::shutdown))
+//! 5. The database pool is closed
+//!    (see [`LinkStore::close`](crate::store::LinkStore::close))
+//!
+//! [`serve`] covers the first two steps; `main` runs the others once it
+//! returns.
+//!
+//! # Examples
+//!
+//! ```bash
+//! kill -TERM $(pidof url_shortner)
+//! # INFO url_shortner::shutdown: Shutdown signal received, draining connections for up to 30s
+//! # INFO url_shortner::shutdown: All connections drained
+//! # INFO url_shortner: Shutdown complete
+//! ```
+
+use axum::Router;
+use std::{io, net::SocketAddr, pin::pin, time::Duration};
+use tokio::{net::TcpListener, sync::oneshot};
+
+/// Default time in-flight requests get to finish after a shutdown signal,
+/// in seconds.
+pub const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 30;
+
+/// Waits for `SIGTERM` or `Ctrl-C` (`SIGINT`).
+///
+/// If a handler cannot be installed, the error is logged and only the
+/// other signal is waited for.
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %err, "Cannot listen for Ctrl-C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut stream) => {
+                stream.recv().await;
+            }
+            Err(err) => {
+                tracing::error!(error = %err, "Cannot listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
+
+/// Serves `app` until `shutdown` completes, then drains the open connections.
+///
+/// # Arguments
+///
+/// * `listener` - The bound listener
+/// * `app` - The application router
+/// * `drain_timeout` - Time in-flight requests get to finish after
+///   `shutdown` completes; connections still open after it are dropped
+/// * `shutdown` - Future that completes when the server should stop,
+///   usually [`signal`]
+///
+/// # Returns
+///
+/// Returns once every connection is closed or the drain timeout elapsed.
+///
+/// # Errors
+///
+/// Returns an error if the server fails before the shutdown signal.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    drain_timeout: Duration,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+    let (signalled, signal_received) = oneshot::channel();
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        shutdown.await;
+        let _ = signalled.send(());
+    });
+    let mut server = pin!(server.into_future());
+
+    tokio::select! {
+        result = &mut server => return result,
+        _ = signal_received => {}
+    }
+
+    tracing::info!(
+        "Shutdown signal received, draining connections for up to {}s",
+        drain_timeout.as_secs()
+    );
+    match tokio::time::timeout(drain_timeout, server).await {
+        Ok(result) => {
+            tracing::info!("All connections drained");
+            result
+        }
+        Err(_) => {
+            tracing::warn!("Drain timeout elapsed, closing the remaining connections");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        time::Instant,
+    };
+
+    /// Starts `app` on a random port; the returned sender triggers shutdown.
+    async fn start(
+        app: Router,
+        drain_timeout: Duration,
+    ) -> (
+        SocketAddr,
+        oneshot::Sender<()>,
+        tokio::task::JoinHandle<io::Result<()>>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (trigger, triggered) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve(listener, app, drain_timeout, async move {
+            let _ = triggered.await;
+        }));
+        (address, trigger, server)
+    }
+
+    /// Sends a GET request on a new connection and returns the raw response.
+    async fn get_slow(address: SocketAddr) -> tokio::task::JoinHandle<String> {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        tokio::spawn(async move {
+            stream
+                .write_all(b"GET /slow HTTP/1.1\r\nhost: test\r\nconnection: close\r\n\r\n")
+                .await
+                .unwrap();
+            let mut response = String::new();
+            let _ = stream.read_to_string(&mut response).await;
+            response
+        })
+    }
+
+    fn slow_app(delay: Duration) -> Router {
+        Router::new().route(
+            "/slow",
+            get(move || async move {
+                tokio::time::sleep(delay).await;
+                "done"
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn finishes_in_flight_requests() {
+        let (address, trigger, server) =
+            start(slow_app(Duration::from_millis(300)), Duration::from_secs(5)).await;
+        let response = get_slow(address).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        trigger.send(()).unwrap();
+        let response = response.await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.ends_with("done"));
+        server.await.unwrap().unwrap();
+        assert!(TcpStream::connect(address).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn cuts_off_requests_after_the_drain_timeout() {
+        let (address, trigger, server) = start(
+            slow_app(Duration::from_secs(60)),
+            Duration::from_millis(200),
+        )
+        .await;
+        let _response = get_slow(address).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let started = Instant::now();
+        trigger.send(()).unwrap();
+        server.await.unwrap().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,51 @@
+//! Shared application state.
+//!
+//! [`AppState`] is cloned into every handler through Axum's `State` extractor.
+//! All fields are cheap to clone (the store is behind an `Arc`, and channel
+//! senders are reference counted internally).
+
+use crate::{
+    analytics::ClickRecorder, blocklist::Blocklist, cache::LinkCache, codes::CodeGenerator,
+    geoip::GeoIp, metrics::Metrics, rate_limit::RateLimiters, store::LinkStore,
+};
+use std::sync::Arc;
+use url::Url;
+
+/// State shared by all HTTP handlers.
+#[derive(Clone)]
+pub struct AppState {
+    /// The storage backend selected from `DATABASE_URL`.
+    pub store: Arc<dyn LinkStore>,
+    /// Handle used to queue click events for the batched analytics writer.
+    pub clicks: ClickRecorder,
+    /// Cache of recently redirected links, checked before the database.
+    pub link_cache: LinkCache,
+    /// Public base URL of the shortener (e.g. `https://sho.rt/`), used to
+    /// build short URLs and to reject long URLs that would redirect back to
+    /// the service. Always ends with `/`.
+    pub base_url: Url,
+    /// Generator of short codes, selected by `codes.strategy`.
+    pub codes: Arc<dyn CodeGenerator>,
+    /// Per-client rate limiters of the route groups, installed by the router.
+    pub rate_limits: RateLimiters,
+    /// Country lookup for redirect rules (disabled without `GEOIP_DATABASE`).
+    pub geoip: Arc<GeoIp>,
+    /// Destinations that must not be shortened (empty without `BLOCKLIST_FILE`).
+    pub blocklist: Arc<Blocklist>,
+    /// Prometheus metrics, also recorded by the request middleware and the store.
+    pub metrics: Arc<Metrics>,
+}
+
+impl AppState {
+    /// Returns the fully qualified short URL for `short_code`.
+    pub fn short_url(&self, short_code: &str) -> String {
+        short_url(&self.base_url, short_code)
+    }
+}
+
+/// Returns the fully qualified short URL for `short_code` under `base_url`.
+///
+/// `base_url` always ends with `/` (see [`crate::config::Config::base_url`]).
+pub fn short_url(base_url: &Url, short_code: &str) -> String {
+    format!("{base_url}{short_code}")
+}
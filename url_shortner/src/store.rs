@@ -0,0 +1,825 @@
+//! Pluggable storage backends.
+//!
+//! Handlers, background tasks and the admin CLI talk to storage only through
+//! the [`LinkStore`] trait. The backend is chosen at startup from the scheme
+//! of `DATABASE_URL` (see [`connect`]):
+//!
+//! | Scheme | Backend | Notes |
+//! |--------|---------|-------|
+//! | `sqlite:` | [`SqliteStore`] | Default; wraps the queries in [`crate::db`] |
+//! | `postgres:` / `postgresql:` | `PostgresStore` | Requires the `postgres` cargo feature |
+//! | `memory:` | [`MemoryStore`] | Data is lost on exit; used by tests and for local experiments |
+//!
+//! The server wraps the chosen backend in a [`MeteredStore`], which records
+//! the duration of every call as a metric (see [`crate::metrics`]).
+//!
+//! # Adding a Backend
+//!
+//! 1. Add a submodule with a type implementing [`LinkStore`]
+//! 2. Match its URL scheme in [`connect`]
+//! 3. Run the shared conformance tests in this module's `tests` against it
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # async fn example() -> Result<(), AppError> {
+//! let store = store::connect(&DatabaseConfig {
+//!     url: "sqlite://urls.db".to_string(),
+//!     max_connections: 10,
+//! })
+//! .await?;
+//! store.migrate().await?;
+//! let link = store.get_active_link("kN3pL4m").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod memory;
+pub mod metered;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod sqlite;
+
+use crate::{
+    config::DatabaseConfig,
+    error::AppError,
+    models::{
+        ApiKey, ClickEvent, CreatedLink, HealthCheck, Link, LinkHealth, LinkRule, LinkStats,
+        LinkUpdate, LinkVariant, NewLink, NewLinkRule, NewLinkVariant,
+    },
+};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+pub use memory::MemoryStore;
+pub use metered::MeteredStore;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
+
+/// Storage operations needed by the service.
+///
+/// Every backend must behave the same way, in particular:
+///
+/// - Unknown short codes and key ids are [`AppError::NotFound`]
+/// - Taken short codes are [`AppError::ShortCodeTaken`], taken dedup keys
+///   [`AppError::Conflict`]
+/// - Click limits are enforced atomically by [`record_click`](Self::record_click)
+/// - Timestamps are Unix seconds
+///
+/// The shared tests in this module check these rules for each backend.
+#[async_trait]
+pub trait LinkStore: Send + Sync {
+    /// Creates or upgrades the schema.
+    ///
+    /// # Returns
+    ///
+    /// Returns the schema version after migrating.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a migration fails or the schema is newer than this
+    /// binary.
+    async fn migrate(&self) -> Result<i64, AppError>;
+
+    /// Runs a trivial query to check that the backend is reachable.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no connection can be acquired or the query fails.
+    async fn ping(&self) -> Result<(), AppError>;
+
+    /// Returns the connection pool counters, or `None` for backends without
+    /// a pool.
+    fn pool_stats(&self) -> Option<PoolStats> {
+        None
+    }
+
+    /// Closes the connection pool at shutdown.
+    ///
+    /// Waits for checked out connections to be returned, then closes every
+    /// connection. Later calls fail. Backends without a pool do nothing.
+    async fn close(&self) {}
+
+    /// Reserves `count` row ids for links that are inserted later.
+    ///
+    /// Reserved ids are never handed out again, not even to links inserted
+    /// without an id, so codes derived from them (see [`crate::codes`]) can
+    /// be generated before the insert. Ids of links that are never inserted
+    /// are simply skipped.
+    ///
+    /// # Returns
+    ///
+    /// Returns `count` distinct ids in ascending order; they are not
+    /// necessarily consecutive.
+    async fn reserve_link_ids(&self, count: usize) -> Result<Vec<i64>, AppError>;
+
+    /// Inserts a new link.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::ShortCodeTaken`] if the short code is taken and
+    /// [`AppError::Conflict`] if the dedup key is.
+    async fn create_short_link(&self, link: &NewLink) -> Result<(), AppError>;
+
+    /// Inserts a batch of links in a single transaction.
+    ///
+    /// Each row succeeds or fails on its own. Rows with a `dedup_key` reuse a
+    /// live link that already owns the key.
+    ///
+    /// # Returns
+    ///
+    /// Returns one result per input link, in the same order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error (and inserts nothing) if the transaction fails.
+    async fn create_short_links(
+        &self,
+        links: &[NewLink],
+    ) -> Result<Vec<Result<CreatedLink, AppError>>, AppError>;
+
+    /// Looks up the deduplicated link that owns a dedup key (it may have expired).
+    async fn find_by_dedup_key(&self, dedup_key: &str) -> Result<Option<Link>, AppError>;
+
+    /// Clears the dedup key of a link so a fresh link can take it over.
+    async fn release_dedup_key(&self, short_code: &str) -> Result<(), AppError>;
+
+    /// Retrieves a link, whether it has expired or not.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::NotFound`] if the short code does not exist.
+    async fn get_link(&self, short_code: &str) -> Result<Link, AppError>;
+
+    /// Retrieves a link that can still be followed.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::NotFound`] if the short code does not exist and
+    /// [`AppError::Gone`] if it has expired or reached its click limit.
+    async fn get_active_link(&self, short_code: &str) -> Result<Link, AppError> {
+        let link = self.get_link(short_code).await?;
+
+        if link.is_expired(chrono::Utc::now().timestamp()) {
+            return Err(AppError::Gone(format!(
+                "short code '{short_code}' has expired"
+            )));
+        }
+
+        Ok(link)
+    }
+
+    /// Lists links with keyset pagination on `id`.
+    ///
+    /// # Arguments
+    ///
+    /// * `owner_id` - Only links created by this API key are returned; `None`
+    ///   lists the links of every owner, including links without one
+    /// * `cursor` - Id of the last link of the previous page (`None` for the first page)
+    /// * `limit` - Maximum number of links to return
+    /// * `search` - Optional text that must appear in the short code or long URL
+    ///   (ASCII case-insensitive)
+    /// * `health` - Optional result the link's latest health probe must have had
+    async fn list_links(
+        &self,
+        owner_id: Option<i64>,
+        cursor: Option<i64>,
+        limit: i64,
+        search: Option<&str>,
+        health: Option<LinkHealth>,
+    ) -> Result<Vec<Link>, AppError>;
+
+    /// Applies a partial update to a link and returns the updated link.
+    ///
+    /// Changing `long_url` resets the link's health to
+    /// [`LinkHealth::Unchecked`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::NotFound`] if the short code does not exist and
+    /// [`AppError::Conflict`] if the new dedup key is taken.
+    async fn update_link(&self, short_code: &str, update: &LinkUpdate) -> Result<Link, AppError>;
+
+    /// Deletes a link together with its recorded clicks.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::NotFound`] if the short code does not exist.
+    async fn delete_link(&self, short_code: &str) -> Result<(), AppError>;
+
+    /// Counts one redirect against a link's click limit, atomically.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::Gone`] if the link reached its limit in the meantime.
+    async fn record_click(&self, short_code: &str) -> Result<(), AppError>;
+
+    /// Deletes every link that has expired or used up its click limit, and
+    /// its clicks, in one transaction.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of deleted links.
+    async fn purge_expired_links(&self, now: i64) -> Result<u64, AppError>;
+
+    /// Reclaims the space of deleted rows and refreshes query statistics.
+    ///
+    /// Used by the admin CLI's `vacuum`, usually after a purge.
+    async fn vacuum(&self) -> Result<(), AppError>;
+
+    /// Loads up to `limit` links that were never probed or were last probed
+    /// before `checked_before`, never-probed and oldest probes first.
+    async fn links_due_for_probe(
+        &self,
+        checked_before: i64,
+        limit: i64,
+    ) -> Result<Vec<Link>, AppError>;
+
+    /// Stores the result of probing `long_url` of a link (by row id).
+    ///
+    /// Does nothing if the link is gone or its URL changed in the meantime.
+    async fn set_link_health(
+        &self,
+        link_id: i64,
+        long_url: &str,
+        check: &HealthCheck,
+        checked_at: i64,
+    ) -> Result<(), AppError>;
+
+    /// Writes a batch of click events, incrementing `click_count` for events
+    /// that were not already counted by [`record_click`](Self::record_click).
+    async fn insert_clicks(&self, events: &[ClickEvent]) -> Result<(), AppError>;
+
+    /// Aggregates the recorded clicks of a link.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::NotFound`] if the short code does not exist.
+    async fn get_link_stats(&self, short_code: &str) -> Result<LinkStats, AppError>;
+
+    /// Loads the redirect rules of a link (by row id) in evaluation order.
+    async fn get_link_rules(&self, link_id: i64) -> Result<Vec<LinkRule>, AppError>;
+
+    /// Replaces every redirect rule of a link atomically; an empty slice
+    /// removes them all. Rules are deleted together with their link.
+    ///
+    /// # Returns
+    ///
+    /// Returns the stored rules in evaluation order.
+    async fn replace_link_rules(
+        &self,
+        link_id: i64,
+        rules: &[NewLinkRule],
+    ) -> Result<Vec<LinkRule>, AppError>;
+
+    /// Loads the A/B variants of a link (by row id) in their stored order.
+    async fn get_link_variants(&self, link_id: i64) -> Result<Vec<LinkVariant>, AppError>;
+
+    /// Replaces every A/B variant of a link atomically; an empty slice
+    /// removes them all. Variants are deleted together with their link.
+    ///
+    /// # Returns
+    ///
+    /// Returns the stored variants in order.
+    async fn replace_link_variants(
+        &self,
+        link_id: i64,
+        variants: &[NewLinkVariant],
+    ) -> Result<Vec<LinkVariant>, AppError>;
+
+    /// Stores a new API key given the hash of the plain key.
+    async fn create_api_key(&self, name: &str, key_hash: &str) -> Result<ApiKey, AppError>;
+
+    /// Looks up an unrevoked API key by its hash.
+    async fn find_active_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError>;
+
+    /// Lists every API key, including revoked ones, oldest first.
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>, AppError>;
+
+    /// Revokes an API key.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AppError::NotFound`] if no active key has the given id.
+    async fn revoke_api_key(&self, id: i64) -> Result<(), AppError>;
+}
+
+/// Connection counters of a database pool, exported as metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Open connections, idle or in use.
+    pub size: u32,
+    /// Open connections waiting for a query.
+    pub idle: u32,
+    /// Largest number of connections the pool may open.
+    pub max: u32,
+}
+
+/// Connects to the backend selected by the scheme of the database URL.
+///
+/// The schema is not migrated; call [`LinkStore::migrate`] afterwards.
+///
+/// # Arguments
+///
+/// * `database` - The URL (e.g. `sqlite://urls.db`, `postgres://user@host/db`
+///   or `memory:`) and the pool size
+///
+/// # Errors
+///
+/// Returns [`AppError::Internal`] for an unknown scheme, or for a Postgres
+/// URL when the binary was built without the `postgres` feature, and a
+/// database error if the connection fails.
+pub async fn connect(database: &DatabaseConfig) -> Result<Arc<dyn LinkStore>, AppError> {
+    let DatabaseConfig {
+        url: database_url,
+        max_connections,
+    } = database;
+    let scheme = database_url.split(':').next().unwrap_or_default();
+
+    match scheme {
+        "sqlite" => Ok(Arc::new(
+            SqliteStore::connect(database_url, *max_connections).await?,
+        )),
+        "memory" => Ok(Arc::new(MemoryStore::new())),
+        #[cfg(feature = "postgres")]
+        "postgres" | "postgresql" => Ok(Arc::new(
+            PostgresStore::connect(database_url, *max_connections).await?,
+        )),
+        #[cfg(not(feature = "postgres"))]
+        "postgres" | "postgresql" => Err(AppError::Internal(
+            "postgres support is not enabled, rebuild with `--features postgres`".to_string(),
+        )),
+        other => Err(AppError::Internal(format!(
+            "unsupported DATABASE_URL scheme '{other}' (expected sqlite, postgres or memory)"
+        ))),
+    }
+}
+
+/// Conformance tests shared by every backend.
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::models::{Platform, RedirectType};
+
+    fn new_link(short_code: &str, owner_id: Option<i64>) -> NewLink {
+        NewLink {
+            short_code: short_code.to_string(),
+            long_url: format!("https://example.com/{short_code}"),
+            owner_id,
+            ..Default::default()
+        }
+    }
+
+    /// Runs every conformance check against a freshly migrated, empty store.
+    pub(crate) async fn check_store(store: &dyn LinkStore) {
+        store.ping().await.unwrap();
+        check_links(store).await;
+        check_dedup_and_bulk(store).await;
+        check_clicks(store).await;
+        check_health(store).await;
+        check_reserved_ids(store).await;
+        store.vacuum().await.unwrap();
+    }
+
+    async fn check_reserved_ids(store: &dyn LinkStore) {
+        let reserved = store.reserve_link_ids(3).await.unwrap();
+        assert_eq!(reserved.len(), 3);
+        assert!(reserved.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(store.reserve_link_ids(0).await.unwrap().is_empty());
+
+        let with_id = NewLink {
+            id: Some(reserved[1]),
+            ..new_link("reserved", None)
+        };
+        store.create_short_link(&with_id).await.unwrap();
+        store
+            .create_short_link(&new_link("after-reserved", None))
+            .await
+            .unwrap();
+        assert_eq!(store.get_link("reserved").await.unwrap().id, reserved[1]);
+        // Ids handed out later never reuse a reserved one.
+        assert!(store.get_link("after-reserved").await.unwrap().id > reserved[2]);
+    }
+
+    async fn check_links(store: &dyn LinkStore) {
+        let owner = store.create_api_key("owner", "hash-owner").await.unwrap();
+        assert_eq!(
+            store
+                .find_active_api_key("hash-owner")
+                .await
+                .unwrap()
+                .map(|key| key.id),
+            Some(owner.id)
+        );
+
+        store
+            .create_short_link(&new_link("Alpha_1", Some(owner.id)))
+            .await
+            .unwrap();
+        store
+            .create_short_link(&new_link("beta", Some(owner.id)))
+            .await
+            .unwrap();
+        store
+            .create_short_link(&new_link("other", None))
+            .await
+            .unwrap();
+        assert!(matches!(
+            store.create_short_link(&new_link("beta", None)).await,
+            Err(AppError::ShortCodeTaken(code)) if code == "beta"
+        ));
+
+        let link = store.get_link("Alpha_1").await.unwrap();
+        assert_eq!(link.long_url, "https://example.com/Alpha_1");
+        assert_eq!(link.owner_id, Some(owner.id));
+        assert!(link.created_at.is_some());
+        assert_eq!(link.redirect_type, RedirectType::Found);
+        assert!(matches!(
+            store.get_link("missing").await,
+            Err(AppError::NotFound(_))
+        ));
+
+        let page = store
+            .list_links(Some(owner.id), None, 1, None, None)
+            .await
+            .unwrap();
+        assert_eq!(page.len(), 1);
+        let rest = store
+            .list_links(Some(owner.id), Some(page[0].id), 10, None, None)
+            .await
+            .unwrap();
+        assert_eq!(rest.len(), 1);
+        let all = store.list_links(None, None, 10, None, None).await.unwrap();
+        assert_eq!(all.len(), 3);
+        let found = store
+            .list_links(Some(owner.id), None, 10, Some("alpha_"), None)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(
+            store
+                .list_links(Some(owner.id), None, 10, Some("%"), None)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let update = LinkUpdate {
+            long_url: Some("https://www.rust-lang.org/".to_string()),
+            max_clicks: Some(Some(1)),
+            redirect_type: Some(RedirectType::PermanentRedirect),
+            password_hash: Some(Some("$argon2id$hash".to_string())),
+            ..Default::default()
+        };
+        let updated = store.update_link("beta", &update).await.unwrap();
+        assert_eq!(updated.long_url, "https://www.rust-lang.org/");
+        assert_eq!(updated.max_clicks, Some(1));
+        assert_eq!(updated.redirect_type, RedirectType::PermanentRedirect);
+        assert_eq!(updated.password_hash.as_deref(), Some("$argon2id$hash"));
+        assert_eq!(
+            store.get_link("beta").await.unwrap().redirect_type,
+            RedirectType::PermanentRedirect
+        );
+        assert!(matches!(
+            store.update_link("missing", &update).await,
+            Err(AppError::NotFound(_))
+        ));
+        let unprotected = LinkUpdate {
+            password_hash: Some(None),
+            ..Default::default()
+        };
+        let updated = store.update_link("beta", &unprotected).await.unwrap();
+        assert!(updated.password_hash.is_none());
+        assert_eq!(updated.max_clicks, Some(1));
+
+        store.record_click("beta").await.unwrap();
+        assert!(matches!(
+            store.record_click("beta").await,
+            Err(AppError::Gone(_))
+        ));
+        assert!(matches!(
+            store.get_active_link("beta").await,
+            Err(AppError::Gone(_))
+        ));
+        assert_eq!(
+            store
+                .purge_expired_links(chrono::Utc::now().timestamp())
+                .await
+                .unwrap(),
+            1
+        );
+
+        let alpha = store.get_link("Alpha_1").await.unwrap();
+        assert!(!alpha.has_rules);
+        let rules = [
+            NewLinkRule {
+                platform: Some(Platform::Ios),
+                target_url: "https://apps.apple.com/app/id1".to_string(),
+                ..Default::default()
+            },
+            NewLinkRule {
+                language: Some("de".to_string()),
+                country: Some("AT".to_string()),
+                target_url: "https://example.com/de-at".to_string(),
+                ..Default::default()
+            },
+        ];
+        let stored = store.replace_link_rules(alpha.id, &rules).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].platform.as_deref(), Some("ios"));
+        assert_eq!(stored[1].country.as_deref(), Some("AT"));
+        assert_eq!(store.get_link_rules(alpha.id).await.unwrap().len(), 2);
+        assert!(store.get_link("Alpha_1").await.unwrap().has_rules);
+        let unrelated = LinkUpdate {
+            interstitial: Some(false),
+            ..Default::default()
+        };
+        assert!(
+            store
+                .update_link("Alpha_1", &unrelated)
+                .await
+                .unwrap()
+                .has_rules
+        );
+        let stored = store
+            .replace_link_rules(alpha.id, &rules[1..])
+            .await
+            .unwrap();
+        assert_eq!(stored[0].language.as_deref(), Some("de"));
+
+        let variants = [
+            NewLinkVariant {
+                name: "a".to_string(),
+                target_url: "https://example.com/a".to_string(),
+                weight: 70,
+            },
+            NewLinkVariant {
+                name: "b".to_string(),
+                target_url: "https://example.com/b".to_string(),
+                weight: 30,
+            },
+        ];
+        assert!(!alpha.has_variants);
+        let stored = store
+            .replace_link_variants(alpha.id, &variants)
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!((stored[1].name.as_str(), stored[1].weight), ("b", 30));
+        assert_eq!(store.get_link_variants(alpha.id).await.unwrap().len(), 2);
+        assert!(store.get_link("Alpha_1").await.unwrap().has_variants);
+        assert!(
+            store
+                .update_link("Alpha_1", &unrelated)
+                .await
+                .unwrap()
+                .has_variants
+        );
+        store.replace_link_variants(alpha.id, &[]).await.unwrap();
+        assert!(!store.get_link("Alpha_1").await.unwrap().has_variants);
+        store
+            .replace_link_variants(alpha.id, &variants)
+            .await
+            .unwrap();
+
+        store.delete_link("Alpha_1").await.unwrap();
+        assert!(store.get_link_rules(alpha.id).await.unwrap().is_empty());
+        assert!(store.get_link_variants(alpha.id).await.unwrap().is_empty());
+        assert!(matches!(
+            store.delete_link("Alpha_1").await,
+            Err(AppError::NotFound(_))
+        ));
+
+        store.revoke_api_key(owner.id).await.unwrap();
+        assert!(
+            store
+                .find_active_api_key("hash-owner")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(matches!(
+            store.revoke_api_key(owner.id).await,
+            Err(AppError::NotFound(_))
+        ));
+        assert_eq!(store.list_api_keys().await.unwrap().len(), 1);
+    }
+
+    async fn check_dedup_and_bulk(store: &dyn LinkStore) {
+        let deduped = |code: &str| NewLink {
+            dedup_key: Some("1:https://docs.rs/".to_string()),
+            ..new_link(code, None)
+        };
+
+        store.create_short_link(&deduped("docs")).await.unwrap();
+        assert!(matches!(
+            store.create_short_link(&deduped("docs2")).await,
+            Err(AppError::Conflict(_))
+        ));
+        assert_eq!(
+            store
+                .find_by_dedup_key("1:https://docs.rs/")
+                .await
+                .unwrap()
+                .map(|link| link.short_code),
+            Some("docs".to_string())
+        );
+
+        let results = store
+            .create_short_links(&[
+                new_link("bulk1", None),
+                new_link("docs", None),
+                deduped("bulk2"),
+                new_link("bulk3", None),
+            ])
+            .await
+            .unwrap();
+        assert!(results[0].as_ref().is_ok_and(|result| result.created));
+        assert!(matches!(results[1], Err(AppError::ShortCodeTaken(_))));
+        assert!(
+            results[2]
+                .as_ref()
+                .is_ok_and(|result| !result.created && result.short_code == "docs")
+        );
+        assert!(results[3].as_ref().is_ok_and(|result| result.created));
+        assert!(store.get_link("bulk3").await.is_ok());
+
+        store.release_dedup_key("docs").await.unwrap();
+        store.create_short_link(&deduped("docs2")).await.unwrap();
+    }
+
+    async fn check_clicks(store: &dyn LinkStore) {
+        store
+            .create_short_link(&new_link("clicked", None))
+            .await
+            .unwrap();
+        let click = |day: i64, referrer: Option<&str>, ip_hash: &str| ClickEvent {
+            short_code: "clicked".to_string(),
+            clicked_at: 1_760_000_000 + day * 86_400,
+            referrer: referrer.map(str::to_string),
+            user_agent: None,
+            ip_hash: Some(ip_hash.to_string()),
+            counted: false,
+            variant: None,
+        };
+        let variant_click = |variant: &str| ClickEvent {
+            variant: Some(variant.to_string()),
+            ..click(1, None, "ip3")
+        };
+        store
+            .insert_clicks(&[
+                click(0, Some("https://a.example/"), "ip1"),
+                click(0, Some("https://a.example/"), "ip2"),
+                click(1, None, "ip1"),
+                variant_click("b"),
+                variant_click("a"),
+                variant_click("b"),
+            ])
+            .await
+            .unwrap();
+
+        let stats = store.get_link_stats("clicked").await.unwrap();
+        assert_eq!(stats.total_clicks, 6);
+        assert_eq!(stats.unique_visitors, 3);
+        assert_eq!(stats.daily.len(), 2);
+        assert_eq!(stats.daily[0].date, "2025-10-09");
+        assert_eq!(stats.top_referrers.len(), 1);
+        assert_eq!(stats.top_referrers[0].clicks, 2);
+        let variants: Vec<(&str, i64)> = stats
+            .variants
+            .iter()
+            .map(|variant| (variant.variant.as_str(), variant.clicks))
+            .collect();
+        assert_eq!(variants, [("a", 1), ("b", 2)]);
+        assert_eq!(store.get_link("clicked").await.unwrap().click_count, 6);
+
+        store.delete_link("clicked").await.unwrap();
+        store
+            .create_short_link(&new_link("clicked", None))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_link_stats("clicked").await.unwrap().total_clicks,
+            0
+        );
+
+        // A purged link takes its clicks along, like a deleted one.
+        let limited = NewLink {
+            max_clicks: Some(1),
+            ..new_link("purged", None)
+        };
+        store.create_short_link(&limited).await.unwrap();
+        store
+            .insert_clicks(&[
+                ClickEvent {
+                    short_code: "purged".to_string(),
+                    ..click(0, None, "ip1")
+                },
+                click(0, None, "ip1"),
+            ])
+            .await
+            .unwrap();
+        assert!(
+            store
+                .purge_expired_links(chrono::Utc::now().timestamp())
+                .await
+                .unwrap()
+                >= 1
+        );
+        store
+            .create_short_link(&new_link("purged", None))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_link_stats("purged").await.unwrap().total_clicks,
+            0
+        );
+        assert_eq!(
+            store.get_link_stats("clicked").await.unwrap().total_clicks,
+            1
+        );
+    }
+
+    async fn check_health(store: &dyn LinkStore) {
+        let owner = store.create_api_key("prober", "hash-prober").await.unwrap();
+        for short_code in ["healthy", "broken"] {
+            store
+                .create_short_link(&new_link(short_code, Some(owner.id)))
+                .await
+                .unwrap();
+        }
+        let healthy = store.get_link("healthy").await.unwrap();
+        let broken = store.get_link("broken").await.unwrap();
+        assert_eq!(healthy.health, LinkHealth::Unchecked);
+        assert!(healthy.health_checked_at.is_none());
+
+        let ok = HealthCheck {
+            health: LinkHealth::Ok,
+            detail: None,
+        };
+        let dead = HealthCheck {
+            health: LinkHealth::Dead,
+            detail: Some("HTTP 404".to_string()),
+        };
+        store
+            .set_link_health(healthy.id, &healthy.long_url, &ok, 100)
+            .await
+            .unwrap();
+        store
+            .set_link_health(broken.id, &broken.long_url, &dead, 200)
+            .await
+            .unwrap();
+
+        // Never-probed links of the other checks come first, then the oldest probe.
+        let due = store.links_due_for_probe(150, 1000).await.unwrap();
+        assert_eq!(due.last().map(|link| link.id), Some(healthy.id));
+        assert!(due.iter().all(|link| link.id != broken.id));
+
+        let dead_links = store
+            .list_links(Some(owner.id), None, 10, None, Some(LinkHealth::Dead))
+            .await
+            .unwrap();
+        assert_eq!(dead_links.len(), 1);
+        assert_eq!(dead_links[0].short_code, "broken");
+        assert_eq!(dead_links[0].health_checked_at, Some(200));
+        assert_eq!(dead_links[0].health_detail.as_deref(), Some("HTTP 404"));
+
+        // A result for a URL the link no longer has is dropped.
+        store
+            .set_link_health(broken.id, "https://example.com/old", &ok, 300)
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_link("broken").await.unwrap().health,
+            LinkHealth::Dead
+        );
+
+        let same_url = LinkUpdate {
+            long_url: Some(healthy.long_url.clone()),
+            ..Default::default()
+        };
+        let updated = store.update_link("healthy", &same_url).await.unwrap();
+        assert_eq!(updated.health, LinkHealth::Ok);
+        let new_url = LinkUpdate {
+            long_url: Some("https://example.com/fixed".to_string()),
+            ..Default::default()
+        };
+        let updated = store.update_link("broken", &new_url).await.unwrap();
+        assert_eq!(updated.health, LinkHealth::Unchecked);
+        assert!(updated.health_checked_at.is_none());
+        assert!(updated.health_detail.is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_scheme() {
+        assert!(matches!(
+            connect(&DatabaseConfig {
+                url: "mysql://localhost/links".to_string(),
+                max_connections: 1,
+            })
+            .await,
+            Err(AppError::Internal(_))
+        ));
+    }
+}
@@ -0,0 +1,565 @@
+//! In-memory backend.
+//!
+//! Keeps everything in `HashMap`s behind a mutex. Nothing is persisted, so it
+//! is meant for tests and quick local experiments (`DATABASE_URL=memory:`).
+//! Every operation takes the lock once, which makes batches atomic just like
+//! a database transaction.
+
+use super::LinkStore;
+use crate::{
+    db::TOP_REFERRERS_LIMIT,
+    error::AppError,
+    models::{
+        ApiKey, ClickEvent, CreatedLink, DailyClicks, HealthCheck, Link, LinkHealth, LinkRule,
+        LinkStats, LinkUpdate, LinkVariant, NewLink, NewLinkRule, NewLinkVariant, Platform,
+        ReferrerCount, VariantClicks,
+    },
+};
+use async_trait::async_trait;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Mutex, MutexGuard},
+};
+
+/// [`LinkStore`] that keeps all data in process memory.
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<Data>,
+}
+
+#[derive(Default)]
+struct Data {
+    /// Links keyed by short code.
+    links: HashMap<String, StoredLink>,
+    clicks: Vec<ClickEvent>,
+    api_keys: Vec<StoredKey>,
+    last_link_id: i64,
+    last_key_id: i64,
+}
+
+struct StoredLink {
+    link: Link,
+    dedup_key: Option<String>,
+    /// Redirect rules in evaluation order; dropped together with the link.
+    rules: Vec<LinkRule>,
+    /// A/B variants; dropped together with the link.
+    variants: Vec<LinkVariant>,
+}
+
+struct StoredKey {
+    api_key: ApiKey,
+    key_hash: String,
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    fn data(&self) -> MutexGuard<'_, Data> {
+        self.data.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Data {
+    fn find_by_dedup_key(&self, dedup_key: &str) -> Option<&StoredLink> {
+        self.links
+            .values()
+            .find(|stored| stored.dedup_key.as_deref() == Some(dedup_key))
+    }
+
+    fn insert(&mut self, link: &NewLink) -> Result<(), AppError> {
+        if self.links.contains_key(&link.short_code) {
+            return Err(AppError::ShortCodeTaken(link.short_code.clone()));
+        }
+        if let Some(dedup_key) = &link.dedup_key
+            && self.find_by_dedup_key(dedup_key).is_some()
+        {
+            return Err(AppError::Conflict(format!(
+                "url '{}' is already shortened",
+                link.long_url
+            )));
+        }
+
+        let id = link.id.unwrap_or(self.last_link_id + 1);
+        self.last_link_id = self.last_link_id.max(id);
+        self.links.insert(
+            link.short_code.clone(),
+            StoredLink {
+                link: Link {
+                    id,
+                    short_code: link.short_code.clone(),
+                    long_url: link.long_url.clone(),
+                    expires_at: link.expires_at,
+                    max_clicks: link.max_clicks,
+                    click_count: 0,
+                    owner_id: link.owner_id,
+                    created_at: Some(chrono::Utc::now().timestamp()),
+                    interstitial: link.interstitial,
+                    redirect_type: link.redirect_type,
+                    password_hash: link.password_hash.clone(),
+                    has_rules: false,
+                    has_variants: false,
+                    health: LinkHealth::Unchecked,
+                    health_checked_at: None,
+                    health_detail: None,
+                },
+                dedup_key: link.dedup_key.clone(),
+                rules: Vec::new(),
+                variants: Vec::new(),
+            },
+        );
+        Ok(())
+    }
+
+    fn stored_mut(&mut self, short_code: &str) -> Result<&mut StoredLink, AppError> {
+        self.links
+            .get_mut(short_code)
+            .ok_or_else(|| AppError::NotFound(format!("short code '{short_code}' not found")))
+    }
+
+    fn stored_by_id(&mut self, link_id: i64) -> Option<&mut StoredLink> {
+        self.links
+            .values_mut()
+            .find(|stored| stored.link.id == link_id)
+    }
+}
+
+#[async_trait]
+impl LinkStore for MemoryStore {
+    /// There is no schema to migrate; always reports version `0`.
+    async fn migrate(&self) -> Result<i64, AppError> {
+        Ok(0)
+    }
+
+    async fn ping(&self) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    async fn vacuum(&self) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    async fn reserve_link_ids(&self, count: usize) -> Result<Vec<i64>, AppError> {
+        let mut data = self.data();
+        let first = data.last_link_id + 1;
+        data.last_link_id += count as i64;
+        Ok((first..=data.last_link_id).collect())
+    }
+
+    async fn create_short_link(&self, link: &NewLink) -> Result<(), AppError> {
+        self.data().insert(link)
+    }
+
+    async fn create_short_links(
+        &self,
+        links: &[NewLink],
+    ) -> Result<Vec<Result<CreatedLink, AppError>>, AppError> {
+        let now = chrono::Utc::now().timestamp();
+        let mut data = self.data();
+        let mut results = Vec::with_capacity(links.len());
+
+        for link in links {
+            if let Some(dedup_key) = &link.dedup_key
+                && let Some(existing) = data.find_by_dedup_key(dedup_key)
+            {
+                if !existing.link.is_expired(now) {
+                    results.push(Ok(CreatedLink {
+                        short_code: existing.link.short_code.clone(),
+                        created: false,
+                    }));
+                    continue;
+                }
+                let short_code = existing.link.short_code.clone();
+                data.stored_mut(&short_code)?.dedup_key = None;
+            }
+
+            results.push(data.insert(link).map(|()| CreatedLink {
+                short_code: link.short_code.clone(),
+                created: true,
+            }));
+        }
+
+        Ok(results)
+    }
+
+    async fn find_by_dedup_key(&self, dedup_key: &str) -> Result<Option<Link>, AppError> {
+        Ok(self
+            .data()
+            .find_by_dedup_key(dedup_key)
+            .map(|stored| stored.link.clone()))
+    }
+
+    async fn release_dedup_key(&self, short_code: &str) -> Result<(), AppError> {
+        if let Some(stored) = self.data().links.get_mut(short_code) {
+            stored.dedup_key = None;
+        }
+        Ok(())
+    }
+
+    async fn get_link(&self, short_code: &str) -> Result<Link, AppError> {
+        Ok(self.data().stored_mut(short_code)?.link.clone())
+    }
+
+    async fn list_links(
+        &self,
+        owner_id: Option<i64>,
+        cursor: Option<i64>,
+        limit: i64,
+        search: Option<&str>,
+        health: Option<LinkHealth>,
+    ) -> Result<Vec<Link>, AppError> {
+        let search = search.map(str::to_ascii_lowercase);
+        let matches = |link: &Link| {
+            let text = search.as_deref().is_none_or(|search| {
+                link.short_code.to_ascii_lowercase().contains(search)
+                    || link.long_url.to_ascii_lowercase().contains(search)
+            });
+            text && health.is_none_or(|health| link.health == health)
+        };
+
+        let data = self.data();
+        let mut links: Vec<Link> = data
+            .links
+            .values()
+            .map(|stored| &stored.link)
+            .filter(|link| owner_id.is_none_or(|owner_id| link.owner_id == Some(owner_id)))
+            .filter(|link| link.id > cursor.unwrap_or(0))
+            .filter(|link| matches(link))
+            .cloned()
+            .collect();
+        links.sort_by_key(|link| link.id);
+        links.truncate(usize::try_from(limit).unwrap_or(0));
+        Ok(links)
+    }
+
+    async fn update_link(&self, short_code: &str, update: &LinkUpdate) -> Result<Link, AppError> {
+        let mut data = self.data();
+
+        let has_dedup_key = data.stored_mut(short_code)?.dedup_key.is_some();
+        let new_dedup_key = update.dedup_key.as_ref().filter(|_| has_dedup_key);
+        if let Some(dedup_key) = new_dedup_key
+            && data
+                .find_by_dedup_key(dedup_key)
+                .is_some_and(|other| other.link.short_code != short_code)
+        {
+            return Err(AppError::Conflict("url is already shortened".to_string()));
+        }
+
+        let stored = data.stored_mut(short_code)?;
+        if let Some(long_url) = &update.long_url
+            && *long_url != stored.link.long_url
+        {
+            stored.link.long_url = long_url.clone();
+            stored.link.health = LinkHealth::Unchecked;
+            stored.link.health_checked_at = None;
+            stored.link.health_detail = None;
+        }
+        if let Some(dedup_key) = new_dedup_key {
+            stored.dedup_key = Some(dedup_key.clone());
+        }
+        if let Some(expires_at) = update.expires_at {
+            stored.link.expires_at = expires_at;
+        }
+        if let Some(max_clicks) = update.max_clicks {
+            stored.link.max_clicks = max_clicks;
+        }
+        if let Some(interstitial) = update.interstitial {
+            stored.link.interstitial = interstitial;
+        }
+        if let Some(redirect_type) = update.redirect_type {
+            stored.link.redirect_type = redirect_type;
+        }
+        if let Some(password_hash) = &update.password_hash {
+            stored.link.password_hash = password_hash.clone();
+        }
+        Ok(stored.link.clone())
+    }
+
+    async fn delete_link(&self, short_code: &str) -> Result<(), AppError> {
+        let mut data = self.data();
+        if data.links.remove(short_code).is_none() {
+            return Err(AppError::NotFound(format!(
+                "short code '{short_code}' not found"
+            )));
+        }
+        data.clicks.retain(|click| click.short_code != short_code);
+        Ok(())
+    }
+
+    async fn record_click(&self, short_code: &str) -> Result<(), AppError> {
+        let mut data = self.data();
+        match data.links.get_mut(short_code) {
+            Some(stored)
+                if stored
+                    .link
+                    .max_clicks
+                    .is_none_or(|max_clicks| stored.link.click_count < max_clicks) =>
+            {
+                stored.link.click_count += 1;
+                Ok(())
+            }
+            _ => Err(AppError::Gone(format!(
+                "short code '{short_code}' has expired"
+            ))),
+        }
+    }
+
+    async fn purge_expired_links(&self, now: i64) -> Result<u64, AppError> {
+        let mut data = self.data();
+        let purged: HashSet<String> = data
+            .links
+            .values()
+            .filter(|stored| stored.link.is_expired(now))
+            .map(|stored| stored.link.short_code.clone())
+            .collect();
+        data.links
+            .retain(|short_code, _| !purged.contains(short_code));
+        data.clicks
+            .retain(|click| !purged.contains(&click.short_code));
+        Ok(purged.len() as u64)
+    }
+
+    async fn links_due_for_probe(
+        &self,
+        checked_before: i64,
+        limit: i64,
+    ) -> Result<Vec<Link>, AppError> {
+        let data = self.data();
+        let mut links: Vec<Link> = data
+            .links
+            .values()
+            .map(|stored| &stored.link)
+            .filter(|link| {
+                link.health_checked_at
+                    .is_none_or(|checked_at| checked_at < checked_before)
+            })
+            .cloned()
+            .collect();
+        // `None` sorts before `Some`, so never-probed links come first.
+        links.sort_by_key(|link| (link.health_checked_at, link.id));
+        links.truncate(usize::try_from(limit).unwrap_or(0));
+        Ok(links)
+    }
+
+    async fn set_link_health(
+        &self,
+        link_id: i64,
+        long_url: &str,
+        check: &HealthCheck,
+        checked_at: i64,
+    ) -> Result<(), AppError> {
+        if let Some(stored) = self.data().stored_by_id(link_id)
+            && stored.link.long_url == long_url
+        {
+            stored.link.health = check.health;
+            stored.link.health_checked_at = Some(checked_at);
+            stored.link.health_detail = check.detail.clone();
+        }
+        Ok(())
+    }
+
+    async fn insert_clicks(&self, events: &[ClickEvent]) -> Result<(), AppError> {
+        let mut data = self.data();
+        for event in events {
+            if !event.counted
+                && let Some(stored) = data.links.get_mut(&event.short_code)
+            {
+                stored.link.click_count += 1;
+            }
+            data.clicks.push(event.clone());
+        }
+        Ok(())
+    }
+
+    async fn get_link_stats(&self, short_code: &str) -> Result<LinkStats, AppError> {
+        let mut data = self.data();
+        data.stored_mut(short_code)?;
+
+        let clicks: Vec<&ClickEvent> = data
+            .clicks
+            .iter()
+            .filter(|click| click.short_code == short_code)
+            .collect();
+
+        let unique_visitors: HashSet<&str> = clicks
+            .iter()
+            .filter_map(|click| click.ip_hash.as_deref())
+            .collect();
+
+        let mut daily: BTreeMap<String, i64> = BTreeMap::new();
+        let mut referrers: HashMap<&str, i64> = HashMap::new();
+        let mut variants: BTreeMap<&str, i64> = BTreeMap::new();
+        for click in &clicks {
+            let date = chrono::DateTime::from_timestamp(click.clicked_at, 0)
+                .map(|time| time.date_naive().to_string())
+                .unwrap_or_default();
+            *daily.entry(date).or_default() += 1;
+            if let Some(referrer) = &click.referrer {
+                *referrers.entry(referrer).or_default() += 1;
+            }
+            if let Some(variant) = &click.variant {
+                *variants.entry(variant).or_default() += 1;
+            }
+        }
+
+        let mut top_referrers: Vec<ReferrerCount> = referrers
+            .into_iter()
+            .map(|(referrer, clicks)| ReferrerCount {
+                referrer: referrer.to_string(),
+                clicks,
+            })
+            .collect();
+        top_referrers.sort_by(|a, b| b.clicks.cmp(&a.clicks).then(a.referrer.cmp(&b.referrer)));
+        top_referrers.truncate(TOP_REFERRERS_LIMIT as usize);
+
+        Ok(LinkStats {
+            short_code: short_code.to_string(),
+            total_clicks: clicks.len() as i64,
+            unique_visitors: unique_visitors.len() as i64,
+            daily: daily
+                .into_iter()
+                .map(|(date, clicks)| DailyClicks { date, clicks })
+                .collect(),
+            top_referrers,
+            variants: variants
+                .into_iter()
+                .map(|(variant, clicks)| VariantClicks {
+                    variant: variant.to_string(),
+                    clicks,
+                })
+                .collect(),
+        })
+    }
+
+    async fn get_link_rules(&self, link_id: i64) -> Result<Vec<LinkRule>, AppError> {
+        Ok(self
+            .data()
+            .stored_by_id(link_id)
+            .map(|stored| stored.rules.clone())
+            .unwrap_or_default())
+    }
+
+    async fn replace_link_rules(
+        &self,
+        link_id: i64,
+        rules: &[NewLinkRule],
+    ) -> Result<Vec<LinkRule>, AppError> {
+        let mut data = self.data();
+        let Some(stored) = data.stored_by_id(link_id) else {
+            return Ok(Vec::new());
+        };
+
+        stored.rules = rules
+            .iter()
+            .map(|rule| LinkRule {
+                platform: rule
+                    .platform
+                    .map(|platform| Platform::as_str(platform).to_string()),
+                language: rule.language.clone(),
+                country: rule.country.clone(),
+                target_url: rule.target_url.clone(),
+            })
+            .collect();
+        stored.link.has_rules = !stored.rules.is_empty();
+        Ok(stored.rules.clone())
+    }
+
+    async fn get_link_variants(&self, link_id: i64) -> Result<Vec<LinkVariant>, AppError> {
+        Ok(self
+            .data()
+            .stored_by_id(link_id)
+            .map(|stored| stored.variants.clone())
+            .unwrap_or_default())
+    }
+
+    async fn replace_link_variants(
+        &self,
+        link_id: i64,
+        variants: &[NewLinkVariant],
+    ) -> Result<Vec<LinkVariant>, AppError> {
+        let mut data = self.data();
+        let Some(stored) = data.stored_by_id(link_id) else {
+            return Ok(Vec::new());
+        };
+        let mut names = HashSet::new();
+        if !variants.iter().all(|variant| names.insert(&variant.name)) {
+            return Err(AppError::Conflict("resource already exists".to_string()));
+        }
+
+        stored.variants = variants
+            .iter()
+            .map(|variant| LinkVariant {
+                name: variant.name.clone(),
+                target_url: variant.target_url.clone(),
+                weight: variant.weight,
+            })
+            .collect();
+        stored.link.has_variants = !stored.variants.is_empty();
+        Ok(stored.variants.clone())
+    }
+
+    async fn create_api_key(&self, name: &str, key_hash: &str) -> Result<ApiKey, AppError> {
+        let mut data = self.data();
+        if data
+            .api_keys
+            .iter()
+            .any(|stored| stored.key_hash == key_hash)
+        {
+            return Err(AppError::Conflict("resource already exists".to_string()));
+        }
+
+        data.last_key_id += 1;
+        let api_key = ApiKey {
+            id: data.last_key_id,
+            name: name.to_string(),
+            created_at: chrono::Utc::now().timestamp(),
+            revoked_at: None,
+        };
+        data.api_keys.push(StoredKey {
+            api_key: api_key.clone(),
+            key_hash: key_hash.to_string(),
+        });
+        Ok(api_key)
+    }
+
+    async fn find_active_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError> {
+        Ok(self
+            .data()
+            .api_keys
+            .iter()
+            .find(|stored| stored.key_hash == key_hash && stored.api_key.revoked_at.is_none())
+            .map(|stored| stored.api_key.clone()))
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>, AppError> {
+        Ok(self
+            .data()
+            .api_keys
+            .iter()
+            .map(|stored| stored.api_key.clone())
+            .collect())
+    }
+
+    async fn revoke_api_key(&self, id: i64) -> Result<(), AppError> {
+        let mut data = self.data();
+        let stored = data
+            .api_keys
+            .iter_mut()
+            .find(|stored| stored.api_key.id == id && stored.api_key.revoked_at.is_none())
+            .ok_or_else(|| AppError::NotFound(format!("no active api key with id {id}")))?;
+        stored.api_key.revoked_at = Some(chrono::Utc::now().timestamp());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn passes_conformance_checks() {
+        crate::store::tests::check_store(&MemoryStore::new()).await;
+    }
+}
@@ -0,0 +1,250 @@
+//! Store decorator that records query durations.
+//!
+//! [`MeteredStore`] wraps the backend chosen by [`connect`](super::connect)
+//! and times every call in the `db_query_duration_seconds` histogram of
+//! [`Metrics`], labelled with the method name. It changes nothing else, so
+//! it works with every backend.
+
+use super::{LinkStore, PoolStats};
+use crate::{
+    error::AppError,
+    metrics::Metrics,
+    models::{
+        ApiKey, ClickEvent, CreatedLink, HealthCheck, Link, LinkHealth, LinkRule, LinkStats,
+        LinkUpdate, LinkVariant, NewLink, NewLinkRule, NewLinkVariant,
+    },
+};
+use async_trait::async_trait;
+use std::{sync::Arc, time::Instant};
+
+/// [`LinkStore`] that times each call of the store it wraps.
+pub struct MeteredStore {
+    inner: Arc<dyn LinkStore>,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredStore {
+    /// Wraps `inner`, recording into `metrics`.
+    pub fn new(inner: Arc<dyn LinkStore>, metrics: Arc<Metrics>) -> Self {
+        MeteredStore { inner, metrics }
+    }
+
+    /// Awaits `query` and records how long it took under `operation`,
+    /// whether it succeeded or not.
+    async fn time<T>(&self, operation: &str, query: impl Future<Output = T>) -> T {
+        let started = Instant::now();
+        let result = query.await;
+        self.metrics.observe_query(operation, started.elapsed());
+        result
+    }
+}
+
+#[async_trait]
+impl LinkStore for MeteredStore {
+    async fn migrate(&self) -> Result<i64, AppError> {
+        self.time("migrate", self.inner.migrate()).await
+    }
+
+    async fn ping(&self) -> Result<(), AppError> {
+        self.time("ping", self.inner.ping()).await
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        self.inner.pool_stats()
+    }
+
+    async fn close(&self) {
+        self.inner.close().await;
+    }
+
+    async fn reserve_link_ids(&self, count: usize) -> Result<Vec<i64>, AppError> {
+        self.time("reserve_link_ids", self.inner.reserve_link_ids(count))
+            .await
+    }
+
+    async fn create_short_link(&self, link: &NewLink) -> Result<(), AppError> {
+        self.time("create_short_link", self.inner.create_short_link(link))
+            .await
+    }
+
+    async fn create_short_links(
+        &self,
+        links: &[NewLink],
+    ) -> Result<Vec<Result<CreatedLink, AppError>>, AppError> {
+        self.time("create_short_links", self.inner.create_short_links(links))
+            .await
+    }
+
+    async fn find_by_dedup_key(&self, dedup_key: &str) -> Result<Option<Link>, AppError> {
+        self.time("find_by_dedup_key", self.inner.find_by_dedup_key(dedup_key))
+            .await
+    }
+
+    async fn release_dedup_key(&self, short_code: &str) -> Result<(), AppError> {
+        self.time(
+            "release_dedup_key",
+            self.inner.release_dedup_key(short_code),
+        )
+        .await
+    }
+
+    async fn get_link(&self, short_code: &str) -> Result<Link, AppError> {
+        self.time("get_link", self.inner.get_link(short_code)).await
+    }
+
+    async fn get_active_link(&self, short_code: &str) -> Result<Link, AppError> {
+        self.time("get_active_link", self.inner.get_active_link(short_code))
+            .await
+    }
+
+    async fn list_links(
+        &self,
+        owner_id: Option<i64>,
+        cursor: Option<i64>,
+        limit: i64,
+        search: Option<&str>,
+        health: Option<LinkHealth>,
+    ) -> Result<Vec<Link>, AppError> {
+        self.time(
+            "list_links",
+            self.inner
+                .list_links(owner_id, cursor, limit, search, health),
+        )
+        .await
+    }
+
+    async fn update_link(&self, short_code: &str, update: &LinkUpdate) -> Result<Link, AppError> {
+        self.time("update_link", self.inner.update_link(short_code, update))
+            .await
+    }
+
+    async fn delete_link(&self, short_code: &str) -> Result<(), AppError> {
+        self.time("delete_link", self.inner.delete_link(short_code))
+            .await
+    }
+
+    async fn record_click(&self, short_code: &str) -> Result<(), AppError> {
+        self.time("record_click", self.inner.record_click(short_code))
+            .await
+    }
+
+    async fn vacuum(&self) -> Result<(), AppError> {
+        self.time("vacuum", self.inner.vacuum()).await
+    }
+
+    async fn purge_expired_links(&self, now: i64) -> Result<u64, AppError> {
+        self.time("purge_expired_links", self.inner.purge_expired_links(now))
+            .await
+    }
+
+    async fn links_due_for_probe(
+        &self,
+        checked_before: i64,
+        limit: i64,
+    ) -> Result<Vec<Link>, AppError> {
+        self.time(
+            "links_due_for_probe",
+            self.inner.links_due_for_probe(checked_before, limit),
+        )
+        .await
+    }
+
+    async fn set_link_health(
+        &self,
+        link_id: i64,
+        long_url: &str,
+        check: &HealthCheck,
+        checked_at: i64,
+    ) -> Result<(), AppError> {
+        self.time(
+            "set_link_health",
+            self.inner
+                .set_link_health(link_id, long_url, check, checked_at),
+        )
+        .await
+    }
+
+    async fn insert_clicks(&self, events: &[ClickEvent]) -> Result<(), AppError> {
+        self.time("insert_clicks", self.inner.insert_clicks(events))
+            .await
+    }
+
+    async fn get_link_stats(&self, short_code: &str) -> Result<LinkStats, AppError> {
+        self.time("get_link_stats", self.inner.get_link_stats(short_code))
+            .await
+    }
+
+    async fn get_link_rules(&self, link_id: i64) -> Result<Vec<LinkRule>, AppError> {
+        self.time("get_link_rules", self.inner.get_link_rules(link_id))
+            .await
+    }
+
+    async fn replace_link_rules(
+        &self,
+        link_id: i64,
+        rules: &[NewLinkRule],
+    ) -> Result<Vec<LinkRule>, AppError> {
+        self.time(
+            "replace_link_rules",
+            self.inner.replace_link_rules(link_id, rules),
+        )
+        .await
+    }
+
+    async fn get_link_variants(&self, link_id: i64) -> Result<Vec<LinkVariant>, AppError> {
+        self.time("get_link_variants", self.inner.get_link_variants(link_id))
+            .await
+    }
+
+    async fn replace_link_variants(
+        &self,
+        link_id: i64,
+        variants: &[NewLinkVariant],
+    ) -> Result<Vec<LinkVariant>, AppError> {
+        self.time(
+            "replace_link_variants",
+            self.inner.replace_link_variants(link_id, variants),
+        )
+        .await
+    }
+
+    async fn create_api_key(&self, name: &str, key_hash: &str) -> Result<ApiKey, AppError> {
+        self.time("create_api_key", self.inner.create_api_key(name, key_hash))
+            .await
+    }
+
+    async fn find_active_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError> {
+        self.time(
+            "find_active_api_key",
+            self.inner.find_active_api_key(key_hash),
+        )
+        .await
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>, AppError> {
+        self.time("list_api_keys", self.inner.list_api_keys()).await
+    }
+
+    async fn revoke_api_key(&self, id: i64) -> Result<(), AppError> {
+        self.time("revoke_api_key", self.inner.revoke_api_key(id))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{models::CacheStats, store::MemoryStore};
+
+    #[tokio::test]
+    async fn passes_conformance_checks_and_times_calls() {
+        let metrics = Arc::new(Metrics::new());
+        let store = MeteredStore::new(Arc::new(MemoryStore::new()), metrics.clone());
+        crate::store::tests::check_store(&store).await;
+
+        let cache = CacheStats::default();
+        let text = metrics.render(&cache, store.pool_stats()).unwrap();
+        assert!(text.contains(r#"db_query_duration_seconds_count{operation="ping"} 1"#));
+        assert!(text.contains(r#"db_query_duration_seconds_count{operation="get_link"}"#));
+    }
+}
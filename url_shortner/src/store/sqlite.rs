@@ -0,0 +1,220 @@
+//! SQLite backend.
+//!
+//! A thin [`LinkStore`] wrapper around the queries in [`crate::db`] and the
+//! migrations in [`crate::migrations`].
+
+use super::{LinkStore, PoolStats};
+use crate::{
+    db,
+    error::AppError,
+    migrations,
+    models::{
+        ApiKey, ClickEvent, CreatedLink, HealthCheck, Link, LinkHealth, LinkRule, LinkStats,
+        LinkUpdate, LinkVariant, NewLink, NewLinkRule, NewLinkVariant,
+    },
+};
+use async_trait::async_trait;
+use sqlx::SqlitePool;
+
+/// [`LinkStore`] backed by a SQLite connection pool.
+#[derive(Clone)]
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// Wraps an existing pool.
+    pub fn new(pool: SqlitePool) -> Self {
+        SqliteStore { pool }
+    }
+
+    /// Connects to a SQLite database (see [`db::db_connection`]).
+    ///
+    /// `sqlite::memory:` gives every pooled connection its own database, so
+    /// use `max_connections = 1` with it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened.
+    pub async fn connect(database_url: &str, max_connections: u32) -> Result<Self, AppError> {
+        Ok(SqliteStore::new(
+            db::db_connection(database_url, max_connections).await?,
+        ))
+    }
+}
+
+#[async_trait]
+impl LinkStore for SqliteStore {
+    async fn migrate(&self) -> Result<i64, AppError> {
+        db::init_db(&self.pool).await?;
+        migrations::current_version(&self.pool).await
+    }
+
+    async fn ping(&self) -> Result<(), AppError> {
+        db::ping(&self.pool).await
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        Some(PoolStats {
+            size: self.pool.size(),
+            idle: u32::try_from(self.pool.num_idle()).unwrap_or(u32::MAX),
+            max: self.pool.options().get_max_connections(),
+        })
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    async fn reserve_link_ids(&self, count: usize) -> Result<Vec<i64>, AppError> {
+        db::reserve_link_ids(&self.pool, count).await
+    }
+
+    async fn create_short_link(&self, link: &NewLink) -> Result<(), AppError> {
+        db::create_short_link(&self.pool, link).await?;
+        Ok(())
+    }
+
+    async fn create_short_links(
+        &self,
+        links: &[NewLink],
+    ) -> Result<Vec<Result<CreatedLink, AppError>>, AppError> {
+        db::create_short_links(&self.pool, links).await
+    }
+
+    async fn find_by_dedup_key(&self, dedup_key: &str) -> Result<Option<Link>, AppError> {
+        db::find_by_dedup_key(&self.pool, dedup_key).await
+    }
+
+    async fn release_dedup_key(&self, short_code: &str) -> Result<(), AppError> {
+        db::release_dedup_key(&self.pool, short_code).await
+    }
+
+    async fn get_link(&self, short_code: &str) -> Result<Link, AppError> {
+        db::get_link(&self.pool, short_code).await
+    }
+
+    async fn get_active_link(&self, short_code: &str) -> Result<Link, AppError> {
+        db::get_active_link(&self.pool, short_code).await
+    }
+
+    async fn list_links(
+        &self,
+        owner_id: Option<i64>,
+        cursor: Option<i64>,
+        limit: i64,
+        search: Option<&str>,
+        health: Option<LinkHealth>,
+    ) -> Result<Vec<Link>, AppError> {
+        db::list_links(&self.pool, owner_id, cursor, limit, search, health).await
+    }
+
+    async fn update_link(&self, short_code: &str, update: &LinkUpdate) -> Result<Link, AppError> {
+        db::update_link(&self.pool, short_code, update).await
+    }
+
+    async fn delete_link(&self, short_code: &str) -> Result<(), AppError> {
+        db::delete_link(&self.pool, short_code).await
+    }
+
+    async fn record_click(&self, short_code: &str) -> Result<(), AppError> {
+        db::record_click(&self.pool, short_code).await
+    }
+
+    async fn vacuum(&self) -> Result<(), AppError> {
+        db::vacuum(&self.pool).await
+    }
+
+    async fn purge_expired_links(&self, now: i64) -> Result<u64, AppError> {
+        db::purge_expired_links(&self.pool, now).await
+    }
+
+    async fn links_due_for_probe(
+        &self,
+        checked_before: i64,
+        limit: i64,
+    ) -> Result<Vec<Link>, AppError> {
+        db::links_due_for_probe(&self.pool, checked_before, limit).await
+    }
+
+    async fn set_link_health(
+        &self,
+        link_id: i64,
+        long_url: &str,
+        check: &HealthCheck,
+        checked_at: i64,
+    ) -> Result<(), AppError> {
+        db::set_link_health(&self.pool, link_id, long_url, check, checked_at).await
+    }
+
+    async fn insert_clicks(&self, events: &[ClickEvent]) -> Result<(), AppError> {
+        db::insert_clicks(&self.pool, events).await
+    }
+
+    async fn get_link_stats(&self, short_code: &str) -> Result<LinkStats, AppError> {
+        db::get_link_stats(&self.pool, short_code).await
+    }
+
+    async fn get_link_rules(&self, link_id: i64) -> Result<Vec<LinkRule>, AppError> {
+        db::get_link_rules(&self.pool, link_id).await
+    }
+
+    async fn replace_link_rules(
+        &self,
+        link_id: i64,
+        rules: &[NewLinkRule],
+    ) -> Result<Vec<LinkRule>, AppError> {
+        db::replace_link_rules(&self.pool, link_id, rules).await
+    }
+
+    async fn get_link_variants(&self, link_id: i64) -> Result<Vec<LinkVariant>, AppError> {
+        db::get_link_variants(&self.pool, link_id).await
+    }
+
+    async fn replace_link_variants(
+        &self,
+        link_id: i64,
+        variants: &[NewLinkVariant],
+    ) -> Result<Vec<LinkVariant>, AppError> {
+        db::replace_link_variants(&self.pool, link_id, variants).await
+    }
+
+    async fn create_api_key(&self, name: &str, key_hash: &str) -> Result<ApiKey, AppError> {
+        db::create_api_key(&self.pool, name, key_hash).await
+    }
+
+    async fn find_active_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError> {
+        db::find_active_api_key(&self.pool, key_hash).await
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>, AppError> {
+        db::list_api_keys(&self.pool).await
+    }
+
+    async fn revoke_api_key(&self, id: i64) -> Result<(), AppError> {
+        db::revoke_api_key(&self.pool, id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn passes_conformance_checks() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let store = SqliteStore::new(pool);
+        assert_eq!(store.migrate().await.unwrap(), migrations::latest_version());
+        assert_eq!(store.pool_stats().map(|stats| stats.max), Some(1));
+
+        crate::store::tests::check_store(&store).await;
+
+        store.close().await;
+        assert!(store.ping().await.is_err());
+    }
+}
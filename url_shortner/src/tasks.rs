@@ -0,0 +1,129 @@
+//! Background tasks that run alongside the HTTP server.
+//!
+//! Each task is spawned onto the Tokio runtime from `main` and runs for the
+//! lifetime of the process.
+//!
+//! - Purge task: periodically deletes expired links
+//! - Probe task: periodically checks that link destinations still work
+//! - Blocklist reload task: picks up changes to the blocklist file
+
+use crate::{
+    blocklist::Blocklist,
+    probe::{self, Prober},
+    store::LinkStore,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+
+/// Default interval between two purge runs, in seconds.
+pub const DEFAULT_PURGE_INTERVAL_SECS: u64 = 300;
+
+/// Spawns a task that deletes expired links every `interval`.
+///
+/// Links that passed their `expires_at` or used up their `max_clicks` are
+/// removed via [`LinkStore::purge_expired_links`]. Failures are logged and the task
+/// keeps running.
+///
+/// # Arguments
+///
+/// * `store` - The storage backend to purge
+/// * `interval` - Time between two purge runs
+///
+/// # Returns
+///
+/// Returns the `JoinHandle` of the spawned task.
+pub fn spawn_purge_task(store: Arc<dyn LinkStore>, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match store
+                .purge_expired_links(chrono::Utc::now().timestamp())
+                .await
+            {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!("Purged {purged} expired links"),
+                Err(err) => tracing::error!(error = %err, "Failed to purge expired links"),
+            }
+        }
+    })
+}
+
+/// Spawns a task that probes the destinations of due links every `interval`.
+///
+/// Each run probes up to `batch_size` links with [`probe::probe_due_links`];
+/// a link is due when it was never probed or its last probe is older than
+/// `recheck_after`. Failures are logged and the task keeps running.
+///
+/// # Arguments
+///
+/// * `store` - The storage backend holding the links
+/// * `prober` - The HTTP client used for the probes
+/// * `interval` - Time between two probe runs
+/// * `recheck_after` - Age after which a probe result is refreshed
+/// * `batch_size` - Maximum number of links probed per run
+///
+/// # Returns
+///
+/// Returns the `JoinHandle` of the spawned task.
+pub fn spawn_probe_task(
+    store: Arc<dyn LinkStore>,
+    prober: Prober,
+    interval: Duration,
+    recheck_after: Duration,
+    batch_size: i64,
+) -> JoinHandle<()> {
+    let recheck_after = i64::try_from(recheck_after.as_secs()).unwrap_or(i64::MAX);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let now = chrono::Utc::now().timestamp();
+            match probe::probe_due_links(store.as_ref(), &prober, now, recheck_after, batch_size)
+                .await
+            {
+                Ok(summary) if summary.probed == 0 => {}
+                Ok(summary) => tracing::info!(
+                    "Probed {} links, {} unhealthy",
+                    summary.probed,
+                    summary.unhealthy
+                ),
+                Err(err) => tracing::error!(error = %err, "Failed to probe links"),
+            }
+        }
+    })
+}
+
+/// Spawns a task that reloads the blocklist file every `interval` if it
+/// changed.
+///
+/// A file that cannot be read or parsed is logged and the previous entries
+/// stay in effect until it is fixed.
+///
+/// # Arguments
+///
+/// * `blocklist` - The blocklist shared with the handlers
+/// * `interval` - Time between two checks of the file
+///
+/// # Returns
+///
+/// Returns the `JoinHandle` of the spawned task.
+pub fn spawn_blocklist_reload_task(
+    blocklist: Arc<Blocklist>,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match blocklist.reload_if_changed() {
+                Ok(false) => {}
+                Ok(true) => tracing::info!("Reloaded blocklist ({} entries)", blocklist.len()),
+                Err(err) => tracing::error!(
+                    error = %err,
+                    "Failed to reload blocklist, keeping the old entries"
+                ),
+            }
+        }
+    })
+}
@@ -0,0 +1,229 @@
+//! Structured logging and request tracing.
+//!
+//! [`init`] installs the global `tracing` subscriber. Events are written to
+//! stderr, as text or as one JSON object per line (`LOG_FORMAT`), and
+//! filtered by `RUST_LOG` directives such as `info,url_shortner=debug` (see
+//! [`crate::config`]). Standard output stays free for the admin subcommands.
+//!
+//! [`track_request`] wraps every route. For each request it:
+//!
+//! 1. Takes the request id from the `X-Request-Id` header, or generates one
+//! 2. Runs the request inside a `request` span carrying the id, method, route
+//!    and path, so every event logged while handling it can be traced back
+//! 3. Logs the status and latency and records them in [`Metrics`]
+//! 4. Returns the id in the `X-Request-Id` response header
+//!
+//! # Example Output
+//!
+//! ```text
+//! 2026-10-18T09:12:44.120Z  INFO request{id=V1StGXR8_Z5jdHi6B-myT method=GET route=/{short_code} path=/kN3pL4m}: url_shortner::telemetry: finished status=302 latency_ms=1.84
+//! ```
+
+use crate::{error::AppError, metrics::Metrics};
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use nanoid::nanoid;
+use serde::Deserialize;
+use std::{
+    io::{self, IsTerminal},
+    str::FromStr,
+    sync::Arc,
+    time::Instant,
+};
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
+
+/// Log filter used when `RUST_LOG` is not set.
+pub const DEFAULT_LOG_FILTER: &str = "info";
+
+/// Header carrying the request id, in both directions.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest request id accepted from a client; longer ids are replaced.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// `route` label of requests that matched no route.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Output format of the log.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines (default).
+    #[default]
+    Text,
+    /// One JSON object per event, for log shippers.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!(
+                "unknown log format '{other}' (expected text or json)"
+            )),
+        }
+    }
+}
+
+/// Installs the global log subscriber.
+///
+/// # Arguments
+///
+/// * `format` - Text or JSON output
+/// * `filter` - `RUST_LOG` style directives, e.g. `info,sqlx=warn`
+///
+/// # Errors
+///
+/// Returns [`AppError::Internal`] if the filter is invalid or a subscriber
+/// is already installed.
+pub fn init(format: LogFormat, filter: &str) -> Result<(), AppError> {
+    let filter = EnvFilter::try_new(filter)
+        .map_err(|err| AppError::Internal(format!("invalid log filter '{filter}': {err}")))?;
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal());
+    let installed = match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+    installed.map_err(|err| AppError::Internal(format!("cannot install the logger: {err}")))
+}
+
+/// Middleware that assigns a request id, traces the request and records its
+/// metrics.
+///
+/// Install it with `Router::layer` after all routes are added, so the
+/// matched route pattern is known and rejected requests (401, 429) are
+/// counted too.
+///
+/// # Examples
+///
+/// ```bash
+/// curl -i http://localhost:3000/ -H "X-Request-Id: deploy-check-1"
+/// # HTTP/1.1 200 OK
+/// # x-request-id: deploy-check-1
+/// ```
+pub async fn track_request(
+    State(metrics): State<Arc<Metrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map_or_else(|| nanoid!(), str::to_string);
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(UNMATCHED_ROUTE, MatchedPath::as_str)
+        .to_string();
+    let span = tracing::info_span!(
+        "request",
+        id = %request_id,
+        method = %method,
+        route = %route,
+        path = %request.uri().path(),
+    );
+
+    let mut response = next.run(request).instrument(span.clone()).await;
+
+    let elapsed = started.elapsed();
+    let status = response.status();
+    span.in_scope(|| {
+        tracing::info!(
+            status = status.as_u16(),
+            latency_ms = elapsed.as_secs_f64() * 1000.0,
+            "finished"
+        );
+    });
+    metrics.observe_request(method.as_str(), &route, status, elapsed);
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Returns `true` if a client supplied request id can be logged and echoed
+/// as is: at most [`MAX_REQUEST_ID_LEN`] visible ASCII characters.
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CacheStats;
+    use axum::{Router, body::Body, middleware, routing::get};
+    use tower::ServiceExt;
+
+    fn get_request(path: &str, request_id: Option<&str>) -> Request {
+        let mut request = Request::get(path);
+        if let Some(request_id) = request_id {
+            request = request.header(REQUEST_ID_HEADER, request_id);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn tags_responses_and_records_routes() {
+        let metrics = Arc::new(Metrics::new());
+        let app = Router::new()
+            .route("/items/{id}", get(|| async { "item" }))
+            .layer(middleware::from_fn_with_state(
+                metrics.clone(),
+                track_request,
+            ));
+
+        let response = app
+            .clone()
+            .oneshot(get_request("/items/1", Some("deploy-check-1")))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "deploy-check-1");
+
+        let response = app
+            .clone()
+            .oneshot(get_request("/items/2", Some("not a valid id")))
+            .await
+            .unwrap();
+        let generated = response.headers()[REQUEST_ID_HEADER].to_str().unwrap();
+        assert_eq!(generated.len(), 21);
+
+        let response = app
+            .oneshot(get_request("/no/such/route", None))
+            .await
+            .unwrap();
+        assert!(response.headers().contains_key(REQUEST_ID_HEADER));
+
+        let cache = CacheStats::default();
+        let text = metrics.render(&cache, None).unwrap();
+        for line in [
+            r#"http_requests_total{method="GET",route="/items/{id}",status="200"} 2"#,
+            r#"http_requests_total{method="GET",route="unmatched",status="404"} 1"#,
+        ] {
+            assert!(text.lines().any(|l| l == line), "{line} missing in\n{text}");
+        }
+    }
+
+    #[test]
+    fn parses_log_formats() {
+        assert_eq!("json".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert_eq!("text".parse::<LogFormat>(), Ok(LogFormat::Text));
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+}
@@ -0,0 +1,574 @@
+//! Validation rules for user supplied input.
+//!
+//! Handlers call into this module before touching the database so that
+//! malformed input is rejected with a 400 instead of being stored.
+//!
+//! # Alias Rules
+//!
+//! A custom alias (vanity short code) must:
+//! - Be between [`ALIAS_MIN_LEN`] and [`ALIAS_MAX_LEN`] characters long
+//! - Only contain ASCII letters, digits, `-` and `_`
+//! - Not be one of the [`RESERVED_ALIASES`] (compared case-insensitively)
+//!
+//! # Expiry Rules
+//!
+//! - `expires_at` must lie in the future
+//! - `max_clicks` must be at least 1
+//!
+//! # Password Rules
+//!
+//! A link password must be between [`PASSWORD_MIN_LEN`] and
+//! [`PASSWORD_MAX_LEN`] characters long.
+//!
+//! # Redirect Rule Rules
+//!
+//! A link has at most [`MAX_LINK_RULES`] redirect rules. Each rule must set at
+//! least one condition; a language is a tag of letters, digits and `-` (at
+//! most [`LANGUAGE_TAG_MAX_LEN`] characters) and a country is a two-letter
+//! code. The target URL follows the URL rules below.
+//!
+//! # Variant Rules
+//!
+//! A link has at most [`MAX_LINK_VARIANTS`] A/B variants. Variant names follow
+//! the alias character rules, are at most [`ALIAS_MAX_LEN`] characters long
+//! and unique per link; weights are between 1 and [`MAX_VARIANT_WEIGHT`].
+//!
+//! # URL Rules
+//!
+//! A long URL must be an absolute `http` or `https` URL with a host that is
+//! not the shortener itself. Accepted URLs are normalized before they are
+//! stored (see [`normalize_url`]).
+
+use crate::{
+    error::AppError,
+    models::{LinkRuleRequest, LinkVariantRequest, NewLinkRule, NewLinkVariant},
+};
+use chrono::{DateTime, Utc};
+use std::net::IpAddr;
+use url::{Host, Url};
+
+/// Maximum length of a long URL, in bytes, after normalization.
+pub const URL_MAX_LEN: usize = 2048;
+
+/// Minimum length of a custom alias.
+pub const ALIAS_MIN_LEN: usize = 3;
+
+/// Maximum length of a custom alias.
+pub const ALIAS_MAX_LEN: usize = 32;
+
+/// Minimum length of a link password, in characters.
+pub const PASSWORD_MIN_LEN: usize = 6;
+
+/// Maximum length of a link password, in characters; bounds the hashing work
+/// per request.
+pub const PASSWORD_MAX_LEN: usize = 128;
+
+/// Maximum number of redirect rules per link.
+pub const MAX_LINK_RULES: usize = 50;
+
+/// Maximum length of a language tag in a redirect rule (the longest
+/// well-formed BCP 47 tags stay below this).
+pub const LANGUAGE_TAG_MAX_LEN: usize = 35;
+
+/// Maximum number of A/B variants per link.
+pub const MAX_LINK_VARIANTS: usize = 20;
+
+/// Largest weight of an A/B variant.
+pub const MAX_VARIANT_WEIGHT: i64 = 1000;
+
+/// Aliases that collide with the service's own routes (including the
+/// `/api/links/bulk` and `/api/links/export` management routes) and can never
+/// be used as a short code.
+pub const RESERVED_ALIASES: &[&str] = &["shorten", "api", "health", "bulk", "export", "metrics"];
+
+/// Validates a custom alias requested by the client.
+///
+/// # Arguments
+///
+/// * `alias` - The requested short code, e.g. `launch2026`
+///
+/// # Returns
+///
+/// Returns `Ok(())` when the alias can be used as a short code.
+///
+/// # Examples
+///
+/// ```
+/// assert!(validate_alias("launch2026").is_ok());
+/// assert!(validate_alias("api").is_err());
+/// ```
+///
+/// # Errors
+///
+/// Returns [`AppError::BadRequest`] if:
+/// - The alias is shorter than [`ALIAS_MIN_LEN`] or longer than [`ALIAS_MAX_LEN`]
+/// - The alias contains characters outside `[A-Za-z0-9_-]`
+/// - The alias is a reserved word
+pub fn validate_alias(alias: &str) -> Result<(), AppError> {
+    let len = alias.chars().count();
+    if !(ALIAS_MIN_LEN..=ALIAS_MAX_LEN).contains(&len) {
+        return Err(AppError::BadRequest(format!(
+            "alias must be between {ALIAS_MIN_LEN} and {ALIAS_MAX_LEN} characters long"
+        )));
+    }
+
+    if !alias
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(AppError::BadRequest(
+            "alias may only contain letters, digits, '-' and '_'".to_string(),
+        ));
+    }
+
+    if is_reserved(alias) {
+        return Err(AppError::BadRequest(format!("alias '{alias}' is reserved")));
+    }
+
+    Ok(())
+}
+
+/// Whether `code` is one of the [`RESERVED_ALIASES`], ignoring case.
+pub fn is_reserved(code: &str) -> bool {
+    RESERVED_ALIASES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(code))
+}
+
+/// Validates the optional expiry limits of a new link.
+///
+/// # Arguments
+///
+/// * `expires_at` - Requested expiry time, if any
+/// * `max_clicks` - Requested click limit, if any
+/// * `now` - The current time
+///
+/// # Errors
+///
+/// Returns [`AppError::BadRequest`] if:
+/// - `expires_at` is not after `now`
+/// - `max_clicks` is less than 1
+pub fn validate_expiry(
+    expires_at: Option<DateTime<Utc>>,
+    max_clicks: Option<i64>,
+    now: DateTime<Utc>,
+) -> Result<(), AppError> {
+    if expires_at.is_some_and(|expires_at| expires_at <= now) {
+        return Err(AppError::BadRequest(
+            "expires_at must be in the future".to_string(),
+        ));
+    }
+
+    if max_clicks.is_some_and(|max_clicks| max_clicks < 1) {
+        return Err(AppError::BadRequest(
+            "max_clicks must be at least 1".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Validates the password of a password-protected link.
+///
+/// # Arguments
+///
+/// * `password` - The requested password
+///
+/// # Errors
+///
+/// Returns [`AppError::BadRequest`] if the password is shorter than
+/// [`PASSWORD_MIN_LEN`] or longer than [`PASSWORD_MAX_LEN`] characters.
+pub fn validate_password(password: &str) -> Result<(), AppError> {
+    let len = password.chars().count();
+    if !(PASSWORD_MIN_LEN..=PASSWORD_MAX_LEN).contains(&len) {
+        return Err(AppError::BadRequest(format!(
+            "password must be between {PASSWORD_MIN_LEN} and {PASSWORD_MAX_LEN} characters long"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Validates the redirect rules of a link and normalizes them for storage.
+///
+/// Language tags are lowercased, country codes uppercased and target URLs
+/// normalized like long URLs (see [`normalize_url`]).
+///
+/// # Arguments
+///
+/// * `rules` - The requested rules in evaluation order
+/// * `base_url` - The shortener's own public base URL
+///
+/// # Errors
+///
+/// Returns [`AppError::BadRequest`] naming the offending rule (0-based) if:
+/// - There are more than [`MAX_LINK_RULES`] rules
+/// - A rule sets none of `platform`, `language` and `country`
+/// - A language is empty, too long or contains characters other than
+///   letters, digits and `-`
+/// - A country is not two ASCII letters
+/// - A target URL fails the URL rules
+pub fn validate_link_rules(
+    rules: &[LinkRuleRequest],
+    base_url: &Url,
+) -> Result<Vec<NewLinkRule>, AppError> {
+    if rules.len() > MAX_LINK_RULES {
+        return Err(AppError::BadRequest(format!(
+            "a link can have at most {MAX_LINK_RULES} rules"
+        )));
+    }
+
+    rules
+        .iter()
+        .enumerate()
+        .map(|(index, rule)| {
+            validate_link_rule(rule, base_url)
+                .map_err(|err| AppError::BadRequest(format!("rule {index}: {err}")))
+        })
+        .collect()
+}
+
+/// Validates a single redirect rule (see [`validate_link_rules`]).
+fn validate_link_rule(rule: &LinkRuleRequest, base_url: &Url) -> Result<NewLinkRule, AppError> {
+    if rule.platform.is_none() && rule.language.is_none() && rule.country.is_none() {
+        return Err(AppError::BadRequest(
+            "set at least one of platform, language and country".to_string(),
+        ));
+    }
+
+    let language = rule
+        .language
+        .as_deref()
+        .map(|language| {
+            let valid = !language.is_empty()
+                && language.len() <= LANGUAGE_TAG_MAX_LEN
+                && language
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-');
+            if valid {
+                Ok(language.to_ascii_lowercase())
+            } else {
+                Err(AppError::BadRequest(format!(
+                    "language '{language}' is not a language tag such as 'de' or 'pt-BR'"
+                )))
+            }
+        })
+        .transpose()?;
+
+    let country = rule
+        .country
+        .as_deref()
+        .map(|country| {
+            if country.len() == 2 && country.chars().all(|c| c.is_ascii_alphabetic()) {
+                Ok(country.to_ascii_uppercase())
+            } else {
+                Err(AppError::BadRequest(format!(
+                    "country '{country}' is not a two-letter country code such as 'DE'"
+                )))
+            }
+        })
+        .transpose()?;
+
+    Ok(NewLinkRule {
+        platform: rule.platform,
+        language,
+        country,
+        target_url: normalize_url(&rule.target_url, base_url)?,
+    })
+}
+
+/// Validates the A/B variants of a link and normalizes their target URLs.
+///
+/// # Arguments
+///
+/// * `variants` - The requested variants
+/// * `base_url` - The shortener's own public base URL
+///
+/// # Errors
+///
+/// Returns [`AppError::BadRequest`] naming the offending variant (0-based) if:
+/// - There are more than [`MAX_LINK_VARIANTS`] variants
+/// - A name is empty, longer than [`ALIAS_MAX_LEN`], contains characters
+///   other than letters, digits, `-` and `_`, or is used twice
+/// - A weight is not between 1 and [`MAX_VARIANT_WEIGHT`]
+/// - A target URL fails the URL rules
+pub fn validate_link_variants(
+    variants: &[LinkVariantRequest],
+    base_url: &Url,
+) -> Result<Vec<NewLinkVariant>, AppError> {
+    if variants.len() > MAX_LINK_VARIANTS {
+        return Err(AppError::BadRequest(format!(
+            "a link can have at most {MAX_LINK_VARIANTS} variants"
+        )));
+    }
+
+    let mut validated: Vec<NewLinkVariant> = Vec::with_capacity(variants.len());
+    for (index, variant) in variants.iter().enumerate() {
+        let checked = validate_link_variant(variant, base_url)
+            .map_err(|err| AppError::BadRequest(format!("variant {index}: {err}")))?;
+        if validated.iter().any(|other| other.name == checked.name) {
+            return Err(AppError::BadRequest(format!(
+                "variant {index}: name '{}' is used twice",
+                checked.name
+            )));
+        }
+        validated.push(checked);
+    }
+
+    Ok(validated)
+}
+
+/// Validates a single A/B variant (see [`validate_link_variants`]).
+fn validate_link_variant(
+    variant: &LinkVariantRequest,
+    base_url: &Url,
+) -> Result<NewLinkVariant, AppError> {
+    let name = &variant.name;
+    let valid_name = !name.is_empty()
+        && name.len() <= ALIAS_MAX_LEN
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name {
+        return Err(AppError::BadRequest(format!(
+            "name '{name}' must be 1 to {ALIAS_MAX_LEN} letters, digits, '-' or '_'"
+        )));
+    }
+    if !(1..=MAX_VARIANT_WEIGHT).contains(&variant.weight) {
+        return Err(AppError::BadRequest(format!(
+            "weight must be between 1 and {MAX_VARIANT_WEIGHT}"
+        )));
+    }
+
+    Ok(NewLinkVariant {
+        name: name.clone(),
+        target_url: normalize_url(&variant.target_url, base_url)?,
+        weight: variant.weight,
+    })
+}
+
+/// Validates and normalizes a long URL before it is stored.
+///
+/// Parsing with the [`url`] crate already lowercases the scheme and host and
+/// drops default ports (`:80` for http, `:443` for https), so equivalent
+/// URLs are stored identically.
+///
+/// # Arguments
+///
+/// * `raw` - The URL as sent by the client
+/// * `base_url` - The shortener's own public base URL, used to detect redirect loops
+///
+/// # Returns
+///
+/// Returns the normalized URL string.
+///
+/// # Examples
+///
+/// ```
+/// let base = Url::parse("http://localhost:3000").unwrap();
+/// let url = normalize_url("HTTPS://Example.COM:443/Path", &base).unwrap();
+/// assert_eq!(url, "https://example.com/Path");
+/// ```
+///
+/// # Errors
+///
+/// Returns [`AppError::BadRequest`] if:
+/// - The URL is empty, relative or otherwise unparsable
+/// - The scheme is not `http` or `https` (e.g. `javascript:`, `ftp:`)
+/// - The URL has no host
+/// - The URL points back at the shortener itself
+/// - The normalized URL is longer than [`URL_MAX_LEN`]
+pub fn normalize_url(raw: &str, base_url: &Url) -> Result<String, AppError> {
+    let url = Url::parse(raw.trim())
+        .map_err(|err| AppError::BadRequest(format!("invalid url: {err}")))?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::BadRequest(format!(
+            "url scheme '{}' is not allowed, use http or https",
+            url.scheme()
+        )));
+    }
+
+    let Some(host) = url.host() else {
+        return Err(AppError::BadRequest("url must have a host".to_string()));
+    };
+
+    if points_at(&host, url.port_or_known_default(), base_url) {
+        return Err(AppError::BadRequest(
+            "url must not point at this shortener".to_string(),
+        ));
+    }
+
+    let normalized = String::from(url);
+    if normalized.len() > URL_MAX_LEN {
+        return Err(AppError::BadRequest(format!(
+            "url must be at most {URL_MAX_LEN} bytes long"
+        )));
+    }
+
+    Ok(normalized)
+}
+
+/// Returns `true` if `host:port` refers to the shortener at `base_url`.
+///
+/// Public hosts match regardless of port or scheme, since the same service is
+/// usually reachable over both http and https. Loopback addresses
+/// (`localhost`, `127.0.0.0/8`, `::1`) are treated as one host and must also
+/// match the port, so other local services can still be shortened during
+/// development.
+fn points_at(host: &Host<&str>, port: Option<u16>, base_url: &Url) -> bool {
+    let Some(base_host) = base_url.host() else {
+        return false;
+    };
+
+    if is_loopback(host) && is_loopback(&base_host) {
+        return port == base_url.port_or_known_default();
+    }
+
+    *host == base_host
+}
+
+/// Returns `true` for `localhost` and loopback IP addresses.
+fn is_loopback(host: &Host<&str>) -> bool {
+    match host {
+        Host::Domain(domain) => domain.eq_ignore_ascii_case("localhost"),
+        Host::Ipv4(ip) => IpAddr::V4(*ip).is_loopback(),
+        Host::Ipv6(ip) => IpAddr::V6(*ip).is_loopback(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_aliases() {
+        assert!(validate_alias("launch2026").is_ok());
+        assert!(validate_alias("spring_sale-24").is_ok());
+    }
+
+    #[test]
+    fn rejects_bad_length() {
+        assert!(validate_alias("ab").is_err());
+        assert!(validate_alias(&"a".repeat(ALIAS_MAX_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_characters() {
+        assert!(validate_alias("has space").is_err());
+        assert!(validate_alias("slash/code").is_err());
+        assert!(validate_alias("emoji🦀").is_err());
+    }
+
+    #[test]
+    fn rejects_reserved_words() {
+        assert!(validate_alias("shorten").is_err());
+        assert!(validate_alias("API").is_err());
+        assert!(validate_alias("Health").is_err());
+    }
+
+    #[test]
+    fn rejects_past_expiry_and_zero_clicks() {
+        let now = Utc::now();
+        assert!(validate_expiry(Some(now - chrono::Duration::seconds(1)), None, now).is_err());
+        assert!(validate_expiry(None, Some(0), now).is_err());
+        assert!(validate_expiry(Some(now + chrono::Duration::hours(1)), Some(5), now).is_ok());
+        assert!(validate_expiry(None, None, now).is_ok());
+    }
+
+    #[test]
+    fn checks_password_length() {
+        assert!(validate_password("hunter22").is_ok());
+        assert!(validate_password("short").is_err());
+        assert!(validate_password(&"p".repeat(PASSWORD_MAX_LEN + 1)).is_err());
+    }
+
+    fn base() -> Url {
+        Url::parse("http://localhost:3000").unwrap()
+    }
+
+    fn rule(language: Option<&str>, country: Option<&str>) -> LinkRuleRequest {
+        LinkRuleRequest {
+            platform: None,
+            language: language.map(str::to_string),
+            country: country.map(str::to_string),
+            target_url: "HTTPS://Example.com/de".to_string(),
+        }
+    }
+
+    #[test]
+    fn normalizes_link_rules() {
+        let rules = validate_link_rules(&[rule(Some("pt-BR"), Some("br"))], &base()).unwrap();
+        assert_eq!(rules[0].language.as_deref(), Some("pt-br"));
+        assert_eq!(rules[0].country.as_deref(), Some("BR"));
+        assert_eq!(rules[0].target_url, "https://example.com/de");
+
+        assert!(validate_link_rules(&[rule(None, None)], &base()).is_err());
+        assert!(validate_link_rules(&[rule(Some("de_DE"), None)], &base()).is_err());
+        assert!(validate_link_rules(&[rule(None, Some("DEU"))], &base()).is_err());
+        let too_many: Vec<_> = (0..=MAX_LINK_RULES)
+            .map(|_| rule(Some("de"), None))
+            .collect();
+        assert!(validate_link_rules(&too_many, &base()).is_err());
+    }
+
+    #[test]
+    fn checks_link_variants() {
+        let variant = |name: &str, weight: i64| LinkVariantRequest {
+            name: name.to_string(),
+            target_url: "https://Example.com/b".to_string(),
+            weight,
+        };
+
+        let variants = validate_link_variants(&[variant("a", 70), variant("b", 30)], &base());
+        assert_eq!(variants.unwrap()[1].target_url, "https://example.com/b");
+        assert!(validate_link_variants(&[variant("a", 1), variant("a", 1)], &base()).is_err());
+        assert!(validate_link_variants(&[variant("a b", 1)], &base()).is_err());
+        assert!(validate_link_variants(&[variant("a", 0)], &base()).is_err());
+        assert!(validate_link_variants(&[variant("a", MAX_VARIANT_WEIGHT + 1)], &base()).is_err());
+    }
+
+    #[test]
+    fn normalizes_host_case_and_default_ports() {
+        assert_eq!(
+            normalize_url("HTTPS://Example.COM:443/Some/Path?q=1", &base()).unwrap(),
+            "https://example.com/Some/Path?q=1"
+        );
+        assert_eq!(
+            normalize_url("  http://example.com:80  ", &base()).unwrap(),
+            "http://example.com/"
+        );
+        assert_eq!(
+            normalize_url("http://example.com:8080/", &base()).unwrap(),
+            "http://example.com:8080/"
+        );
+    }
+
+    #[test]
+    fn rejects_unsafe_or_relative_urls() {
+        for url in [
+            "",
+            "/relative/path",
+            "example.com",
+            "javascript:alert(1)",
+            "ftp://example.com/file",
+            "data:text/html,hi",
+        ] {
+            assert!(normalize_url(url, &base()).is_err(), "accepted {url:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_redirect_loops() {
+        assert!(normalize_url("http://localhost:3000/abc", &base()).is_err());
+        assert!(normalize_url("http://127.0.0.1:3000/abc", &base()).is_err());
+        assert!(normalize_url("http://LOCALHOST:3000", &base()).is_err());
+        assert!(normalize_url("http://localhost:4000/abc", &base()).is_ok());
+
+        let public = Url::parse("https://sho.rt").unwrap();
+        assert!(normalize_url("https://SHO.RT/launch", &public).is_err());
+        assert!(normalize_url("https://sho.rt:443/launch", &public).is_err());
+        assert!(normalize_url("http://sho.rt:8080/launch", &public).is_err());
+        assert!(normalize_url("https://example.com/sho.rt", &public).is_ok());
+    }
+}
@@ -0,0 +1,172 @@
+//! A/B split redirects.
+//!
+//! A link with [`LinkVariant`]s spreads its visitors across the variants'
+//! targets in proportion to their weights, e.g. 70/30. The first redirect
+//! picks a variant at random ([`pick_weighted`]) and sets a cookie naming it
+//! ([`sticky_cookie`]); later visits from the same browser go to the same
+//! variant for [`STICKY_COOKIE_MAX_AGE`] seconds, as long as the variant
+//! still exists.
+//!
+//! Each redirect records the variant's name with the click, so
+//! `GET /api/links/{code}/stats` reports clicks per variant.
+//!
+//! Redirect rules (see [`crate::rules`]) are evaluated first; variants only
+//! replace the link's `long_url`.
+
+use crate::models::LinkVariant;
+use axum::http::{HeaderMap, header};
+use rand::Rng;
+
+/// Prefix of the cookie that pins a visitor to a variant; the short code
+/// follows, so every link has its own assignment.
+pub const COOKIE_PREFIX: &str = "usv_";
+
+/// How long a visitor stays on their variant (30 days).
+pub const STICKY_COOKIE_MAX_AGE: i64 = 30 * 24 * 60 * 60;
+
+/// The variant a visitor is sent to.
+#[derive(Debug)]
+pub struct Assignment<'a> {
+    pub variant: &'a LinkVariant,
+    /// `true` if the variant was just picked and the cookie must be set.
+    pub is_new: bool,
+}
+
+/// Chooses the variant of a visitor: the one named by their cookie, or a
+/// new weighted random pick.
+///
+/// Returns `None` if `variants` is empty.
+pub fn assign<'a>(
+    variants: &'a [LinkVariant],
+    short_code: &str,
+    headers: &HeaderMap,
+    rng: &mut impl Rng,
+) -> Option<Assignment<'a>> {
+    if let Some(name) = cookie_value(headers, &cookie_name(short_code))
+        && let Some(variant) = variants.iter().find(|variant| variant.name == name)
+    {
+        return Some(Assignment {
+            variant,
+            is_new: false,
+        });
+    }
+
+    pick_weighted(variants, rng).map(|variant| Assignment {
+        variant,
+        is_new: true,
+    })
+}
+
+/// Picks a variant at random, each with a probability of its weight divided
+/// by the sum of all weights.
+///
+/// Returns `None` if `variants` is empty or no variant has a positive weight.
+pub fn pick_weighted<'a>(
+    variants: &'a [LinkVariant],
+    rng: &mut impl Rng,
+) -> Option<&'a LinkVariant> {
+    let total: i64 = variants.iter().map(|variant| variant.weight.max(0)).sum();
+    if total <= 0 {
+        return None;
+    }
+
+    let mut ticket = rng.gen_range(0..total);
+    variants.iter().find(|variant| {
+        let weight = variant.weight.max(0);
+        if ticket < weight {
+            return true;
+        }
+        ticket -= weight;
+        false
+    })
+}
+
+/// Returns the name of the cookie that pins visitors of `short_code`.
+pub fn cookie_name(short_code: &str) -> String {
+    format!("{COOKIE_PREFIX}{short_code}")
+}
+
+/// Builds the `Set-Cookie` value that pins a visitor to `variant`.
+///
+/// The cookie is sent for every path (a base URL may add a path prefix in
+/// front of the short code), is invisible to scripts and survives following
+/// the short link from other sites.
+pub fn sticky_cookie(short_code: &str, variant: &str) -> String {
+    format!(
+        "{}={variant}; Max-Age={STICKY_COOKIE_MAX_AGE}; Path=/; HttpOnly; SameSite=Lax",
+        cookie_name(short_code)
+    )
+}
+
+/// Looks up a cookie of the request by name.
+fn cookie_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(cookie_name, _)| *cookie_name == name)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    fn variants() -> Vec<LinkVariant> {
+        [("a", 70), ("b", 30)]
+            .into_iter()
+            .map(|(name, weight)| LinkVariant {
+                name: name.to_string(),
+                target_url: format!("https://example.com/{name}"),
+                weight,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_traffic_by_weight() {
+        let variants = variants();
+        let mut rng = StdRng::seed_from_u64(7);
+        let picks_of_a = (0..10_000)
+            .filter(|_| pick_weighted(&variants, &mut rng).unwrap().name == "a")
+            .count();
+        assert!((6_700..7_300).contains(&picks_of_a), "{picks_of_a}");
+
+        assert!(pick_weighted(&[], &mut rng).is_none());
+    }
+
+    #[test]
+    fn cookie_keeps_the_visitor_on_their_variant() {
+        let variants = variants();
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            "theme=dark; usv_spring=b; usv_other=a".parse().unwrap(),
+        );
+
+        for _ in 0..20 {
+            let assignment = assign(&variants, "spring", &headers, &mut rng).unwrap();
+            assert_eq!(assignment.variant.name, "b");
+            assert!(!assignment.is_new);
+        }
+
+        headers.insert(header::COOKIE, "usv_spring=removed".parse().unwrap());
+        assert!(
+            assign(&variants, "spring", &headers, &mut rng)
+                .unwrap()
+                .is_new
+        );
+    }
+
+    #[test]
+    fn sticky_cookie_names_the_variant() {
+        assert_eq!(
+            sticky_cookie("spring", "b"),
+            "usv_spring=b; Max-Age=2592000; Path=/; HttpOnly; SameSite=Lax"
+        );
+    }
+}
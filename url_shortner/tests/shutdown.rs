@@ -0,0 +1,279 @@
+//! End-to-end tests of graceful shutdown.
+//!
+//! Each test runs the server binary on a temporary SQLite database, sends it
+//! `SIGTERM` while a request is still in flight and checks what happens to
+//! the request, the queued clicks and the process.
+
+#![cfg(unix)]
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpStream},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::{Duration, Instant},
+};
+
+const BINARY: &str = env!("CARGO_BIN_EXE_url_shortner");
+
+/// Time the server gets to start or to exit.
+const PROCESS_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Temporary working directory holding the database, removed on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("url_shortner_{name}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs the binary in `dir` with a clean configuration.
+fn command(dir: &Path, shutdown_timeout_secs: u64) -> Command {
+    let mut command = Command::new(BINARY);
+    command
+        .current_dir(dir)
+        .env_remove("CONFIG_FILE")
+        .env("DATABASE_URL", "sqlite://urls.db?mode=rwc")
+        .env("BIND_ADDRESS", "127.0.0.1:0")
+        .env("IP_HASH_SALT", "test-salt")
+        .env("PROBE_INTERVAL_SECS", "0")
+        .env("SHUTDOWN_TIMEOUT_SECS", shutdown_timeout_secs.to_string())
+        .env("LOG_FORMAT", "text")
+        .env("RUST_LOG", "info");
+    command
+}
+
+/// Creates an API key with the `keys create` subcommand.
+fn create_api_key(dir: &Path) -> String {
+    let output = command(dir, 0)
+        .args(["keys", "create", "--name", "test"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .find(|line| line.starts_with("usk_"))
+        .expect("the key is printed")
+        .to_string()
+}
+
+/// A running server process.
+struct Server {
+    child: Child,
+    address: SocketAddr,
+    log: Arc<Mutex<String>>,
+}
+
+impl Server {
+    /// Starts the server and waits until it listens.
+    fn start(dir: &Path, shutdown_timeout_secs: u64) -> Self {
+        let mut child = command(dir, shutdown_timeout_secs)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let stderr = BufReader::new(child.stderr.take().unwrap());
+        let log = Arc::new(Mutex::new(String::new()));
+        let (listening, address) = mpsc::channel();
+        let lines = log.clone();
+        thread::spawn(move || {
+            for line in stderr.lines().map_while(Result::ok) {
+                if let Some((_, rest)) = line.split_once("Listening on ") {
+                    let address = rest.split_whitespace().next().unwrap_or_default();
+                    let _ = listening.send(address.parse::<SocketAddr>().unwrap());
+                }
+                let mut log = lines.lock().unwrap();
+                log.push_str(&line);
+                log.push('\n');
+            }
+        });
+
+        let address = address
+            .recv_timeout(PROCESS_TIMEOUT)
+            .unwrap_or_else(|_| panic!("server did not start:\n{}", log.lock().unwrap()));
+        Server {
+            child,
+            address,
+            log,
+        }
+    }
+
+    /// Sends `SIGTERM` to the server.
+    fn terminate(&self) {
+        let status = Command::new("kill")
+            .args(["-TERM", &self.child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    /// Waits for the server to exit and returns its status.
+    fn wait(&mut self) -> ExitStatus {
+        let deadline = Instant::now() + PROCESS_TIMEOUT;
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "server did not exit");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn log(&self) -> String {
+        // The reader thread may still be copying the last lines.
+        thread::sleep(Duration::from_millis(100));
+        self.log.lock().unwrap().clone()
+    }
+
+    /// Sends one request on a new connection and returns the raw response.
+    fn request(&self, method: &str, path: &str, api_key: &str, body: &str) -> String {
+        let mut stream = TcpStream::connect(self.address).unwrap();
+        stream
+            .write_all(
+                format!(
+                    "{method} {path} HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\
+                     authorization: Bearer {api_key}\r\ncontent-type: application/json\r\n\
+                     content-length: {}\r\n\r\n{body}",
+                    body.len()
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    /// Starts a bulk request and sends only the first half of its body.
+    fn start_bulk_request(&self, api_key: &str, body: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(self.address).unwrap();
+        stream.set_read_timeout(Some(PROCESS_TIMEOUT)).unwrap();
+        stream
+            .write_all(
+                format!(
+                    "POST /api/links/bulk HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\
+                     authorization: Bearer {api_key}\r\ncontent-type: application/json\r\n\
+                     content-length: {}\r\n\r\n{}",
+                    body.len(),
+                    &body[..body.len() / 2]
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        // Give the server time to route the request and start reading the body.
+        thread::sleep(Duration::from_millis(200));
+        stream
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Returns the JSON body of a raw HTTP response.
+fn json_body(response: &str) -> serde_json::Value {
+    let (_, body) = response
+        .split_once("\r\n\r\n")
+        .expect("a complete response");
+    serde_json::from_str(body).unwrap_or_else(|err| panic!("{err}: {response}"))
+}
+
+#[test]
+fn finishes_in_flight_requests_and_writes_queued_clicks() {
+    let dir = TempDir::new("shutdown_drain");
+    let api_key = create_api_key(&dir.0);
+    let mut server = Server::start(&dir.0, 30);
+
+    let response = server.request(
+        "POST",
+        "/shorten",
+        &api_key,
+        r#"{"url": "https://example.com/drain"}"#,
+    );
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    let short_code = json_body(&response)["short_code"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    for _ in 0..20 {
+        let response = server.request("GET", &format!("/{short_code}"), "", "");
+        assert!(response.starts_with("HTTP/1.1 302"), "{response}");
+    }
+
+    let body = r#"[{"url": "https://example.com/in-flight"}]"#;
+    let mut in_flight = server.start_bulk_request(&api_key, body);
+    server.terminate();
+    thread::sleep(Duration::from_millis(300));
+    in_flight
+        .write_all(&body.as_bytes()[body.len() / 2..])
+        .unwrap();
+    let mut response = String::new();
+    in_flight.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert_eq!(json_body(&response)["succeeded"], 1);
+
+    assert!(server.wait().success());
+    let log = server.log();
+    assert!(log.contains("All connections drained"), "{log}");
+    assert!(log.contains("Shutdown complete"), "{log}");
+    assert!(TcpStream::connect(server.address).is_err());
+
+    // The clicks were still queued when the signal arrived.
+    let mut server = Server::start(&dir.0, 30);
+    let response = server.request(
+        "GET",
+        &format!("/api/links/{short_code}/stats"),
+        &api_key,
+        "",
+    );
+    assert_eq!(json_body(&response)["total_clicks"], 20, "{response}");
+    let response = server.request("GET", "/api/links", &api_key, "");
+    assert!(
+        response.contains("https://example.com/in-flight"),
+        "{response}"
+    );
+
+    server.terminate();
+    assert!(server.wait().success());
+}
+
+#[test]
+fn cuts_off_requests_after_the_drain_timeout() {
+    let dir = TempDir::new("shutdown_timeout");
+    let api_key = create_api_key(&dir.0);
+    let mut server = Server::start(&dir.0, 1);
+
+    // The rest of the body is never sent.
+    let mut in_flight =
+        server.start_bulk_request(&api_key, r#"[{"url": "https://example.com/stuck"}]"#);
+    let signalled = Instant::now();
+    server.terminate();
+
+    assert!(server.wait().success());
+    assert!(signalled.elapsed() >= Duration::from_secs(1));
+    let mut response = String::new();
+    let _ = in_flight.read_to_string(&mut response);
+    assert!(response.is_empty(), "{response}");
+
+    let log = server.log();
+    assert!(log.contains("Drain timeout elapsed"), "{log}");
+    assert!(log.contains("Shutdown complete"), "{log}");
+}